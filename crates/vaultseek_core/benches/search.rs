@@ -0,0 +1,93 @@
+use std::hint::black_box;
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use vaultseek_core::{
+    file_tree::FileTree,
+    indexer::bigram_index::BigramIndex,
+    post_filter::post_filter,
+    sorter::{SortField, SortOrder, Sorter},
+};
+
+const NUM_FILES: usize = 200_000;
+const SEED: u64 = 42;
+
+fn bench_index(c: &mut Criterion) {
+    let tree = FileTree::generate_synthetic(NUM_FILES, SEED);
+    c.bench_function("bigram_index_new", |b| {
+        b.iter(|| BigramIndex::new(black_box(&tree)))
+    });
+}
+
+fn bench_query_word(c: &mut Criterion) {
+    let tree = FileTree::generate_synthetic(NUM_FILES, SEED);
+    let index = BigramIndex::new(&tree);
+    let mut group = c.benchmark_group("query_word");
+    // "report" is part of many generated names, "übersicht" only of a few
+    group.bench_function("common", |b| {
+        b.iter(|| index.query_word(black_box("report")))
+    });
+    group.bench_function("rare", |b| {
+        b.iter(|| index.query_word(black_box("übersicht")))
+    });
+    group.finish();
+}
+
+fn bench_query_char(c: &mut Criterion) {
+    let tree = FileTree::generate_synthetic(NUM_FILES, SEED);
+    let mut index = BigramIndex::without_char_index(&tree);
+    let mut group = c.benchmark_group("query_char");
+    // Without the char index every bigram containing the character is merged
+    group.bench_function("scan", |b| b.iter(|| index.query_char(black_box('e'))));
+    index.build_char_index(&tree);
+    group.bench_function("char_index", |b| {
+        b.iter(|| index.query_char(black_box('e')))
+    });
+    group.finish();
+}
+
+fn bench_post_filter(c: &mut Criterion) {
+    let tree = FileTree::generate_synthetic(NUM_FILES, SEED);
+    let index = BigramIndex::new(&tree);
+    let candidates = index.query_word("report");
+    c.bench_function("post_filter", |b| {
+        b.iter_batched(
+            || candidates.clone(),
+            |mut indices| post_filter(&tree, &mut indices, black_box("report_1")),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn bench_sort(c: &mut Criterion) {
+    let tree = FileTree::generate_synthetic(NUM_FILES, SEED);
+    let index = BigramIndex::new(&tree);
+    let results = index.query_word("übersicht");
+    let sorter = Sorter::new();
+    let mut group = c.benchmark_group("sort_small_results");
+    for (name, field) in [
+        ("filename", SortField::Filename),
+        ("size", SortField::Size),
+        ("date_modified", SortField::DateModified),
+    ] {
+        // Build the order list once, so only sorting the results is measured
+        sorter.sort_by(&tree, &mut results.clone(), field, SortOrder::Ascending);
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || results.clone(),
+                |mut indices| sorter.sort_by(&tree, &mut indices, field, SortOrder::Descending),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_index,
+    bench_query_word,
+    bench_query_char,
+    bench_post_filter,
+    bench_sort
+);
+criterion_main!(benches);
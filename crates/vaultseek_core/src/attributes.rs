@@ -0,0 +1,242 @@
+use std::fmt;
+use std::ops::{BitOr, BitOrAssign};
+
+use serde::{Deserialize, Serialize};
+
+// Windows file attributes, as stored in EFU files. Serialized as the plain number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Attributes(pub u32);
+
+impl Attributes {
+    pub const NONE: Attributes = Attributes(0);
+    pub const READONLY: Attributes = Attributes(0x1);
+    pub const HIDDEN: Attributes = Attributes(0x2);
+    pub const SYSTEM: Attributes = Attributes(0x4);
+    pub const DIRECTORY: Attributes = Attributes(0x10);
+    pub const ARCHIVE: Attributes = Attributes(0x20);
+    pub const NORMAL: Attributes = Attributes(0x80);
+    pub const TEMPORARY: Attributes = Attributes(0x100);
+    pub const SPARSE_FILE: Attributes = Attributes(0x200);
+    pub const REPARSE_POINT: Attributes = Attributes(0x400);
+    pub const COMPRESSED: Attributes = Attributes(0x800);
+    pub const OFFLINE: Attributes = Attributes(0x1000);
+    pub const NOT_CONTENT_INDEXED: Attributes = Attributes(0x2000);
+    pub const ENCRYPTED: Attributes = Attributes(0x4000);
+
+    // Names used by Display, in the order they are listed
+    const NAMES: [(Attributes, &'static str); 13] = [
+        (Attributes::READONLY, "ReadOnly"),
+        (Attributes::HIDDEN, "Hidden"),
+        (Attributes::SYSTEM, "System"),
+        (Attributes::DIRECTORY, "Directory"),
+        (Attributes::ARCHIVE, "Archive"),
+        (Attributes::NORMAL, "Normal"),
+        (Attributes::TEMPORARY, "Temporary"),
+        (Attributes::SPARSE_FILE, "SparseFile"),
+        (Attributes::REPARSE_POINT, "ReparsePoint"),
+        (Attributes::COMPRESSED, "Compressed"),
+        (Attributes::OFFLINE, "Offline"),
+        (Attributes::NOT_CONTENT_INDEXED, "NotContentIndexed"),
+        (Attributes::ENCRYPTED, "Encrypted"),
+    ];
+
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+    // Whether all flags of `other` are set
+    pub fn contains(self, other: Attributes) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_readonly(self) -> bool {
+        self.contains(Attributes::READONLY)
+    }
+    pub fn is_hidden(self) -> bool {
+        self.contains(Attributes::HIDDEN)
+    }
+    pub fn is_system(self) -> bool {
+        self.contains(Attributes::SYSTEM)
+    }
+    pub fn is_dir(self) -> bool {
+        self.contains(Attributes::DIRECTORY)
+    }
+    pub fn is_archive(self) -> bool {
+        self.contains(Attributes::ARCHIVE)
+    }
+    pub fn is_temporary(self) -> bool {
+        self.contains(Attributes::TEMPORARY)
+    }
+    pub fn is_reparse_point(self) -> bool {
+        self.contains(Attributes::REPARSE_POINT)
+    }
+    pub fn is_compressed(self) -> bool {
+        self.contains(Attributes::COMPRESSED)
+    }
+    pub fn is_encrypted(self) -> bool {
+        self.contains(Attributes::ENCRYPTED)
+    }
+
+    // Convert a unix st_mode to the closest windows attributes.
+    // Directories become Directory, symbolic links ReparsePoint and files without the
+    // owner write bit ReadOnly.
+    pub fn from_unix_mode(mode: u16) -> Attributes {
+        Attributes::from_unix_mode_with(mode, &UnixAttributeMapping::default())
+    }
+
+    // Like from_unix_mode, with the write bits that keep a file from being ReadOnly taken from
+    // the mapping
+    pub fn from_unix_mode_with(mode: u16, mapping: &UnixAttributeMapping) -> Attributes {
+        // From octal:
+        // 0140000   socket
+        // 0120000   symbolic link
+        // 0100000   regular file
+        // 0060000   block device
+        // 0040000   directory
+        // 0020000   character device
+        // 0010000   FIFO
+        let node_type = 0o170000 & mode;
+
+        let mut attributes = Attributes::NONE;
+        if node_type == 0o40000 {
+            attributes |= Attributes::DIRECTORY;
+        }
+        if node_type == 0o120000 {
+            attributes |= Attributes::REPARSE_POINT;
+        }
+        if mode & mapping.write_bits == 0 {
+            attributes |= Attributes::READONLY;
+        }
+        attributes
+    }
+}
+
+// How unix file systems are mapped to windows attributes when importing, e.g. from ncdu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnixAttributeMapping {
+    // Names starting with a dot are Hidden
+    pub dotfiles_hidden: bool,
+    // Files without any of these write bits are ReadOnly. By default only the owner counts,
+    // 0o222 only marks files nobody can write to.
+    pub write_bits: u16,
+}
+impl Default for UnixAttributeMapping {
+    fn default() -> Self {
+        UnixAttributeMapping {
+            dotfiles_hidden: true,
+            write_bits: 0o200,
+        }
+    }
+}
+
+impl From<u32> for Attributes {
+    fn from(bits: u32) -> Self {
+        Attributes(bits)
+    }
+}
+
+impl BitOr for Attributes {
+    type Output = Attributes;
+    fn bitor(self, rhs: Attributes) -> Attributes {
+        Attributes(self.0 | rhs.0)
+    }
+}
+impl BitOrAssign for Attributes {
+    fn bitor_assign(&mut self, rhs: Attributes) {
+        self.0 |= rhs.0;
+    }
+}
+
+// Lists the set flags, e.g. "Hidden, Directory". Unknown bits are ignored.
+impl fmt::Display for Attributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        for (flag, name) in Attributes::NAMES {
+            if self.contains(flag) {
+                if !first {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", name)?;
+                first = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Accessor = fn(Attributes) -> bool;
+
+    #[test]
+    fn test_accessors() {
+        let accessors: [(Attributes, Accessor); 9] = [
+            (Attributes::READONLY, Attributes::is_readonly),
+            (Attributes::HIDDEN, Attributes::is_hidden),
+            (Attributes::SYSTEM, Attributes::is_system),
+            (Attributes::DIRECTORY, Attributes::is_dir),
+            (Attributes::ARCHIVE, Attributes::is_archive),
+            (Attributes::TEMPORARY, Attributes::is_temporary),
+            (Attributes::REPARSE_POINT, Attributes::is_reparse_point),
+            (Attributes::COMPRESSED, Attributes::is_compressed),
+            (Attributes::ENCRYPTED, Attributes::is_encrypted),
+        ];
+        for (flag, accessor) in accessors {
+            assert!(accessor(flag));
+            assert!(accessor(flag | Attributes::NORMAL));
+            assert!(!accessor(Attributes::NONE));
+            // Only the matching accessor is true
+            for (other, other_accessor) in accessors {
+                assert_eq!(other_accessor(flag), other == flag);
+            }
+        }
+        assert_eq!(Attributes::from(0x10).bits(), 16);
+    }
+
+    #[test]
+    fn test_from_unix_mode() {
+        assert_eq!(Attributes::from_unix_mode(0o100644), Attributes::NONE);
+        assert_eq!(Attributes::from_unix_mode(0o100444), Attributes::READONLY);
+        assert_eq!(Attributes::from_unix_mode(0o40755), Attributes::DIRECTORY);
+        assert_eq!(
+            Attributes::from_unix_mode(0o40555),
+            Attributes::DIRECTORY | Attributes::READONLY
+        );
+        assert_eq!(
+            Attributes::from_unix_mode(0o120777),
+            Attributes::REPARSE_POINT
+        );
+        // Sockets, devices and FIFOs have no matching attribute
+        assert_eq!(Attributes::from_unix_mode(0o140755), Attributes::NONE);
+        assert_eq!(Attributes::from_unix_mode(0o20644), Attributes::NONE);
+
+        // Writable by the group only
+        let any_writer = UnixAttributeMapping {
+            write_bits: 0o222,
+            ..Default::default()
+        };
+        assert_eq!(Attributes::from_unix_mode(0o100464), Attributes::READONLY);
+        assert_eq!(
+            Attributes::from_unix_mode_with(0o100464, &any_writer),
+            Attributes::NONE
+        );
+        assert_eq!(
+            Attributes::from_unix_mode_with(0o100444, &any_writer),
+            Attributes::READONLY
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Attributes::NONE.to_string(), "");
+        assert_eq!(Attributes::DIRECTORY.to_string(), "Directory");
+        assert_eq!(
+            (Attributes::DIRECTORY | Attributes::HIDDEN | Attributes::READONLY).to_string(),
+            "ReadOnly, Hidden, Directory"
+        );
+        // Unknown bits are not listed
+        assert_eq!(Attributes(0x80000 | 0x20).to_string(), "Archive");
+    }
+}
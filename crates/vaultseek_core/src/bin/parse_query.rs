@@ -1,7 +1,12 @@
 fn main() {
     // read input from command line arguments
     let query: String = std::env::args().skip(1).collect::<Vec<_>>().join(" ");
-    let parsed_query = vaultseek_core::query::query_parser::parse_query(&query);
-    println!("{:#?}", parsed_query);
+    match vaultseek_core::query::query_parser::parse_query(&query) {
+        Ok(parsed_query) => {
+            println!("{:#?}", parsed_query);
+            println!("Rendered: {}", parsed_query);
+        }
+        Err(e) => eprintln!("Error parsing query: {}", e),
+    }
 
 }
@@ -1,17 +1,33 @@
 // test performance
+// Usage: perf_test [filelist.efu | --synthetic <num_files>] [query]
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
     let start_time = std::time::Instant::now();
     println!("Loading file tree...");
-    let tree =
-        vaultseek_core::loader::efu::import_efu("filelist.efu").expect("Failed to load file tree");
+    let (tree, query) = if args.first().map(String::as_str) == Some("--synthetic") {
+        let num_files = args
+            .get(1)
+            .and_then(|n| n.parse().ok())
+            .expect("Expected the number of files after --synthetic");
+        (
+            vaultseek_core::file_tree::FileTree::generate_synthetic(num_files, 42),
+            args.get(2),
+        )
+    } else {
+        let path = args.first().map(String::as_str).unwrap_or("filelist.efu");
+        (
+            vaultseek_core::loader::efu::import_efu(path).expect("Failed to load file tree"),
+            args.get(1),
+        )
+    };
     println!(
         "Loaded {} elements in {:?}",
         tree.len(),
         start_time.elapsed()
     );
     let searcher = vaultseek_core::searcher::Searcher::from_file_tree(tree);
-    let query = "Brand";
+    let query = query.map(String::as_str).unwrap_or("Brand");
     let sort_by = Some(vaultseek_core::sorter::SortField::Filename);
     let sort_order = Some(vaultseek_core::sorter::SortOrder::Ascending);
 
@@ -0,0 +1,165 @@
+// Replays a file of queries, e.g. from a user report, and reports the timing of each one
+// Usage: replay [filelist.efu | --synthetic <num_files>] <queries.txt>
+
+use std::time::{Duration, Instant};
+
+use vaultseek_core::file_tree::FileTree;
+use vaultseek_core::searcher::{SearchOptions, Searcher};
+
+struct QueryRun {
+    query: String,
+    duration: Duration,
+    // The number of results, or the parse error
+    results: Result<usize, String>,
+}
+
+struct Summary {
+    queries: usize,
+    errors: usize,
+    total: Duration,
+    p50: Duration,
+    p95: Duration,
+    p99: Duration,
+    slowest: String,
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} queries ({} failed) in {:?}",
+            self.queries, self.errors, self.total
+        )?;
+        writeln!(
+            f,
+            "p50 {:?}, p95 {:?}, p99 {:?}",
+            self.p50, self.p95, self.p99
+        )?;
+        write!(f, "Slowest: {}", self.slowest)
+    }
+}
+
+// Queries from the file, skipping empty lines and # comments
+fn read_queries(content: &str) -> Vec<&str> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+fn replay(searcher: &Searcher, queries: &[&str]) -> Vec<QueryRun> {
+    let options = SearchOptions::default();
+    queries
+        .iter()
+        .map(|&query| {
+            let start_time = Instant::now();
+            let results = searcher
+                .try_search_with_options(query, &options)
+                .map(|results| results.len())
+                .map_err(|e| e.to_string());
+            QueryRun {
+                query: query.to_string(),
+                duration: start_time.elapsed(),
+                results,
+            }
+        })
+        .collect()
+}
+
+// The duration that the given fraction of the runs doesn't exceed, by nearest rank
+fn percentile(sorted: &[Duration], fraction: f64) -> Duration {
+    let rank = (fraction * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn summarize(runs: &[QueryRun]) -> Option<Summary> {
+    let slowest = runs.iter().max_by_key(|run| run.duration)?;
+    let mut durations: Vec<Duration> = runs.iter().map(|run| run.duration).collect();
+    durations.sort_unstable();
+    Some(Summary {
+        queries: runs.len(),
+        errors: runs.iter().filter(|run| run.results.is_err()).count(),
+        total: durations.iter().sum(),
+        p50: percentile(&durations, 0.50),
+        p95: percentile(&durations, 0.95),
+        p99: percentile(&durations, 0.99),
+        slowest: slowest.query.clone(),
+    })
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let start_time = Instant::now();
+    let (tree, query_file) = if args.first().map(String::as_str) == Some("--synthetic") {
+        let num_files = args
+            .get(1)
+            .and_then(|n| n.parse().ok())
+            .expect("Expected the number of files after --synthetic");
+        (FileTree::generate_synthetic(num_files, 42), args.get(2))
+    } else {
+        let path = args.first().expect("Expected a file list and a query file");
+        (
+            vaultseek_core::loader::efu::import_efu(path).expect("Failed to load file tree"),
+            args.get(1),
+        )
+    };
+    let query_file = query_file.expect("Expected a query file");
+    let content = std::fs::read_to_string(query_file).expect("Failed to read the query file");
+    let queries = read_queries(&content);
+
+    let searcher = Searcher::from_file_tree(tree);
+    println!(
+        "Loaded {} elements in {:?}",
+        searcher.get_file_tree().len(),
+        start_time.elapsed()
+    );
+
+    let runs = replay(&searcher, &queries);
+    for run in &runs {
+        match &run.results {
+            Ok(count) => println!("{:>12?} {:>8} results  {}", run.duration, count, run.query),
+            Err(e) => println!(
+                "{:>12?} {:>8}          {} ({})",
+                run.duration, "error", run.query, e
+            ),
+        }
+    }
+    match summarize(&runs) {
+        Some(summary) => println!("\n{}", summary),
+        None => println!("No queries in {}", query_file),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_sample_queries() {
+        let queries = read_queries(include_str!("../../samples/queries.txt"));
+        assert_eq!(queries.len(), 10);
+        let searcher = Searcher::from_file_tree(FileTree::generate_synthetic(2000, 7));
+        let runs = replay(&searcher, &queries);
+        assert!(
+            runs.iter().all(|run| run.results.is_ok()),
+            "sample queries parse"
+        );
+
+        let summary = summarize(&runs).unwrap();
+        assert_eq!(summary.queries, 10);
+        assert_eq!(summary.errors, 0);
+        assert!(summary.p50 <= summary.p95 && summary.p95 <= summary.p99);
+        assert!(summary.p99 <= summary.total);
+        assert!(summary.to_string().starts_with("10 queries (0 failed)"));
+        assert!(summarize(&[]).is_none());
+    }
+
+    #[test]
+    fn test_percentile() {
+        let durations: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&durations, 0.50), Duration::from_millis(50));
+        assert_eq!(percentile(&durations, 0.99), Duration::from_millis(99));
+        assert_eq!(percentile(&durations[..1], 0.95), Duration::from_millis(1));
+    }
+}
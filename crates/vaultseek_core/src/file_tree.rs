@@ -1,12 +1,33 @@
-// Filename struct to represent a filename with start index and end in byte array
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::LazyLock;
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::attributes::Attributes;
+
+// Filename struct to represent a filename with start index and end in byte array.
+// The offsets always describe a valid UTF-8 range (start <= end) inside the strbuf of the
+// FileTree that created it, so a Filename is only meaningful together with that tree.
+// Only FileTree::new_filename can create one, which keeps the range valid. A Filename moved to
+// another tree reads as an empty name there, never as an invalid str.
+/// ```compile_fail
+/// let filename = vaultseek_core::file_tree::Filename::new(4, 2);
+/// ```
 pub struct Filename(usize, usize);
 impl Filename {
-    pub fn new(start: usize, end: usize) -> Self {
+    fn new(start: usize, end: usize) -> Self {
+        debug_assert!(start <= end, "Filename range {start}..{end} is reversed");
         Filename(start, end)
     }
     pub fn len(&self) -> usize {
         self.1 - self.0
     }
+    pub fn is_empty(&self) -> bool {
+        self.0 == self.1
+    }
 }
 
 pub struct Element {
@@ -14,28 +35,476 @@ pub struct Element {
     pub size: Option<i64>,
     pub date_modified: Option<i64>,
     pub date_created: Option<i64>,
-    pub attributes: u32,
+    pub attributes: Attributes,
     pub parent: usize,
     pub children: Vec<usize>,
 }
-impl Element {}
+impl Element {
+    pub fn is_dir(&self) -> bool {
+        self.attributes.is_dir()
+    }
+    pub fn element_type(&self) -> ElementType {
+        if self.is_dir() {
+            ElementType::Folder
+        } else {
+            ElementType::File
+        }
+    }
+}
+
+// Inode information, only available for imports from unix file systems like ncdu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkInfo {
+    pub device: u64,
+    pub inode: u64,
+    pub nlink: u32, // Number of hard links to the inode
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementType {
+    File,
+    Folder,
+}
+
+// Kind of file, derived from its extension, e.g. for file-type icons in the UI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileCategory {
+    Image,
+    Video,
+    Audio,
+    Document,
+    Archive,
+    Code,
+    Executable,
+    Other,
+}
+impl FileCategory {
+    // The category of a group name like image in ext:image, case-insensitive. Other isn't a group.
+    pub fn from_group_name(name: &str) -> Option<FileCategory> {
+        match name.to_ascii_lowercase().as_str() {
+            "image" => Some(FileCategory::Image),
+            "video" => Some(FileCategory::Video),
+            "audio" => Some(FileCategory::Audio),
+            "document" => Some(FileCategory::Document),
+            "archive" => Some(FileCategory::Archive),
+            "code" => Some(FileCategory::Code),
+            "executable" => Some(FileCategory::Executable),
+            _ => None,
+        }
+    }
+}
+
+const BUILTIN_CATEGORIES: &[(FileCategory, &[&str])] = &[
+    (
+        FileCategory::Image,
+        &[
+            "jpg", "jpeg", "png", "gif", "bmp", "tif", "tiff", "webp", "svg", "ico", "heic",
+            "heif", "raw", "cr2", "nef", "arw", "dng", "psd", "avif",
+        ],
+    ),
+    (
+        FileCategory::Video,
+        &[
+            "mp4", "mkv", "avi", "mov", "wmv", "flv", "webm", "m4v", "mpg", "mpeg", "3gp", "vob",
+        ],
+    ),
+    (
+        FileCategory::Audio,
+        &[
+            "mp3", "wav", "flac", "aac", "ogg", "oga", "opus", "m4a", "wma", "aiff", "mid", "midi",
+        ],
+    ),
+    (
+        FileCategory::Document,
+        &[
+            "pdf", "doc", "docx", "odt", "rtf", "txt", "md", "xls", "xlsx", "ods", "csv", "ppt",
+            "pptx", "odp", "epub", "tex",
+        ],
+    ),
+    (
+        FileCategory::Archive,
+        &[
+            "zip", "rar", "7z", "tar", "gz", "tgz", "bz2", "xz", "zst", "lz4", "iso", "cab",
+        ],
+    ),
+    (
+        FileCategory::Code,
+        &[
+            "rs", "c", "h", "cpp", "hpp", "cc", "cs", "java", "kt", "py", "js", "mjs", "ts", "tsx",
+            "jsx", "go", "rb", "php", "swift", "sh", "ps1", "bat", "lua", "html", "css", "json",
+            "xml", "yaml", "yml", "toml", "sql",
+        ],
+    ),
+    (
+        FileCategory::Executable,
+        &[
+            "exe", "msi", "dll", "so", "dylib", "app", "apk", "deb", "rpm", "appimage",
+        ],
+    ),
+];
+
+// Mapping of lowercase extensions to categories. The default is the built-in mapping, more
+// extensions can be added or remapped with `insert`.
+#[derive(Debug, Clone)]
+pub struct ExtensionCategories {
+    categories: HashMap<String, FileCategory>,
+}
+
+impl Default for ExtensionCategories {
+    fn default() -> Self {
+        let mut categories = ExtensionCategories::empty();
+        for (category, extensions) in BUILTIN_CATEGORIES {
+            for ext in *extensions {
+                categories.categories.insert(ext.to_string(), *category);
+            }
+        }
+        categories
+    }
+}
+
+impl ExtensionCategories {
+    // A mapping without any extensions, everything is Other
+    pub fn empty() -> Self {
+        ExtensionCategories {
+            categories: HashMap::new(),
+        }
+    }
+
+    // Maps an extension (without the dot) to a category, replacing its previous category
+    pub fn insert(&mut self, ext: &str, category: FileCategory) {
+        self.categories.insert(ext.to_lowercase(), category);
+    }
+
+    // Case-insensitive, unknown extensions are Other
+    pub fn classify(&self, ext: &str) -> FileCategory {
+        self.categories
+            .get(&ext.to_lowercase())
+            .copied()
+            .unwrap_or(FileCategory::Other)
+    }
+
+    // The extensions mapped to the category, in no particular order
+    pub fn extensions(&self, category: FileCategory) -> impl Iterator<Item = &str> {
+        self.categories
+            .iter()
+            .filter(move |(_, c)| **c == category)
+            .map(|(ext, _)| ext.as_str())
+    }
+}
+
+static DEFAULT_CATEGORIES: LazyLock<ExtensionCategories> =
+    LazyLock::new(ExtensionCategories::default);
+
+// Categorizes an extension (without the dot) with the built-in mapping
+pub fn classify_extension(ext: &str) -> FileCategory {
+    DEFAULT_CATEGORIES.classify(ext)
+}
+
+// MIME types of common extensions, for mime: and for clients that need a content type
+const BUILTIN_MIME_TYPES: &[(&str, &str)] = &[
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("bmp", "image/bmp"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    ("webp", "image/webp"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/vnd.microsoft.icon"),
+    ("heic", "image/heic"),
+    ("heif", "image/heif"),
+    ("avif", "image/avif"),
+    ("psd", "image/vnd.adobe.photoshop"),
+    ("mp4", "video/mp4"),
+    ("m4v", "video/x-m4v"),
+    ("mkv", "video/x-matroska"),
+    ("avi", "video/x-msvideo"),
+    ("mov", "video/quicktime"),
+    ("wmv", "video/x-ms-wmv"),
+    ("flv", "video/x-flv"),
+    ("webm", "video/webm"),
+    ("mpg", "video/mpeg"),
+    ("mpeg", "video/mpeg"),
+    ("3gp", "video/3gpp"),
+    ("mp3", "audio/mpeg"),
+    ("wav", "audio/wav"),
+    ("flac", "audio/flac"),
+    ("aac", "audio/aac"),
+    ("ogg", "audio/ogg"),
+    ("oga", "audio/ogg"),
+    ("opus", "audio/opus"),
+    ("m4a", "audio/mp4"),
+    ("wma", "audio/x-ms-wma"),
+    ("aiff", "audio/aiff"),
+    ("mid", "audio/midi"),
+    ("midi", "audio/midi"),
+    ("pdf", "application/pdf"),
+    ("doc", "application/msword"),
+    (
+        "docx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+    ("xls", "application/vnd.ms-excel"),
+    (
+        "xlsx",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    ),
+    ("ppt", "application/vnd.ms-powerpoint"),
+    (
+        "pptx",
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    ),
+    ("odt", "application/vnd.oasis.opendocument.text"),
+    ("ods", "application/vnd.oasis.opendocument.spreadsheet"),
+    ("odp", "application/vnd.oasis.opendocument.presentation"),
+    ("rtf", "application/rtf"),
+    ("epub", "application/epub+zip"),
+    ("txt", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("js", "text/javascript"),
+    ("mjs", "text/javascript"),
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("toml", "application/toml"),
+    ("wasm", "application/wasm"),
+    ("zip", "application/zip"),
+    ("rar", "application/vnd.rar"),
+    ("7z", "application/x-7z-compressed"),
+    ("tar", "application/x-tar"),
+    ("gz", "application/gzip"),
+    ("tgz", "application/gzip"),
+    ("bz2", "application/x-bzip2"),
+    ("xz", "application/x-xz"),
+    ("zst", "application/zstd"),
+    ("iso", "application/x-iso9660-image"),
+    ("exe", "application/vnd.microsoft.portable-executable"),
+    ("msi", "application/x-msi"),
+    ("apk", "application/vnd.android.package-archive"),
+    ("deb", "application/vnd.debian.binary-package"),
+    ("rpm", "application/x-rpm"),
+];
+
+// The MIME type of an extension (without the dot), case-insensitive. None if it isn't known.
+pub fn mime_type(ext: &str) -> Option<&'static str> {
+    BUILTIN_MIME_TYPES
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(ext))
+        .map(|&(_, mime)| mime)
+}
+
+// The MIME type of a filename by the longest of its extensions with a known type
+pub fn mime_type_of(filename: &str) -> Option<&'static str> {
+    extension_suffixes(filename).find_map(mime_type)
+}
+
+// Returns the extension of a filename, the part after the last dot. A leading dot is part of
+// the name, so dotfiles like .gitignore have no extension, and neither have names ending in a dot.
+pub fn extension(filename: &str) -> Option<&str> {
+    extension_suffixes(filename).last()
+}
+
+// All suffixes of a filename that count as its extension, longest first, so archive.tar.gz has
+// the extensions tar.gz and gz. Follows the same rules as `extension`.
+pub fn extension_suffixes(filename: &str) -> impl Iterator<Item = &str> {
+    let has_extension = !filename.ends_with('.');
+    filename
+        .char_indices()
+        .skip(1)
+        .filter(move |&(_, c)| has_extension && c == '.')
+        .map(move |(dot, _)| &filename[dot + 1..])
+}
+
+// Unicode normalization form applied to filenames, e.g. to match names exported on macOS (NFD) with NFC queries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalization {
+    Nfc,
+    Nfd,
+}
+impl Normalization {
+    pub fn apply<'a>(&self, string: &'a str) -> Cow<'a, str> {
+        // Skip the allocation if the string is already normalized (always the case for ASCII)
+        match self {
+            Normalization::Nfc => {
+                if unicode_normalization::is_nfc_quick(string.chars())
+                    == unicode_normalization::IsNormalized::Yes
+                {
+                    Cow::Borrowed(string)
+                } else {
+                    Cow::Owned(string.nfc().collect())
+                }
+            }
+            Normalization::Nfd => {
+                if unicode_normalization::is_nfd_quick(string.chars())
+                    == unicode_normalization::IsNormalized::Yes
+                {
+                    Cow::Borrowed(string)
+                } else {
+                    Cow::Owned(string.nfd().collect())
+                }
+            }
+        }
+    }
+}
+
+// Guards against pathological exports with extremely long names or deep paths. None is unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TreeLimits {
+    pub max_name_len: Option<usize>, // In bytes, longer names are truncated
+    pub max_depth: Option<usize>,    // Paths with more components are skipped
+}
 
 pub struct FileTree {
     pub elements: Vec<Element>,
-    strbuf: Vec<u8>, // Buffer for storing filenames as byte arrays
+    strbuf: String,                       // Buffer for storing all filenames
+    normalization: Option<Normalization>, // Normalization applied to added names, None keeps the bytes as is
+    limits: TreeLimits,
+    // Stored next to the elements, as most trees (EFU) have no inode information at all
+    link_info: HashMap<usize, LinkInfo>,
+    // Total size of the files inside each folder, see compute_rollup_sizes. Empty until computed.
+    rollup_sizes: Vec<i64>,
+    // Spans of the names already in strbuf by the hash of their bytes, see set_interning.
+    // None if interning is off.
+    interned: Option<HashMap<u64, (usize, usize)>>,
+    // Split paths on these too, besides \ and /, see set_extra_separators
+    extra_separators: Vec<String>,
+    // Whether a trailing separator marks a directory, see set_trailing_separator_dirs
+    trailing_separator_dirs: bool,
+}
+// Splits a path on \ and /, and on the extra separators. Empty names around an extra separator
+// are skipped, so archive.zip!/inner is the same as archive.zip!inner.
+fn split_path<'a>(path: &'a str, extra_separators: &'a [String]) -> impl Iterator<Item = &'a str> {
+    path.split(&['\\', '/']).flat_map(move |part| {
+        let mut rest = Some(part);
+        std::iter::from_fn(move || {
+            loop {
+                let current = rest?;
+                let next = extra_separators
+                    .iter()
+                    .filter_map(|separator| {
+                        Some((current.find(separator.as_str())?, separator.len()))
+                    })
+                    .min();
+                let Some((start, len)) = next else {
+                    rest = None;
+                    // Only the rest after an extra separator can be empty here
+                    return (current.len() == part.len() || !current.is_empty()).then_some(current);
+                };
+                rest = Some(&current[start + len..]);
+                if start > 0 {
+                    return Some(&current[..start]);
+                }
+            }
+        })
+    })
 }
+
 impl FileTree {
     pub fn with_capacity(capacity: usize) -> Self {
         // create a new FileTree with a specified initial capacity and a root element
         let mut tree = FileTree {
             elements: Vec::with_capacity(capacity),
-            strbuf: Vec::with_capacity(capacity * 10), // Initial capacity for the string buffer
+            strbuf: String::with_capacity(capacity * 10), // Initial capacity for the string buffer
+            normalization: None,
+            limits: TreeLimits::default(),
+            link_info: HashMap::new(),
+            rollup_sizes: Vec::new(),
+            interned: None,
+            extra_separators: Vec::new(),
+            trailing_separator_dirs: true,
         };
         // Add a root element
         tree.add_root();
         tree
     }
 
+    // Set the normalization applied to names added from now on
+    pub fn set_normalization(&mut self, normalization: Option<Normalization>) {
+        self.normalization = normalization;
+    }
+    pub fn normalization(&self) -> Option<Normalization> {
+        self.normalization
+    }
+    // Store names added from now on only once, so repeated names like index.html or node_modules
+    // share their bytes. Costs a hash lookup per added name, off by default.
+    pub fn set_interning(&mut self, enabled: bool) {
+        if !enabled {
+            self.interned = None;
+        } else if self.interned.is_none() {
+            self.interned = Some(HashMap::new());
+        }
+    }
+    // Bytes taken by all names, shared names count once
+    pub fn name_bytes(&self) -> usize {
+        self.strbuf.len()
+    }
+    // Set the limits applied to paths added from now on
+    pub fn set_limits(&mut self, limits: TreeLimits) {
+        self.limits = limits;
+    }
+    pub fn limits(&self) -> TreeLimits {
+        self.limits
+    }
+    // On by default: foo/bar/ sets the directory attribute of bar, and once bar is a directory
+    // adding foo/bar again keeps it one, so both spellings end up as the same folder. Off, the
+    // attributes of the last added path are kept as they are.
+    pub fn set_trailing_separator_dirs(&mut self, enabled: bool) {
+        self.trailing_separator_dirs = enabled;
+    }
+    // Additional separators for paths added from now on, e.g. `!` or `::` for exports that list
+    // archive contents as archive.zip!inner/file, so the contents become a subtree of the archive
+    pub fn set_extra_separators<S: AsRef<str>>(&mut self, separators: &[S]) {
+        self.extra_separators = separators
+            .iter()
+            .map(|separator| separator.as_ref().to_string())
+            .filter(|separator| !separator.is_empty())
+            .collect();
+    }
+    pub fn extra_separators(&self) -> &[String] {
+        &self.extra_separators
+    }
+    // Whether an element `depth` components below the root is deeper than max_depth allows
+    pub(crate) fn exceeds_max_depth(&self, depth: usize) -> bool {
+        self.limits
+            .max_depth
+            .is_some_and(|max_depth| depth > max_depth)
+    }
+
+    // Truncate a name to max_name_len bytes, at a character boundary
+    fn limit_name<'a>(&self, name: &'a str) -> &'a str {
+        match self.limits.max_name_len {
+            Some(max_name_len) if name.len() > max_name_len => {
+                let mut end = max_name_len;
+                while !name.is_char_boundary(end) {
+                    end -= 1;
+                }
+                tracing::warn!(
+                    "Truncating name of {} bytes to {} bytes: {}...",
+                    name.len(),
+                    end,
+                    &name[..end.min(40)]
+                );
+                &name[..end]
+            }
+            _ => name,
+        }
+    }
+
+    // Normalize a name or query the same way the names in this tree are normalized
+    pub fn normalize<'a>(&self, string: &'a str) -> Cow<'a, str> {
+        match self.normalization {
+            Some(normalization) => normalization.apply(string),
+            None => Cow::Borrowed(string),
+        }
+    }
+
     pub fn add_element(&mut self, element: Element) -> usize {
         let index = self.elements.len();
         self.elements.push(element);
@@ -47,11 +516,11 @@ impl FileTree {
         if self.elements.is_empty() {
             let filename = self.new_filename("Root");
             let root = Element {
-                filename: filename,
+                filename,
                 size: None,
                 date_modified: None,
                 date_created: None,
-                attributes: 0,
+                attributes: Attributes::NONE,
                 parent: 0, // Root has no parent
                 children: Vec::new(),
             };
@@ -61,20 +530,38 @@ impl FileTree {
         }
     }
 
+    // Add the element at the path, creating missing parent folders, or update it if it exists.
+    // Returns the index of the element, or None if the path was skipped for being deeper than
+    // max_depth.
     pub fn add_or_update_recursive(
         &mut self,
         path: &str,
         size: Option<i64>,
         date_modified: Option<i64>,
         date_created: Option<i64>,
-        attributes: u32,
-    ) -> usize {
+        attributes: Attributes,
+    ) -> Option<usize> {
         let mut current_index = 0; // Start from the root
+        let path = self.normalize(path);
+        let marked_dir = self.trailing_separator_dirs && path.ends_with(['\\', '/']);
         let path = path.trim_matches(&['\\', '/'][..]); // Trim leading/trailing slashes
+        // Taken out while the path is walked, since that adds to the tree, and put back after
+        let separators = std::mem::take(&mut self.extra_separators);
+
+        if !path.is_empty() && self.exceeds_max_depth(split_path(path, &separators).count()) {
+            tracing::warn!(
+                "Skipping path deeper than {} levels: {}",
+                self.limits.max_depth.unwrap_or_default(),
+                path
+            );
+            self.extra_separators = separators;
+            return None;
+        }
 
         if !path.is_empty() {
             // If empty path, we stay at root
-            for part in path.split(&['\\', '/']) {
+            for part in split_path(path, &separators) {
+                let part = self.limit_name(part);
                 // println!("Part: {}, current_index: {}", part, current_index);
                 // if part == "tank" { panic!("Debugging"); }
 
@@ -92,7 +579,7 @@ impl FileTree {
                             size: None,
                             date_modified: None,
                             date_created: None,
-                            attributes: 0,
+                            attributes: Attributes::NONE,
                             parent: current_index,
                             children: Vec::new(),
                         };
@@ -105,6 +592,7 @@ impl FileTree {
                 };
             }
         }
+        self.extra_separators = separators;
         // Update the final element with the provided metadata
         let element = self
             .elements
@@ -113,15 +601,59 @@ impl FileTree {
         element.size = size;
         element.date_modified = date_modified;
         element.date_created = date_created;
+        let was_dir = self.trailing_separator_dirs && element.is_dir();
         element.attributes = attributes;
+        if marked_dir || was_dir {
+            element.attributes |= Attributes::DIRECTORY;
+        }
 
-        current_index
+        Some(current_index)
+    }
+
+    pub fn find_by_path(&self, path: &str) -> Option<usize> {
+        // Resolve a path to an element index without modifying the tree. Uses the same
+        // splitting and normalization as add_or_update_recursive, so matching is case-sensitive.
+        if self.elements.is_empty() {
+            return None;
+        }
+        let mut current_index = 0; // Start from the root
+        let path = self.normalize(path);
+        let path = path.trim_matches(&['\\', '/'][..]); // Trim leading/trailing slashes
+
+        if !path.is_empty() {
+            for part in split_path(path, &self.extra_separators) {
+                let children = &self.elements[current_index].children;
+                current_index = match children
+                    .binary_search_by_key(&part, |&child_index| self.get_filename(child_index))
+                {
+                    Ok(index) => children[index],
+                    // Children added with add_child aren't sorted, fall back to a linear scan
+                    Err(_) => *children
+                        .iter()
+                        .find(|&&child_index| self.get_filename(child_index) == part)?,
+                };
+            }
+        }
+        Some(current_index)
     }
 
     pub fn new_filename(&mut self, string: &str) -> Filename {
         // Create a new Filename from a string, storing it in the strbuf
+        if let Some(interned) = &mut self.interned {
+            let mut hasher = DefaultHasher::new();
+            string.hash(&mut hasher);
+            let (start, end) = *interned.entry(hasher.finish()).or_insert_with(|| {
+                let start = self.strbuf.len();
+                self.strbuf.push_str(string);
+                (start, self.strbuf.len())
+            });
+            if &self.strbuf[start..end] == string {
+                return Filename::new(start, end);
+            }
+            // A different name with the same hash, store this one separately
+        }
         let start = self.strbuf.len();
-        self.strbuf.extend_from_slice(string.as_bytes());
+        self.strbuf.push_str(string);
         let end = self.strbuf.len();
         Filename::new(start, end)
     }
@@ -137,48 +669,74 @@ impl FileTree {
     }
 
     pub fn filename_as_str(&self, filename: &Filename) -> &str {
-        // Convert the byte slice to a str using the start and end indices
-        let filename_bytes = &self.strbuf[filename.0..filename.1];
-        // Convert bytes to str, assuming UTF-8 encoding
-        std::str::from_utf8(filename_bytes).unwrap_or("")
+        // Slice the buffer using the start and end indices. Checked, so a Filename from another
+        // tree that is out of bounds or splits a character gives an empty name.
+        self.strbuf.get(filename.0..filename.1).unwrap_or("")
     }
     pub fn get_filename(&self, index: usize) -> &str {
         // Get the filename of the element at the specified index
-        let filename = &self.elements[index].filename;
-        // Convert the byte slice to a str using the start and end indices
-        // SAFETY: We ensure that the indices are valid when creating Filename instances
-        let filename_bytes = unsafe { self.strbuf.get_unchecked(filename.0..filename.1) };
-        // Convert bytes to str, assuming UTF-8 encoding
-        //SAFETY: We ensure that the bytes are valid UTF-8 when adding filenames
-        unsafe { std::str::from_utf8_unchecked(filename_bytes) }
+        self.filename_as_str(&self.elements[index].filename)
     }
 
     pub fn get_full_path(&self, index: usize) -> String {
-        // Get the path of the element at the specified index. Not including the filename itself.
-        let mut path = String::new();
+        // Get the path of the element at the specified index, including its own name.
+        // Collect the ancestors first, so the string is allocated once with the final length.
+        let mut components = Vec::new();
+        let mut length = 0;
         let mut current_index = index;
         while current_index != 0 {
             let element = &self.elements[current_index];
-            if !path.is_empty() {
-                path = format!("{}\\{}", self.filename_as_str(&element.filename), path);
-            } else {
-                path = self.filename_as_str(&element.filename).to_string();
-            }
+            length += element.filename.len() + 1; // Name and separator
+            components.push(current_index);
             current_index = element.parent;
         }
+
+        let mut path = String::with_capacity(length.saturating_sub(1));
+        for (i, &component) in components.iter().rev().enumerate() {
+            if i > 0 {
+                path.push('\\');
+            }
+            path.push_str(self.filename_as_str(&self.elements[component].filename));
+        }
         path
     }
 
+    pub fn ancestors(&self, index: usize) -> Vec<(usize, &str)> {
+        // The folders containing the element and the element itself with their names, from the
+        // top level folder down, e.g. for breadcrumbs. The root is not included.
+        let mut ancestors = Vec::new();
+        let mut current_index = index;
+        while current_index != 0 {
+            ancestors.push((current_index, self.get_filename(current_index)));
+            current_index = self.elements[current_index].parent;
+        }
+        ancestors.reverse();
+        ancestors
+    }
+
     pub fn collect_all_children(&self, index: usize) -> Vec<usize> {
         // Collect all children of the specified element recursively
+        self.collect_all_children_to_depth(index, usize::MAX)
+    }
+
+    // Like collect_all_children, but only the descendants at most max_depth levels below the
+    // element, 1 being its direct children. Deeper folders aren't walked at all.
+    pub fn collect_all_children_to_depth(&self, index: usize, max_depth: usize) -> Vec<usize> {
         let mut children = Vec::new();
+        self.collect_children_into(index, max_depth, &mut children);
+        children
+    }
+
+    fn collect_children_into(&self, index: usize, max_depth: usize, children: &mut Vec<usize>) {
+        if max_depth == 0 {
+            return;
+        }
         if let Some(element) = self.get(index) {
             for &child_index in &element.children {
                 children.push(child_index);
-                children.extend(self.collect_all_children(child_index));
+                self.collect_children_into(child_index, max_depth - 1, children);
             }
         }
-        children
     }
 
     pub fn add_child(
@@ -188,11 +746,13 @@ impl FileTree {
         size: Option<i64>,
         date_modified: Option<i64>,
         date_created: Option<i64>,
-        attributes: u32,
+        attributes: Attributes,
     ) -> usize {
         // Add a child element to the specified parent element
         let child_index = self.elements.len();
         self.elements[parent].children.push(child_index);
+        let name = self.normalize(name);
+        let name = self.limit_name(&name);
         let child = Element {
             filename: self.new_filename(name),
             size,
@@ -205,6 +765,47 @@ impl FileTree {
         self.elements.push(child);
         child_index
     }
+    pub fn set_link_info(&mut self, index: usize, link_info: LinkInfo) {
+        self.link_info.insert(index, link_info);
+    }
+    pub fn link_info(&self, index: usize) -> Option<&LinkInfo> {
+        self.link_info.get(&index)
+    }
+
+    // Sets the directory attribute on all elements with children, for file lists where it's
+    // missing, e.g. for folders only created implicitly by the paths of their files. Files can't
+    // contain anything, so folders are recognized even if the export lists a size for them.
+    // Returns the number of elements that weren't marked as directories before.
+    pub fn infer_directories(&mut self) -> usize {
+        let mut inferred = 0;
+        for element in self.elements.iter_mut().skip(1) {
+            if !element.children.is_empty() && !element.is_dir() {
+                element.attributes |= Attributes::DIRECTORY;
+                inferred += 1;
+            }
+        }
+        inferred
+    }
+
+    // Sums up the file sizes below every folder, unknown sizes count as 0. Sizes stored for the
+    // folders themselves are ignored. Call again after changing the tree.
+    pub fn compute_rollup_sizes(&mut self) {
+        let mut rollup_sizes = vec![0; self.elements.len()];
+        // Parents are always added before their children, so children are summed up first
+        for (index, element) in self.elements.iter().enumerate().skip(1).rev() {
+            // Folders created implicitly for a path have no directory attribute, only children
+            if !element.is_dir() && element.children.is_empty() {
+                rollup_sizes[index] = element.size.unwrap_or(0);
+            }
+            rollup_sizes[element.parent] += rollup_sizes[index];
+        }
+        self.rollup_sizes = rollup_sizes;
+    }
+    // The computed total size of a folder, or the size of a file. None if not computed.
+    pub fn rollup_size(&self, index: usize) -> Option<i64> {
+        self.rollup_sizes.get(index).copied()
+    }
+
     pub fn shrink_to_fit(&mut self) {
         // Reduce the capacity of the elements vector to fit the current number of elements
         self.elements.shrink_to_fit();
@@ -213,4 +814,318 @@ impl FileTree {
         // Return the number of elements in the tree
         self.elements.len()
     }
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_by_path() {
+        let mut tree = FileTree::with_capacity(8);
+        let file = tree.add_test_file("C:\\Users\\Public\\notes.txt");
+        let folder = tree
+            .add_or_update_recursive("C:\\Users\\Public", None, None, None, Attributes::DIRECTORY)
+            .unwrap();
+        tree.add_or_update_recursive("C:\\Users\\Admin", None, None, None, Attributes::DIRECTORY);
+
+        assert_eq!(
+            tree.find_by_path("C:\\Users\\Public\\notes.txt"),
+            Some(file)
+        );
+        assert_eq!(tree.find_by_path("C:/Users/Public/notes.txt"), Some(file));
+        assert_eq!(tree.find_by_path("\\C:\\Users\\Public\\"), Some(folder));
+        assert_eq!(tree.find_by_path(""), Some(0));
+        assert_eq!(tree.find_by_path("C:\\Users\\Public\\missing.txt"), None);
+        assert_eq!(
+            tree.find_by_path("C:\\Users\\Public\\notes.txt\\child"),
+            None
+        );
+        // Lookups are case-sensitive, like inserting
+        assert_eq!(tree.find_by_path("c:\\users\\public\\notes.txt"), None);
+        // Looking a path up doesn't add anything
+        assert_eq!(tree.len(), 6);
+    }
+
+    #[test]
+    fn test_find_by_path_unsorted_children() {
+        // add_child (used by the ncdu loader) appends children without sorting them
+        let mut tree = FileTree::with_capacity(4);
+        tree.add_or_update_recursive("", None, None, None, Attributes::DIRECTORY);
+        let zeta = tree.add_child(0, "zeta", None, None, None, Attributes::DIRECTORY);
+        let alpha = tree.add_child(0, "alpha", None, None, None, Attributes::DIRECTORY);
+        let file = tree.add_child(alpha, "file.txt", Some(1), None, None, Attributes::NONE);
+
+        assert_eq!(tree.find_by_path("zeta"), Some(zeta));
+        assert_eq!(tree.find_by_path("alpha/file.txt"), Some(file));
+        assert_eq!(tree.find_by_path("beta"), None);
+    }
+
+    #[test]
+    fn test_filename_len() {
+        let mut tree = FileTree::with_capacity(4);
+        tree.set_interning(true);
+        let names = ["notes.txt", "ärger.md", "notes.txt", "日本語"];
+        for name in names {
+            tree.add_child(0, name, None, None, None, Attributes::NONE);
+        }
+        assert_eq!(tree.get(0).unwrap().filename.len(), "Root".len());
+        for (index, name) in names.iter().enumerate() {
+            let filename = &tree.get(index + 1).unwrap().filename;
+            assert_eq!(filename.len(), name.len());
+            assert!(!filename.is_empty());
+            // Every range handed out by the tree is a valid UTF-8 slice of its own buffer
+            assert_eq!(tree.filename_as_str(filename), *name);
+            assert_eq!(tree.get_filename(index + 1), *name);
+        }
+
+        // A Filename moved over from another tree can't produce an invalid str, neither past
+        // the end of the buffer nor in the middle of a character
+        let mut small = FileTree::with_capacity(2);
+        let target = small.add_child(0, "日本", None, None, None, Attributes::NONE);
+        let mut other = FileTree::with_capacity(4);
+        other.add_child(0, "x", None, None, None, Attributes::NONE);
+        // Bytes 5..7, inside the first character of 日本 in the small tree
+        let split = other.add_child(0, "yz", None, None, None, Attributes::NONE);
+        let long = other.add_child(0, "long name.txt", None, None, None, Attributes::NONE);
+        for index in [split, long] {
+            let empty = other.new_filename("");
+            let foreign = std::mem::replace(&mut other.elements[index].filename, empty);
+            small.elements[target].filename = foreign;
+            assert_eq!(small.get_filename(target), "");
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut tree = FileTree::with_capacity(8);
+        tree.set_limits(TreeLimits {
+            max_name_len: Some(8),
+            max_depth: Some(3),
+        });
+        // Truncated at a character boundary, "é" takes two bytes
+        let file = tree.add_test_file("C:\\abcdefgé.txt");
+        assert_eq!(tree.get_filename(file), "abcdefg");
+        let child = tree.add_child(0, "0123456789", None, None, None, Attributes::NONE);
+        assert_eq!(tree.get_filename(child), "01234567");
+
+        let len_before = tree.len();
+        let skipped =
+            tree.add_or_update_recursive("a\\b\\c\\d", Some(1), None, None, Attributes::NONE);
+        assert_eq!(skipped, None);
+        assert_eq!(tree.len(), len_before);
+        assert!(tree.find_by_path("a").is_none());
+        let deepest = tree.add_test_file("a\\b\\c");
+        assert_eq!(tree.get_full_path(deepest), "a\\b\\c");
+    }
+
+    #[test]
+    fn test_trailing_separator_dirs() {
+        for paths in [["foo/bar/", "foo/bar"], ["foo/bar", "foo\\bar\\"]] {
+            let mut tree = FileTree::with_capacity(4);
+            let first = tree
+                .add_or_update_recursive(paths[0], None, None, None, Attributes::NONE)
+                .unwrap();
+            let second = tree
+                .add_or_update_recursive(paths[1], Some(5), None, None, Attributes::HIDDEN)
+                .unwrap();
+            assert_eq!(first, second);
+            assert_eq!(tree.len(), 3);
+            assert!(tree.elements[second].is_dir());
+            assert!(
+                tree.elements[second]
+                    .attributes
+                    .contains(Attributes::HIDDEN)
+            );
+        }
+
+        let mut tree = FileTree::with_capacity(4);
+        tree.set_trailing_separator_dirs(false);
+        let bar = tree
+            .add_or_update_recursive("foo/bar/", None, None, None, Attributes::NONE)
+            .unwrap();
+        assert!(!tree.elements[bar].is_dir());
+    }
+
+    #[test]
+    fn test_extra_separators() {
+        let mut tree = FileTree::with_capacity(8);
+        let flat = tree.add_test_file("C:\\backup.zip!docs\\a.txt");
+        assert_eq!(tree.get_full_path(flat), "C:\\backup.zip!docs\\a.txt");
+
+        tree.set_extra_separators(&["!", "::"]);
+        let file = tree.add_test_file("C:\\data.zip!inner/nested.tar::deep\\file.txt");
+        let names: Vec<&str> = tree
+            .ancestors(file)
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect();
+        assert_eq!(
+            names,
+            vec!["C:", "data.zip", "inner", "nested.tar", "deep", "file.txt"]
+        );
+        // Both spellings of the archive root lead to the same subtree
+        let other = tree.add_test_file("C:\\data.zip!/inner\\b.txt");
+        assert_eq!(
+            tree.elements[other].parent,
+            tree.find_by_path("C:/data.zip/inner").unwrap()
+        );
+        assert_eq!(
+            tree.find_by_path("C:\\data.zip!inner::nested.tar"),
+            tree.find_by_path("C:/data.zip/inner/nested.tar")
+        );
+    }
+
+    #[test]
+    fn test_get_full_path_deep() {
+        let mut tree = FileTree::with_capacity(200);
+        let path = (0..150)
+            .map(|depth| format!("folder{}", depth))
+            .collect::<Vec<_>>()
+            .join("\\");
+        let file = tree.add_test_file(&path);
+        let full_path = tree.get_full_path(file);
+        assert_eq!(full_path, path);
+        // Allocated once with the exact length, instead of growing per component
+        assert_eq!(full_path.capacity(), full_path.len());
+        assert_eq!(tree.get_full_path(0), "");
+    }
+
+    #[test]
+    fn test_collect_all_children_to_depth() {
+        let mut tree = FileTree::with_capacity(8);
+        let deep = tree
+            .add_or_update_recursive("a/b/c/d.txt", None, None, None, Attributes::NONE)
+            .unwrap();
+        let top = tree
+            .add_or_update_recursive("a/e.txt", None, None, None, Attributes::NONE)
+            .unwrap();
+        let a = tree.find_by_path("a").unwrap();
+        let b = tree.find_by_path("a/b").unwrap();
+        let c = tree.find_by_path("a/b/c").unwrap();
+
+        assert!(tree.collect_all_children_to_depth(a, 0).is_empty());
+        assert_eq!(tree.collect_all_children_to_depth(a, 1), vec![b, top]);
+        assert_eq!(tree.collect_all_children_to_depth(a, 2), vec![b, c, top]);
+        assert_eq!(
+            tree.collect_all_children_to_depth(a, 3),
+            tree.collect_all_children(a)
+        );
+        assert!(tree.collect_all_children(a).contains(&deep));
+    }
+
+    #[test]
+    fn test_ancestors() {
+        let mut tree = FileTree::with_capacity(8);
+        let file = tree.add_test_file("C:\\docs\\a.txt");
+        let docs = tree.elements[file].parent;
+        let drive = tree.elements[docs].parent;
+        assert_eq!(
+            tree.ancestors(file),
+            vec![(drive, "C:"), (docs, "docs"), (file, "a.txt")]
+        );
+        assert_eq!(tree.ancestors(drive), vec![(drive, "C:")]);
+        assert!(tree.ancestors(0).is_empty());
+    }
+
+    #[test]
+    fn test_mime_type() {
+        assert_eq!(mime_type("png"), Some("image/png"));
+        assert_eq!(mime_type("JPG"), Some("image/jpeg"));
+        assert_eq!(mime_type("unknown"), None);
+        assert_eq!(mime_type_of("photo.PNG"), Some("image/png"));
+        assert_eq!(mime_type_of("backup.tar.gz"), Some("application/gzip"));
+        assert_eq!(mime_type_of("notes.txt.bak"), None);
+        assert_eq!(mime_type_of(".png"), None);
+    }
+
+    #[test]
+    fn test_classify_extension() {
+        assert_eq!(classify_extension("jpg"), FileCategory::Image);
+        assert_eq!(classify_extension("JPEG"), FileCategory::Image);
+        assert_eq!(classify_extension("mkv"), FileCategory::Video);
+        assert_eq!(classify_extension("flac"), FileCategory::Audio);
+        assert_eq!(classify_extension("pdf"), FileCategory::Document);
+        assert_eq!(classify_extension("7z"), FileCategory::Archive);
+        assert_eq!(classify_extension("rs"), FileCategory::Code);
+        assert_eq!(classify_extension("exe"), FileCategory::Executable);
+        assert_eq!(classify_extension("xyz123"), FileCategory::Other);
+        assert_eq!(classify_extension(""), FileCategory::Other);
+    }
+
+    #[test]
+    fn test_extension_categories_insert() {
+        let mut categories = ExtensionCategories::default();
+        categories.insert("Zig", FileCategory::Code);
+        categories.insert("txt", FileCategory::Code);
+        assert_eq!(categories.classify("zig"), FileCategory::Code);
+        assert_eq!(categories.classify("TXT"), FileCategory::Code);
+        assert!(
+            categories
+                .extensions(FileCategory::Code)
+                .any(|ext| ext == "zig")
+        );
+        assert_eq!(
+            ExtensionCategories::empty().classify("jpg"),
+            FileCategory::Other
+        );
+        // The built-in mapping is unchanged
+        assert_eq!(classify_extension("txt"), FileCategory::Document);
+    }
+
+    #[test]
+    fn test_interning() {
+        let build = |interning: bool| {
+            let mut tree = FileTree::with_capacity(1_000);
+            tree.set_interning(interning);
+            for project in 0..100 {
+                for name in [
+                    "index.html",
+                    "README.md",
+                    "node_modules/lodash/package.json",
+                ] {
+                    let path = format!("projects/project{}/{}", project, name);
+                    tree.add_test_file(&path);
+                }
+            }
+            tree
+        };
+        let plain = build(false);
+        let interned = build(true);
+        assert!(interned.name_bytes() * 5 < plain.name_bytes());
+
+        let first = interned
+            .find_by_path("projects/project1/index.html")
+            .unwrap();
+        let second = interned
+            .find_by_path("projects/project2/index.html")
+            .unwrap();
+        let (first, second) = (
+            &interned.elements[first].filename,
+            &interned.elements[second].filename,
+        );
+        assert_eq!((first.0, first.1), (second.0, second.1));
+        // The paths and names are the same as without interning
+        for index in 0..plain.len() {
+            assert_eq!(plain.get_full_path(index), interned.get_full_path(index));
+        }
+    }
+
+    #[test]
+    fn test_extension() {
+        assert_eq!(extension("photo.JPG"), Some("JPG"));
+        assert_eq!(extension("archive.tar.gz"), Some("gz"));
+        assert_eq!(
+            extension_suffixes("archive.tar.gz").collect::<Vec<_>>(),
+            vec!["tar.gz", "gz"]
+        );
+        assert_eq!(extension(".gitignore"), None);
+        assert_eq!(extension(".config.json"), Some("json"));
+        assert_eq!(extension("notes."), None);
+        assert_eq!(extension("notes.txt."), None);
+        assert_eq!(extension("Makefile"), None);
+    }
 }
@@ -0,0 +1,123 @@
+// Paths that are hidden from every search, e.g. `$Recycle.Bin` or `.git` folders.
+//
+// The list is applied when searching rather than when loading: the loaders stay untouched, the
+// same file list can be served with different ignore lists, and parent/child indices in the tree
+// stay valid. Matching is done once per element when the list is set, so a search only pays for
+// a lookup per result.
+
+use regex::{Regex, RegexBuilder};
+
+use crate::file_tree::FileTree;
+
+#[derive(Debug, Clone)]
+pub struct IgnoreList {
+    // Patterns containing a separator are matched against the full path, others against the name
+    path_patterns: Vec<Regex>,
+    name_patterns: Vec<Regex>,
+}
+
+impl IgnoreList {
+    // Glob syntax: `*` matches within one path component, `**` across components and `?` one
+    // character. Both `\` and `/` are accepted as separator. Matching is case-insensitive.
+    pub fn new<T: AsRef<str>>(patterns: &[T]) -> Result<Self, regex::Error> {
+        let mut path_patterns = Vec::new();
+        let mut name_patterns = Vec::new();
+        for pattern in patterns {
+            let pattern = pattern.as_ref().trim();
+            if pattern.is_empty() {
+                continue;
+            }
+            let regex = glob_to_regex(pattern)?;
+            if pattern.contains(['\\', '/']) {
+                path_patterns.push(regex);
+            } else {
+                name_patterns.push(regex);
+            }
+        }
+        Ok(IgnoreList {
+            path_patterns,
+            name_patterns,
+        })
+    }
+
+    // Parse a list separated by `;` or newlines, e.g. from an environment variable
+    pub fn parse(list: &str) -> Result<Self, regex::Error> {
+        let patterns: Vec<&str> = list.split([';', '\n']).collect();
+        IgnoreList::new(&patterns)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.path_patterns.is_empty() && self.name_patterns.is_empty()
+    }
+
+    pub fn is_match(&self, name: &str, path: &str) -> bool {
+        self.name_patterns.iter().any(|regex| regex.is_match(name))
+            || self.path_patterns.iter().any(|regex| regex.is_match(path))
+    }
+
+    // Returns for every element whether it is ignored. Everything inside an ignored folder is
+    // ignored as well.
+    pub fn ignored_elements(&self, tree: &FileTree) -> Vec<bool> {
+        let mut ignored = vec![false; tree.len()];
+        if self.is_empty() {
+            return ignored;
+        }
+        for (index, element) in tree.get_elements().iter().enumerate().skip(1) {
+            // Parents are always added before their children
+            debug_assert!(element.parent < index);
+            ignored[index] = ignored[element.parent]
+                || self.is_match(
+                    tree.filename_as_str(&element.filename),
+                    &tree.get_full_path(index),
+                );
+        }
+        ignored
+    }
+}
+
+fn glob_to_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str(r"[^\\/]*"),
+            '?' => regex.push_str(r"[^\\/]"),
+            '\\' | '/' => regex.push_str(r"[\\/]"),
+            _ => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    RegexBuilder::new(&regex).case_insensitive(true).build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_matching() {
+        let list = IgnoreList::new(&[
+            "$Recycle.Bin",
+            "*.tmp",
+            "C:\\Windows\\*",
+            "D:/**/node_modules",
+        ])
+        .unwrap();
+        assert!(list.is_match("$RECYCLE.BIN", "C:\\$RECYCLE.BIN"));
+        assert!(list.is_match("cache.TMP", "E:\\cache.TMP"));
+        assert!(!list.is_match("cache.tmp.txt", "E:\\cache.tmp.txt"));
+        assert!(list.is_match("System32", "C:\\Windows\\System32"));
+        // `*` stays within one component, the folder itself doesn't match
+        assert!(!list.is_match("Windows", "C:\\Windows"));
+        assert!(!list.is_match("x.dll", "C:\\Windows\\System32\\x.dll"));
+        assert!(list.is_match("node_modules", "D:\\Projects\\app\\node_modules"));
+        assert!(!list.is_match("node_modules", "E:\\node_modules"));
+
+        assert!(IgnoreList::parse(" ; ").unwrap().is_empty());
+        assert!(!IgnoreList::parse(".git;*.tmp").unwrap().is_empty());
+    }
+}
@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
 
 use crate::file_tree::FileTree;
 
@@ -22,7 +23,7 @@ impl CompressedPostingsList {
         let mut bytes: [u8; 10] = [0; 10]; // Buffer for variable byte encoding
         let mut bytes_index = 0; // Index for the bytes buffer
         for &i in &postings_list {
-            let gap = i as usize - last_i as usize; // Calculate the gap
+            let gap = i - last_i; // Calculate the gap
             last_i = i; // Update the last index
 
             // Encode the gap using variable byte encoding
@@ -66,19 +67,54 @@ impl CompressedPostingsList {
     }
 }
 
+// The maps are ordered, so iterating them (e.g. to save the index or to report memory per
+// bigram) gives the same order for the same tree on every build
 pub struct BigramIndex {
-    pub index: HashMap<Bigram, CompressedPostingsList>,
+    pub index: BTreeMap<Bigram, CompressedPostingsList>,
+    // Inverted index from single characters to elements, None if built without_char_index
+    pub char_index: Option<BTreeMap<char, CompressedPostingsList>>,
     num_elements: usize,
 }
 impl BigramIndex {
+    // Build the bigram and the single character index.
+    // One character queries then only decompress one postings list, instead of scanning the
+    // whole bigram map. Search runs on every keystroke, so the first keystroke has to be fast.
     pub fn new(tree: &FileTree) -> Self {
-        let index = create_bigram_reverse_index(tree);
+        let mut index = BigramIndex::without_char_index(tree);
+        index.build_char_index(tree);
+        index
+    }
+
+    // Like new, but gives up once the deadline has passed and returns None, so a huge file list
+    // can't block the caller indefinitely
+    pub fn with_deadline(tree: &FileTree, deadline: Instant) -> Option<Self> {
+        let index = create_bigram_reverse_index(tree, Some(deadline))?;
+        let char_index = create_char_reverse_index(tree, Some(deadline))?;
+        Some(BigramIndex {
+            index,
+            char_index: Some(char_index),
+            num_elements: tree.len(),
+        })
+    }
+
+    // Build only the bigram index, query_char then falls back to scanning all bigrams.
+    // Memory tradeoff: every element is stored once per distinct character in its name, so the
+    // char index takes roughly as much space as the bigram index.
+    pub fn without_char_index(tree: &FileTree) -> Self {
+        let index = create_bigram_reverse_index(tree, None).expect("No deadline to pass");
         BigramIndex {
             index,
+            char_index: None,
             num_elements: tree.len(),
         }
     }
 
+    // Build the single character index for an index created without_char_index
+    pub fn build_char_index(&mut self, tree: &FileTree) {
+        self.char_index = Some(create_char_reverse_index(tree, None).expect("No deadline to pass"));
+    }
+
+    // Returns the elements containing all bigrams of the word, in ascending order
     pub fn query_word<T: AsRef<str>>(&self, word: T) -> Vec<usize> {
         // Split the query into bigrams (bi-letters)
         let mut bigrams = Vec::new();
@@ -132,7 +168,14 @@ impl BigramIndex {
         indices.shrink_to_fit(); // Reduce capacity to the actual size
         indices
     }
+    // Returns the elements containing the character, in ascending order
     pub fn query_char(&self, c: char) -> Vec<usize> {
+        if let Some(char_index) = &self.char_index {
+            return match char_index.get(&c) {
+                Some(postings_list) => postings_list.decompress(),
+                None => Vec::new(),
+            };
+        }
         // go over the index and find all indices that contain the character
         let mut indices = vec![false; self.num_elements];
         for (bigram, postings_list) in &self.index {
@@ -159,14 +202,30 @@ impl BigramIndex {
         // Return size of the index
         self.index.len()
     }
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+}
+
+// Whether to give up building an index at the i-th element. The clock is only read every few
+// thousand elements, it's slow compared to indexing one name.
+fn deadline_passed(deadline: Option<Instant>, i: usize) -> bool {
+    i.is_multiple_of(4096) && deadline.is_some_and(|deadline| Instant::now() >= deadline)
 }
 
-fn create_bigram_reverse_index(tree: &FileTree) -> HashMap<Bigram, CompressedPostingsList> {
-    println!("Creating bigram reverse index...");
+// None if the deadline passed before the index was complete
+fn create_bigram_reverse_index(
+    tree: &FileTree,
+    deadline: Option<Instant>,
+) -> Option<BTreeMap<Bigram, CompressedPostingsList>> {
+    let _span = tracing::debug_span!("create_bigram_reverse_index").entered();
     let time_start = std::time::Instant::now();
     // Create a bigram reverse index for the elements
     let mut index: HashMap<Bigram, Vec<usize>> = HashMap::new();
     for (i, element) in tree.get_elements().iter().enumerate() {
+        if deadline_passed(deadline, i) {
+            return None;
+        }
         // take every two letters of the filename
         let filename = tree.filename_as_str(&element.filename).to_lowercase();
         // Split the query into bigrams (bi-letters)
@@ -190,26 +249,66 @@ fn create_bigram_reverse_index(tree: &FileTree) -> HashMap<Bigram, CompressedPos
         indices.shrink_to_fit(); // Reduce capacity to the actual number of indices
     }
 
-    let mut compressed_index: HashMap<Bigram, CompressedPostingsList> = HashMap::new();
+    let mut compressed_index: BTreeMap<Bigram, CompressedPostingsList> = BTreeMap::new();
     let mut total_size = 0;
     for (bigram, indices) in index {
         let comp_post = CompressedPostingsList::new(indices);
         total_size += comp_post.indices.len(); // Calculate the size of the compressed postings list
         compressed_index.insert(bigram, comp_post);
     }
-    println!(
-        "Created bigram reverse index with {} entries and total size of {} bytes in {:?}",
-        compressed_index.len(),
-        total_size,
-        time_start.elapsed()
+    tracing::debug!(
+        entries = compressed_index.len(),
+        bytes = total_size,
+        elapsed = ?time_start.elapsed(),
+        "Created bigram reverse index"
     );
 
-    compressed_index
+    Some(compressed_index)
+}
+
+// None if the deadline passed before the index was complete
+fn create_char_reverse_index(
+    tree: &FileTree,
+    deadline: Option<Instant>,
+) -> Option<BTreeMap<char, CompressedPostingsList>> {
+    let _span = tracing::debug_span!("create_char_reverse_index").entered();
+    let time_start = std::time::Instant::now();
+    let mut index: HashMap<char, Vec<usize>> = HashMap::new();
+    for (i, element) in tree.get_elements().iter().enumerate() {
+        if deadline_passed(deadline, i) {
+            return None;
+        }
+        let filename = tree.filename_as_str(&element.filename).to_lowercase();
+        for c in filename.chars() {
+            let indices = index.entry(c).or_default();
+            // Elements are visited in order, so a repeated character is always the last entry
+            if indices.last() != Some(&i) {
+                indices.push(i);
+            }
+        }
+    }
+
+    let mut compressed_index: BTreeMap<char, CompressedPostingsList> = BTreeMap::new();
+    let mut total_size = 0;
+    for (c, indices) in index {
+        let comp_post = CompressedPostingsList::new(indices);
+        total_size += comp_post.indices.len();
+        compressed_index.insert(c, comp_post);
+    }
+    tracing::debug!(
+        entries = compressed_index.len(),
+        bytes = total_size,
+        elapsed = ?time_start.elapsed(),
+        "Created char reverse index"
+    );
+
+    Some(compressed_index)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::attributes::Attributes;
 
     #[test]
     fn test_compressed_postings_list() {
@@ -232,4 +331,79 @@ mod tests {
             assert_eq!(postings_list, decompressed);
         }
     }
+
+    #[test]
+    fn test_char_index_matches_scan() {
+        let tree = FileTree::generate_synthetic(20_000, 3);
+        let mut index = BigramIndex::without_char_index(&tree);
+        let chars = ['r', 'e', '_', '日', 'é', 'ü', 'x', '本'];
+
+        let scanned: Vec<Vec<usize>> = chars.iter().map(|&c| index.query_char(c)).collect();
+        index.build_char_index(&tree);
+        let cached: Vec<Vec<usize>> = chars.iter().map(|&c| index.query_char(c)).collect();
+
+        assert_eq!(scanned, cached);
+        assert!(cached.iter().all(|indices| !indices.is_empty()));
+        assert!(cached.iter().all(|indices| indices.is_sorted()));
+    }
+
+    #[test]
+    fn test_char_index_single_char_names() {
+        // Single character names have no bigrams, only the char index finds them
+        let mut tree = FileTree::with_capacity(3);
+        let single = tree
+            .add_or_update_recursive("日", None, None, None, Attributes::NONE)
+            .unwrap();
+        let longer = tree
+            .add_or_update_recursive("日本", None, None, None, Attributes::NONE)
+            .unwrap();
+        let mut index = BigramIndex::without_char_index(&tree);
+        assert_eq!(index.query_char('日'), vec![longer]);
+
+        index.build_char_index(&tree);
+        assert_eq!(index.query_char('日'), vec![single, longer]);
+        assert!(index.query_char('x').is_empty());
+    }
+
+    #[test]
+    fn test_new_builds_char_index() {
+        let tree = FileTree::generate_synthetic(5_000, 11);
+        let mut index = BigramIndex::new(&tree);
+        assert!(index.char_index.is_some());
+        let chars = ['r', '日', 'ü', 'x'];
+        let expected: Vec<Vec<usize>> = chars
+            .iter()
+            .map(|&c| BigramIndex::without_char_index(&tree).query_char(c))
+            .collect();
+
+        // Without the bigram map a scan would find nothing, so the results come from the char index
+        index.index.clear();
+        let results: Vec<Vec<usize>> = chars.iter().map(|&c| index.query_char(c)).collect();
+        assert_eq!(results, expected);
+        assert!(results.iter().all(|indices| !indices.is_empty()));
+    }
+
+    #[test]
+    fn test_reproducible_order() {
+        let tree = FileTree::generate_synthetic(2_000, 3);
+        let entries = |index: &BigramIndex| {
+            let bigrams: Vec<(Bigram, Vec<u8>)> = index
+                .index
+                .iter()
+                .map(|(bigram, postings)| (bigram.clone(), postings.indices.clone()))
+                .collect();
+            let chars: Vec<(char, Vec<u8>)> = index
+                .char_index
+                .iter()
+                .flatten()
+                .map(|(&c, postings)| (c, postings.indices.clone()))
+                .collect();
+            (bigrams, chars)
+        };
+        // Two builds of the same tree iterate in the same order
+        assert_eq!(
+            entries(&BigramIndex::new(&tree)),
+            entries(&BigramIndex::new(&tree))
+        );
+    }
 }
@@ -1 +1,2 @@
 pub mod bigram_index;
+pub mod parent_names;
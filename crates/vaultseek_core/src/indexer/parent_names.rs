@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use crate::file_tree::FileTree;
+
+// Folders by their lowercased name. Queries on the folder an element is in, like parent:,
+// nosubfolders: and parentat:, look up the named folders and take their contents instead of walking up
+// from every element. Only built when such a query is run.
+pub struct ParentNameIndex {
+    folders: HashMap<String, Vec<usize>>,
+}
+
+impl ParentNameIndex {
+    pub fn new(tree: &FileTree) -> Self {
+        let mut folders: HashMap<String, Vec<usize>> = HashMap::new();
+        // Only elements with children can be a parent, the root has no name
+        for index in 1..tree.len() {
+            if !tree.elements[index].children.is_empty() {
+                folders
+                    .entry(tree.get_filename(index).to_lowercase())
+                    .or_default()
+                    .push(index);
+            }
+        }
+        ParentNameIndex { folders }
+    }
+
+    // The non-empty folders with this name, ignoring case, in ascending order
+    pub fn folders(&self, name: &str) -> &[usize] {
+        self.folders
+            .get(&name.to_lowercase())
+            .map_or(&[], |folders| folders.as_slice())
+    }
+
+    // All elements below the folders with this name, sorted. A superset of the parent: matches.
+    pub fn descendants(&self, tree: &FileTree, name: &str) -> Vec<usize> {
+        let mut descendants: Vec<usize> = self
+            .folders(name)
+            .iter()
+            .flat_map(|&folder| tree.collect_all_children(folder))
+            .collect();
+        // Folders of that name can be nested in each other
+        descendants.sort_unstable();
+        descendants.dedup();
+        descendants
+    }
+
+    // The elements exactly `level` folders below the folders with this name, sorted. A superset
+    // of the parentat: matches, and for level 1 of the nosubfolders: matches.
+    pub fn descendants_at(&self, tree: &FileTree, name: &str, level: usize) -> Vec<usize> {
+        let mut current = self.folders(name).to_vec();
+        for _ in 0..level {
+            current = current
+                .iter()
+                .flat_map(|&folder| tree.elements[folder].children.iter().copied())
+                .collect();
+        }
+        current.sort_unstable();
+        current.dedup();
+        current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attributes::Attributes;
+
+    #[test]
+    fn test_parent_name_index() {
+        let mut tree = FileTree::with_capacity(8);
+        tree.add_or_update_recursive("Docs\\a.txt", None, None, None, Attributes::NONE);
+        tree.add_or_update_recursive("x\\docs\\b\\c.txt", None, None, None, Attributes::NONE);
+        tree.add_or_update_recursive("x\\empty", None, None, None, Attributes::DIRECTORY);
+        let index = ParentNameIndex::new(&tree);
+
+        let path = |i: usize| tree.get_full_path(i);
+        assert_eq!(index.folders("DOCS").len(), 2);
+        let children: Vec<_> = index
+            .descendants_at(&tree, "docs", 1)
+            .into_iter()
+            .map(path)
+            .collect();
+        assert_eq!(children, vec!["Docs\\a.txt", "x\\docs\\b"]);
+        let nested: Vec<_> = index
+            .descendants_at(&tree, "docs", 2)
+            .into_iter()
+            .map(path)
+            .collect();
+        assert_eq!(nested, vec!["x\\docs\\b\\c.txt"]);
+        let all: Vec<_> = index
+            .descendants(&tree, "docs")
+            .into_iter()
+            .map(path)
+            .collect();
+        assert_eq!(all, vec!["Docs\\a.txt", "x\\docs\\b", "x\\docs\\b\\c.txt"]);
+        // Folders without children are never a parent
+        assert!(index.folders("empty").is_empty());
+        assert!(index.descendants_at(&tree, "missing", 1).is_empty());
+    }
+}
@@ -1,7 +1,11 @@
+pub mod attributes;
 pub mod file_tree;
+pub mod ignore_list;
 pub mod indexer;
 pub mod loader;
 pub mod post_filter;
+pub mod ranker;
 pub mod searcher;
 pub mod sorter;
 pub mod query;
+mod synthetic;
@@ -1,35 +1,61 @@
-use std::{error::Error, path::Path};
+use std::{
+    error::Error,
+    io::{Cursor, Read, Seek, SeekFrom},
+    path::Path,
+};
 
 use serde::{Deserialize, Serialize};
 
-use crate::file_tree::FileTree;
+use crate::{attributes::Attributes, file_tree::FileTree, loader::LoadOptions};
 
+// Columns are mapped by header name, so their order doesn't matter and unknown columns are
+// skipped. Everything except the filename is optional, as Everything can export without them.
 #[derive(Deserialize, Serialize)]
 struct Record {
     #[serde(rename = "Filename")]
     filename: String,
-    #[serde(rename = "Size")]
+    #[serde(rename = "Size", default)]
     size: Option<i64>,
-    #[serde(rename = "Date Modified")]
+    #[serde(rename = "Date Modified", default)]
     date_modified: Option<i64>,
-    #[serde(rename = "Date Created")]
+    #[serde(rename = "Date Created", default)]
     date_created: Option<i64>,
-    #[serde(rename = "Attributes")]
-    attributes: u32,
+    #[serde(rename = "Attributes", default)]
+    attributes: Attributes,
 }
 
 pub fn import_efu<P: AsRef<Path>>(filepath: P) -> Result<FileTree, Box<dyn Error>> {
-    let file_list_reader = std::fs::File::open(filepath)?;
+    import_efu_with_options(filepath, &LoadOptions::default())
+}
+
+pub fn import_efu_with_options<P: AsRef<Path>>(
+    filepath: P,
+    options: &LoadOptions,
+) -> Result<FileTree, Box<dyn Error>> {
+    let mut file = std::fs::File::open(filepath)?;
 
     // Estimate the number of records in the file
-    let file_size = file_list_reader.metadata()?.len();
+    let file_size = file.metadata()?.len();
     // Assuming an average record size of 100 bytes, adjust as necessary
     let estimated_records = (file_size / 100) as usize;
     // List of elements to build the tree structure
     let mut tree: FileTree = FileTree::with_capacity(estimated_records);
+    tree.set_normalization(options.normalization);
+    tree.set_limits(options.limits);
+    tree.set_interning(options.intern_names);
 
     // Create a CSV reader from the file
+    let file_list_reader: Box<dyn Read> = match detect_encoding(&mut file)? {
+        Encoding::Utf8 => Box::new(file),
+        encoding => {
+            // csv only reads UTF-8, so the whole file is transcoded in memory
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            Box::new(Cursor::new(decode_utf16(&bytes, encoding)?.into_bytes()))
+        }
+    };
     let mut rdr = csv::Reader::from_reader(file_list_reader);
+    check_headers(rdr.headers()?)?;
 
     // Iterate over the records and build the tree structure
     for record in rdr.deserialize() {
@@ -45,8 +71,318 @@ pub fn import_efu<P: AsRef<Path>>(filepath: P) -> Result<FileTree, Box<dyn Error
         // println!("Added file: {}", record.filename);
     }
 
+    if options.infer_directories {
+        let inferred = tree.infer_directories();
+        tracing::debug!("Inferred {} directories from their children", inferred);
+    }
+
     // Reduce capacity to the actual number of elements
     tree.shrink_to_fit();
     // Return the elements as a vector
     Ok(tree)
 }
+
+// Reads the records after byte_offset of an EFU file that is only appended to, and merges them
+// into the tree. Returns the offset to continue from on the next call, which is after the last
+// complete line, so a record that is still being written is read again once it's complete.
+// Offset 0 reads all records. The header is read on every call, as it maps the columns.
+pub fn import_efu_from<P: AsRef<Path>>(
+    tree: &mut FileTree,
+    filepath: P,
+    byte_offset: u64,
+) -> Result<u64, Box<dyn Error>> {
+    let mut file = std::fs::File::open(filepath)?;
+    let file_size = file.metadata()?.len();
+    if byte_offset > file_size {
+        return Err(format!(
+            "EFU file is {} bytes, shorter than the offset {}, it was probably replaced",
+            file_size, byte_offset
+        )
+        .into());
+    }
+
+    if let encoding @ (Encoding::Utf16Le | Encoding::Utf16Be) = detect_encoding(&mut file)? {
+        return Err(format!(
+            "EFU file is {}, appending is only supported for UTF-8, convert the file first",
+            encoding.name()
+        )
+        .into());
+    }
+    file.rewind()?; // Offsets are in bytes of the file, including the byte order mark
+    let mut rdr = csv::Reader::from_reader(&mut file);
+    let headers = rdr.headers()?.clone();
+    check_headers(&headers)?;
+    let start = byte_offset.max(rdr.position().byte());
+    drop(rdr);
+
+    file.seek(SeekFrom::Start(start))?;
+    let mut appended = Vec::new();
+    file.read_to_end(&mut appended)?;
+    let complete = appended
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .map_or(0, |newline| newline + 1);
+
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(&appended[..complete]);
+    let mut added = 0;
+    for record in rdr.records() {
+        let record: Record = record?.deserialize(Some(&headers))?;
+        tree.add_or_update_recursive(
+            &record.filename,
+            record.size,
+            record.date_modified,
+            record.date_created,
+            record.attributes,
+        );
+        added += 1;
+    }
+    tracing::debug!("Merged {} records appended to the EFU file", added);
+    Ok(start + complete as u64)
+}
+
+// Everything exports UTF-8, but files saved again by an editor on Windows are often UTF-16
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+        }
+    }
+}
+
+// Detects the encoding by the byte order mark and leaves the file right after it, so the first
+// column isn't read as \u{feff}Filename. Without a byte order mark the file is UTF-8.
+fn detect_encoding(file: &mut std::fs::File) -> Result<Encoding, Box<dyn Error>> {
+    let mut start = Vec::with_capacity(3);
+    file.by_ref().take(3).read_to_end(&mut start)?;
+    let (encoding, bom_len) = match start[..] {
+        [0xEF, 0xBB, 0xBF, ..] => (Encoding::Utf8, 3),
+        [0xFF, 0xFE, ..] => (Encoding::Utf16Le, 2),
+        [0xFE, 0xFF, ..] => (Encoding::Utf16Be, 2),
+        _ => (Encoding::Utf8, 0),
+    };
+    file.seek(SeekFrom::Start(bom_len))?;
+    Ok(encoding)
+}
+
+fn decode_utf16(bytes: &[u8], encoding: Encoding) -> Result<String, Box<dyn Error>> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err(format!(
+            "EFU file is {} but has an odd number of bytes",
+            encoding.name()
+        )
+        .into());
+    }
+    let units = bytes.chunks_exact(2).map(|pair| match encoding {
+        Encoding::Utf16Be => u16::from_be_bytes([pair[0], pair[1]]),
+        _ => u16::from_le_bytes([pair[0], pair[1]]),
+    });
+    char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .map_err(|e| format!("EFU file is {} but isn't valid: {}", encoding.name(), e).into())
+}
+
+// Without filenames there is nothing to import, fail early instead of at every record
+fn check_headers(headers: &csv::StringRecord) -> Result<(), Box<dyn Error>> {
+    if !headers.iter().any(|header| header == "Filename") {
+        return Err(format!(
+            "EFU file has no Filename column, found columns: {}",
+            headers.iter().collect::<Vec<_>>().join(", ")
+        )
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_tree::Normalization;
+
+    #[test]
+    fn test_import_efu_normalization() {
+        let path = std::env::temp_dir().join("vaultseek_test_normalization.efu");
+        std::fs::write(
+            &path,
+            "Filename,Size,Date Modified,Date Created,Attributes\n\"C:\\docs\\cafe\u{301}.txt\",100,,,32\n",
+        )
+        .unwrap();
+
+        let tree = import_efu(&path).unwrap();
+        assert_eq!(tree.get_filename(tree.len() - 1), "cafe\u{301}.txt");
+
+        let options = LoadOptions {
+            normalization: Some(Normalization::Nfc),
+            ..Default::default()
+        };
+        let tree = import_efu_with_options(&path, &options).unwrap();
+        assert_eq!(tree.get_filename(tree.len() - 1), "caf\u{e9}.txt");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn import_str(name: &str, contents: impl AsRef<[u8]>) -> Result<FileTree, Box<dyn Error>> {
+        import_str_with_options(name, contents, &LoadOptions::default())
+    }
+
+    fn import_str_with_options(
+        name: &str,
+        contents: impl AsRef<[u8]>,
+        options: &LoadOptions,
+    ) -> Result<FileTree, Box<dyn Error>> {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, contents).unwrap();
+        let tree = import_efu_with_options(&path, options);
+        std::fs::remove_file(&path).unwrap();
+        tree
+    }
+
+    #[test]
+    fn test_import_efu_encodings() {
+        let contents = "Filename,Size\n\"C:\\caf\u{e9}\\\u{1f600}.txt\",5\n";
+        let utf8_bom = [&[0xEF, 0xBB, 0xBF][..], contents.as_bytes()].concat();
+        let utf16le: Vec<u8> = [0xFF, 0xFE]
+            .into_iter()
+            .chain(contents.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        let utf16be: Vec<u8> = [0xFE, 0xFF]
+            .into_iter()
+            .chain(contents.encode_utf16().flat_map(u16::to_be_bytes))
+            .collect();
+        for (name, bytes) in [
+            ("vaultseek_test_utf8_bom.efu", utf8_bom),
+            ("vaultseek_test_utf16le.efu", utf16le),
+            ("vaultseek_test_utf16be.efu", utf16be),
+        ] {
+            let tree = import_str(name, bytes).unwrap();
+            let file = tree.find_by_path("C:\\caf\u{e9}\\\u{1f600}.txt").unwrap();
+            assert_eq!(tree.get(file).unwrap().size, Some(5), "{}", name);
+        }
+
+        // A broken UTF-16 file is an error naming the encoding, not garbled names
+        let error = import_str("vaultseek_test_utf16_odd.efu", [0xFF, 0xFE, b'F', 0, b'i'])
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("UTF-16LE"), "{}", error);
+    }
+
+    #[test]
+    fn test_import_efu_reordered_columns() {
+        let tree = import_str(
+            "vaultseek_test_reordered.efu",
+            "Attributes,Extra,Size,Filename,Date Created,Date Modified\n32,x,100,\"C:\\a.txt\",5,7\n",
+        )
+        .unwrap();
+        let file = tree.find_by_path("C:\\a.txt").unwrap();
+        let element = tree.get(file).unwrap();
+        assert_eq!(element.size, Some(100));
+        assert_eq!(element.date_modified, Some(7));
+        assert_eq!(element.date_created, Some(5));
+        assert_eq!(element.attributes, Attributes::ARCHIVE);
+    }
+
+    #[test]
+    fn test_import_efu_missing_columns() {
+        let tree = import_str(
+            "vaultseek_test_missing_columns.efu",
+            "Filename,Size\n\"C:\\a.txt\",100\n\"C:\\b.txt\",\n",
+        )
+        .unwrap();
+        let a = tree.get(tree.find_by_path("C:\\a.txt").unwrap()).unwrap();
+        assert_eq!(a.size, Some(100));
+        assert_eq!(a.date_modified, None);
+        assert_eq!(a.date_created, None);
+        assert_eq!(a.attributes, Attributes::NONE);
+        let b = tree.get(tree.find_by_path("C:\\b.txt").unwrap()).unwrap();
+        assert_eq!(b.size, None);
+
+        let error = import_str("vaultseek_test_no_filename.efu", "Name,Size\na.txt,1\n")
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("no Filename column"));
+    }
+
+    #[test]
+    fn test_import_efu_from_offset() {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join("vaultseek_test_append.efu");
+        std::fs::write(&path, "Filename,Size\n\"C:\\a.txt\",1\n\"C:\\b.txt\",2\n").unwrap();
+        let append = |contents: &str| {
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .unwrap();
+            file.write_all(contents.as_bytes()).unwrap();
+        };
+        let size = |tree: &FileTree, path: &str| tree.get(tree.find_by_path(path)?)?.size;
+
+        let mut tree = FileTree::with_capacity(8);
+        let offset = import_efu_from(&mut tree, &path, 0).unwrap();
+        assert_eq!(offset, std::fs::metadata(&path).unwrap().len());
+        assert_eq!(size(&tree, "C:\\b.txt"), Some(2));
+        // Nothing new
+        assert_eq!(import_efu_from(&mut tree, &path, offset).unwrap(), offset);
+
+        // The half written record isn't consumed until its line is complete
+        append("\"C:\\c.txt\",3\n\"C:\\a.txt\",10\n\"C:\\d.t");
+        let partial = import_efu_from(&mut tree, &path, offset).unwrap();
+        assert_eq!(size(&tree, "C:\\c.txt"), Some(3));
+        assert_eq!(size(&tree, "C:\\a.txt"), Some(10));
+        assert!(tree.find_by_path("C:\\d.t").is_none());
+        append("xt\",4\n");
+        let offset = import_efu_from(&mut tree, &path, partial).unwrap();
+        assert_eq!(size(&tree, "C:\\d.txt"), Some(4));
+        assert_eq!(offset, std::fs::metadata(&path).unwrap().len());
+
+        // The same records as a full import, just in a different order
+        let full = import_efu(&path).unwrap();
+        assert_eq!(full.len(), tree.len());
+
+        // A replaced, shorter file can't be continued
+        std::fs::write(&path, "Filename\n").unwrap();
+        assert!(import_efu_from(&mut tree, &path, offset).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_import_efu_infer_directories() {
+        // C:\photos is listed with a size but without the directory attribute, C:\docs and C:\
+        // aren't listed at all
+        let contents = "Filename,Size,Attributes\n\"C:\\photos\",500,0\n\"C:\\photos\\a.jpg\",500,32\n\"C:\\docs\\notes.txt\",10,0\n\"C:\\empty\",,0\n";
+        let folders = |tree: &FileTree| -> Vec<String> {
+            (1..tree.len())
+                .filter(|&index| tree.get(index).unwrap().is_dir())
+                .map(|index| tree.get_full_path(index))
+                .collect()
+        };
+
+        let tree = import_str("vaultseek_test_no_inference.efu", contents).unwrap();
+        assert!(folders(&tree).is_empty());
+
+        let options = LoadOptions {
+            infer_directories: true,
+            ..Default::default()
+        };
+        let tree =
+            import_str_with_options("vaultseek_test_infer_directories.efu", contents, &options)
+                .unwrap();
+        // Without children there's no telling whether C:\empty is a folder
+        assert_eq!(folders(&tree), vec!["C:", "C:\\photos", "C:\\docs"]);
+        let photos = tree.get(tree.find_by_path("C:\\photos").unwrap()).unwrap();
+        assert_eq!(photos.size, Some(500));
+        let notes = tree
+            .get(tree.find_by_path("C:\\docs\\notes.txt").unwrap())
+            .unwrap();
+        assert!(!notes.is_dir());
+    }
+}
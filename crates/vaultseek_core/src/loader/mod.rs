@@ -1,2 +1,37 @@
+#[cfg(feature = "efu")]
 pub mod efu;
+#[cfg(feature = "ncdu")]
 pub mod ncdu_json;
+
+use crate::attributes::UnixAttributeMapping;
+use crate::file_tree::{Normalization, TreeLimits};
+
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    // Unicode normalization applied to filenames. Off by default to preserve the exported bytes
+    pub normalization: Option<Normalization>,
+    // Maximum name length and path depth, unlimited by default
+    pub limits: TreeLimits,
+    // How permissions and dotfiles of unix imports (ncdu) become attributes
+    pub unix_attributes: UnixAttributeMapping,
+    // Store repeated names once, see FileTree::set_interning
+    pub intern_names: bool,
+    // Mark elements with children as directories if the export lacks the directory attribute,
+    // see FileTree::infer_directories. ncdu exports always mark their directories.
+    pub infer_directories: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    // Also runs in the lean build, see the features in Cargo.toml
+    #[cfg(feature = "efu")]
+    #[test]
+    fn test_efu_only_loader() {
+        let path = std::env::temp_dir().join("vaultseek_test_efu_only.efu");
+        std::fs::write(&path, "Filename,Size\nC:\\docs\\a.txt,5\n").unwrap();
+        let tree = super::efu::import_efu(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let file = tree.find_by_path("C:\\docs\\a.txt").unwrap();
+        assert_eq!(tree.get(file).unwrap().size, Some(5));
+    }
+}
@@ -1,17 +1,25 @@
-use std::{error::Error, io::BufReader, path::Path};
+use std::{error::Error, fmt, io::BufReader, path::Path};
 
-use serde::{Deserialize, Serialize, de};
-use serde_json::{Deserializer, Value};
+use serde::{
+    Deserialize, Deserializer, Serialize,
+    de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor},
+};
 
-use crate::file_tree::FileTree;
+use crate::{
+    attributes::{Attributes, UnixAttributeMapping},
+    file_tree::{FileTree, LinkInfo},
+    loader::LoadOptions,
+};
 
-type NcduTopLevel = (i32, i32, Value, NcduDirectory);
 // [
 //   <majorver>,
 //   <minorver>,
 //   <metadata>,
 //   <directory>
 // ]
+// A directory is an array of its info block followed by the entries, where files are info
+// blocks and subdirectories are arrays again. The export is read as a stream and each entry
+// is added to the tree right away, so the JSON is never held in memory as a whole.
 
 fn one() -> u32 {
     1
@@ -40,14 +48,6 @@ struct NcduInfoBlock {
     mtime: Option<u64>,
 }
 
-type NcduDirectory = Vec<NcduDirectoryEntry>;
-#[derive(Deserialize, Serialize, Debug)]
-#[serde(untagged)]
-enum NcduDirectoryEntry {
-    InfoBlock(NcduInfoBlock),
-    Directory(NcduDirectory),
-}
-
 fn get_date_modified_from_info(info: &NcduInfoBlock) -> Option<i64> {
     // convert to windows FILETIME (100-nanosecond intervals since January 1, 1601)
     if let Some(mtime) = info.mtime {
@@ -59,142 +59,425 @@ fn get_date_modified_from_info(info: &NcduInfoBlock) -> Option<i64> {
     }
 }
 
-fn get_attributes(info: &NcduInfoBlock, isdir: bool, filename: &str) -> u32 {
-    // From octal:
-    // 0140000   socket
-    // 0120000   symbolic link
-    // 0100000   regular file
-    // 0060000   block device
-    // 0040000   directory
-    // 0020000   character device
-    // 0010000   FIFO
-
-    let node_type = 0o170000 & info.mode.unwrap_or(0);
-
-    // To:
-    // 1: Read-only
-    // 2: Hidden
-    // 4: System
-    // 16: Directory
-    // 32: Archive
-    // 128: Normal
-    // 256: Temporary
-    // 512: Sparse file
-    // 1024: Reparse point
-    // 2048: Compressed
-    // 4096: Offline
-    // 8192: Not content indexed
-    // 16384: Encrypted
-
-    let mut attributes = 0u32;
-    if node_type == 0o40000 || isdir {
-        attributes |= 16; // FILE_ATTRIBUTE_DIRECTORY
-    }
-    if node_type == 0o120000 {
-        // symbolic link
-        attributes |= 0x400; // FILE_ATTRIBUTE_REPARSE_POINT
-    }
-    if let Some(mode) = info.mode {
-        if mode & 0o200 == 0 {
-            attributes |= 1; // FILE_ATTRIBUTE_READONLY
-        }
+fn set_link_info_from_info(tree: &mut FileTree, index: usize, info: &NcduInfoBlock) {
+    // ncdu only exports the inode with --extended or for hard links, 0 means unknown
+    if info.ino != 0 {
+        tree.set_link_info(
+            index,
+            LinkInfo {
+                device: info.dev,
+                inode: info.ino,
+                nlink: info.nlink,
+            },
+        );
     }
-    if filename.starts_with('.') {
-        attributes |= 2; // FILE_ATTRIBUTE_HIDDEN
+}
+
+fn get_attributes(
+    info: &NcduInfoBlock,
+    isdir: bool,
+    filename: &str,
+    mapping: &UnixAttributeMapping,
+) -> Attributes {
+    let mut attributes = info
+        .mode
+        .map(|mode| Attributes::from_unix_mode_with(mode, mapping))
+        .unwrap_or_default();
+    if isdir {
+        attributes |= Attributes::DIRECTORY;
+    }
+    if mapping.dotfiles_hidden && filename.starts_with('.') {
+        attributes |= Attributes::HIDDEN;
     }
     attributes
 }
 
 pub fn import_ncdu_json<P: AsRef<Path>>(filepath: P) -> Result<FileTree, Box<dyn Error>> {
+    import_ncdu_json_with_options(filepath, &LoadOptions::default())
+}
+
+pub fn import_ncdu_json_with_options<P: AsRef<Path>>(
+    filepath: P,
+    options: &LoadOptions,
+) -> Result<FileTree, Box<dyn Error>> {
     let file_list_reader = std::fs::File::open(filepath)?;
     // Estimate the number of records in the file
     let file_size = file_list_reader.metadata()?.len();
-    
     let file_list_buf_reader = BufReader::new(file_list_reader);
-    let data: NcduTopLevel = serde_json::from_reader(file_list_buf_reader)?;
 
     // Assuming an average record size of 100 bytes, adjust as necessary
     let estimated_records = (file_size / 100) as usize;
     // List of elements to build the tree structure
     let mut tree: FileTree = FileTree::with_capacity(estimated_records);
+    tree.set_normalization(options.normalization);
+    tree.set_limits(options.limits);
+    tree.set_interning(options.intern_names);
+
+    let mut deserializer = serde_json::Deserializer::from_reader(file_list_buf_reader);
+    TopLevelSeed {
+        tree: &mut tree,
+        mapping: &options.unix_attributes,
+    }
+    .deserialize(&mut deserializer)?;
+    deserializer.end()?;
+
+    // Reduce capacity to the actual number of elements
+    tree.shrink_to_fit();
+    // Return the elements as a vector
+    Ok(tree)
+}
+
+// Reads the top-level array and builds the tree from the directory in it
+struct TopLevelSeed<'a> {
+    tree: &'a mut FileTree,
+    mapping: &'a UnixAttributeMapping,
+}
+
+impl<'de> DeserializeSeed<'de> for TopLevelSeed<'_> {
+    type Value = ();
 
-    fn add_recursively(
-        tree: &mut FileTree,
-        dir: &NcduDirectory,
-        parent_index: usize,
-    ) -> Result<(), Box<dyn Error>> {
-        if let Some(NcduDirectoryEntry::InfoBlock(info)) = dir.get(0) {
-            // Process the info block if needed
-            let current_parent = tree.add_child(
-                parent_index,
-                &info.name,
-                info.asize,
-                get_date_modified_from_info(info),
-                None,
-                get_attributes(info, true, &info.name),
-            );
-
-            // Process the rest of the directory entries
-            for entry in dir.iter().skip(1) {
-                match entry {
-                    NcduDirectoryEntry::InfoBlock(info) => {
-                        // It's a file entry
-                        tree.add_child(
-                            current_parent,
-                            &info.name,
-                            info.asize,
-                            get_date_modified_from_info(info),
-                            None,
-                            get_attributes(info, false, &info.name),
-                        );
-                    }
-                    NcduDirectoryEntry::Directory(sub_dir) => {
-                        // It's a sub-directory, recurse into it
-                        add_recursively(tree, sub_dir, current_parent)?;
-                    }
-                }
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for TopLevelSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an ncdu export array")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        seq.next_element::<i32>()?; // major version
+        seq.next_element::<i32>()?; // minor version
+        seq.next_element::<IgnoredAny>()?; // metadata
+        let directory = DirectorySeed {
+            tree: self.tree,
+            mapping: self.mapping,
+            parent: None,
+            depth: 0,
+        };
+        seq.next_element_seed(directory)?
+            .ok_or_else(|| de::Error::custom("Invalid NCDU top-level structure: missing directory"))
+    }
+}
+
+// Reads a directory array and adds the directory and everything in it to the tree. The root
+// directory has no parent and is added by its path, e.g. /home. Directories deeper than the
+// max_depth limit are read but skipped with everything in them.
+struct DirectorySeed<'a> {
+    tree: &'a mut FileTree,
+    mapping: &'a UnixAttributeMapping,
+    parent: Option<usize>,
+    depth: usize, // Of the directory below the tree root, only known for subdirectories
+}
+
+impl<'de> DeserializeSeed<'de> for DirectorySeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for DirectorySeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an ncdu directory array")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let info: NcduInfoBlock = seq.next_element()?.ok_or_else(|| {
+            de::Error::custom("Invalid NCDU directory structure: missing InfoBlock")
+        })?;
+        let attributes = get_attributes(&info, true, &info.name, self.mapping);
+        let date_modified = get_date_modified_from_info(&info);
+        let (current_parent, depth) = match self.parent {
+            Some(_) if self.tree.exceeds_max_depth(self.depth) => (None, self.depth),
+            Some(parent) => (
+                Some(self.tree.add_child(
+                    parent,
+                    &info.name,
+                    info.asize,
+                    date_modified,
+                    None,
+                    attributes,
+                )),
+                self.depth,
+            ),
+            None => {
+                let root = self.tree.add_or_update_recursive(
+                    &info.name,
+                    info.asize,
+                    date_modified,
+                    None,
+                    attributes,
+                );
+                let depth = root.map_or(0, |root| self.tree.ancestors(root).len());
+                (root, depth)
             }
-        } else {
-            return Err("Invalid NCDU directory structure: missing InfoBlock".into());
-        }
+        };
+        let Some(current_parent) = current_parent else {
+            tracing::warn!("Skipping folder deeper than the depth limit: {}", info.name);
+            while seq.next_element::<IgnoredAny>()?.is_some() {}
+            return Ok(());
+        };
+        set_link_info_from_info(self.tree, current_parent, &info);
 
+        // Process the rest of the directory entries
+        while seq
+            .next_element_seed(EntrySeed {
+                tree: &mut *self.tree,
+                mapping: self.mapping,
+                parent: current_parent,
+                depth: depth + 1,
+            })?
+            .is_some()
+        {}
         Ok(())
     }
+}
 
-    // Iterate over the records and build the tree structure
-    if let NcduDirectoryEntry::InfoBlock(info) = &data.3[0] {
-        let root_index = tree.add_or_update_recursive(
+// Reads one entry of a directory, an info block for a file or an array for a subdirectory
+struct EntrySeed<'a> {
+    tree: &'a mut FileTree,
+    mapping: &'a UnixAttributeMapping,
+    parent: usize,
+    depth: usize, // Of the entry, one below its parent
+}
+
+impl<'de> DeserializeSeed<'de> for EntrySeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for EntrySeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an ncdu info block or directory array")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<(), A::Error> {
+        // It's a file entry
+        let info = NcduInfoBlock::deserialize(de::value::MapAccessDeserializer::new(map))?;
+        if self.tree.exceeds_max_depth(self.depth) {
+            tracing::warn!("Skipping file deeper than the depth limit: {}", info.name);
+            return Ok(());
+        }
+        let index = self.tree.add_child(
+            self.parent,
             &info.name,
             info.asize,
-            get_date_modified_from_info(info),
+            get_date_modified_from_info(&info),
             None,
-            get_attributes(info, true, &info.name),
+            get_attributes(&info, false, &info.name, self.mapping),
+        );
+        set_link_info_from_info(self.tree, index, &info);
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<(), A::Error> {
+        // It's a sub-directory, recurse into it
+        DirectorySeed {
+            tree: self.tree,
+            mapping: self.mapping,
+            parent: Some(self.parent),
+            depth: self.depth,
+        }
+        .visit_seq(seq)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{file_tree::TreeLimits, searcher::Searcher};
+
+    #[test]
+    fn test_import_ncdu_hardlinks() {
+        let path = std::env::temp_dir().join("vaultseek_test_hardlinks.json");
+        std::fs::write(
+            &path,
+            r#"[1,2,{"progname":"ncdu"},
+[{"name":"/data","dev":5,"ino":2},
+ {"name":"a.txt","asize":10,"dev":5,"ino":100,"hlnkc":true,"nlink":2},
+ [{"name":"sub","dev":5,"ino":3},
+  {"name":"b.txt","asize":10,"dev":5,"ino":100,"hlnkc":true,"nlink":2},
+  {"name":"c.txt","asize":10,"dev":5,"ino":101},
+  {"name":"d.txt","asize":10,"dev":6,"ino":100}]
+]]"#,
+        )
+        .unwrap();
+        let tree = import_ncdu_json(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let a = tree.find_by_path("/data/a.txt").unwrap();
+        let b = tree.find_by_path("/data/sub/b.txt").unwrap();
+        let c = tree.find_by_path("/data/sub/c.txt").unwrap();
+        let d = tree.find_by_path("/data/sub/d.txt").unwrap();
+        assert_eq!(
+            tree.link_info(a),
+            Some(&LinkInfo {
+                device: 5,
+                inode: 100,
+                nlink: 2
+            })
         );
-        for entry in data.3.iter().skip(1) {
-            match entry {
-                NcduDirectoryEntry::InfoBlock(info) => {
-                    // It's a file entry
-                    tree.add_child(
-                        root_index,
-                        &info.name,
-                        info.asize,
-                        get_date_modified_from_info(info),
-                        None,
-                        get_attributes(info, false, &info.name),
-                    );
-                }
-                NcduDirectoryEntry::Directory(sub_dir) => {
-                    // It's a sub-directory, recurse into it
-                    add_recursively(&mut tree, sub_dir, root_index)?;
-                }
+        assert_eq!(tree.link_info(c).unwrap().nlink, 1);
+
+        let searcher = Searcher::from_file_tree(tree);
+        assert_eq!(searcher.search("hardlinks:", None, None), vec![a, b]);
+        assert_eq!(searcher.search("hardlinks:1", None, None).len(), 4);
+        // The same inode number on another device is another file
+        assert_eq!(searcher.search("inode:5:100", None, None), vec![a, b]);
+        assert_eq!(searcher.search("inode:6:100", None, None), vec![d]);
+        assert_eq!(searcher.search("inode:5:101", None, None), vec![c]);
+    }
+
+    #[test]
+    fn test_import_ncdu_streaming() {
+        // A few thousand entries, nested two levels deep below the root
+        let mut json = String::from(r#"[1,2,{"progname":"ncdu","timestamp":1}, [{"name":"/data"}"#);
+        let mut expected = FileTree::with_capacity(4096);
+        expected.add_or_update_recursive("/data", None, None, None, Attributes::DIRECTORY);
+        for d in 0..40 {
+            json.push_str(&format!(r#",[{{"name":"dir{}"}}"#, d));
+            json.push_str(r#",[{"name":"sub"}"#);
+            for f in 0..25 {
+                json.push_str(&format!(
+                    r#",{{"name":"deep{}.txt","asize":{},"mtime":{}}}"#,
+                    f, f, d
+                ));
+                let path = format!("/data/dir{}/sub/deep{}.txt", d, f);
+                let date_modified = (d + 11644473600i64) * 10_000_000;
+                expected.add_or_update_recursive(
+                    &path,
+                    Some(f),
+                    Some(date_modified),
+                    None,
+                    Attributes::NONE,
+                );
+            }
+            json.push(']');
+            for f in 0..25 {
+                json.push_str(&format!(r#",{{"name":"file{}.bin","asize":{}}}"#, f, d * f));
+                let path = format!("/data/dir{}/file{}.bin", d, f);
+                expected.add_or_update_recursive(&path, Some(d * f), None, None, Attributes::NONE);
+            }
+            json.push(']');
+            for folder in [format!("/data/dir{}", d), format!("/data/dir{}/sub", d)] {
+                expected.add_or_update_recursive(&folder, None, None, None, Attributes::DIRECTORY);
             }
         }
-    } else {
-        return Err("Invalid NCDU top-level structure: missing InfoBlock".into());
+        json.push_str("]]");
+
+        let path = std::env::temp_dir().join("vaultseek_test_streaming.json");
+        std::fs::write(&path, json).unwrap();
+        let tree = import_ncdu_json(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let summary = |tree: &FileTree| {
+            let mut elements: Vec<_> = (1..tree.len())
+                .map(|index| {
+                    let element = &tree.get_elements()[index];
+                    let path = tree.get_full_path(index);
+                    (path, element.size, element.date_modified, element.is_dir())
+                })
+                .collect();
+            elements.sort();
+            elements
+        };
+        assert_eq!(tree.len(), 1 + 1 + 40 * 52);
+        assert_eq!(summary(&tree), summary(&expected));
     }
 
-    // Reduce capacity to the actual number of elements
-    tree.shrink_to_fit();
-    // Return the elements as a vector
-    Ok(tree)
+    #[test]
+    fn test_import_ncdu_invalid_structure() {
+        let path = std::env::temp_dir().join("vaultseek_test_invalid.json");
+        for json in [
+            r#"[1,2,{}]"#,
+            r#"[1,2,{},[[{"name":"sub"}]]]"#,
+            r#"[1,2,{},[{"name":"/data"},[]]]"#,
+        ] {
+            std::fs::write(&path, json).unwrap();
+            assert!(import_ncdu_json(&path).is_err(), "{}", json);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_import_ncdu_dotfiles() {
+        let path = std::env::temp_dir().join("vaultseek_test_dotfiles.json");
+        std::fs::write(
+            &path,
+            r#"[1,2,{"progname":"ncdu"},
+[{"name":"/home","mode":16877},
+ {"name":".bashrc","asize":10,"mode":33188},
+ {"name":"notes.txt","asize":10,"mode":33060}
+]]"#,
+        )
+        .unwrap();
+        let tree = import_ncdu_json(&path).unwrap();
+        let bashrc = tree.find_by_path("/home/.bashrc").unwrap();
+        let notes = tree.find_by_path("/home/notes.txt").unwrap();
+        assert_eq!(tree.get_elements()[bashrc].attributes, Attributes::HIDDEN);
+        assert_eq!(tree.get_elements()[notes].attributes, Attributes::READONLY);
+
+        let options = LoadOptions {
+            unix_attributes: UnixAttributeMapping {
+                dotfiles_hidden: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let tree = import_ncdu_json_with_options(&path, &options).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let bashrc = tree.find_by_path("/home/.bashrc").unwrap();
+        assert_eq!(tree.get_elements()[bashrc].attributes, Attributes::NONE);
+    }
+
+    #[test]
+    fn test_import_ncdu_max_depth() {
+        let path = std::env::temp_dir().join("vaultseek_test_ncdu_max_depth.json");
+        std::fs::write(
+            &path,
+            r#"[1,2,{"progname":"ncdu"},
+[{"name":"/data/disk"},
+ {"name":"a.txt","asize":10},
+ [{"name":"sub"},
+  {"name":"b.txt","asize":10},
+  [{"name":"deeper"},
+   {"name":"c.txt","asize":10}]]
+]]"#,
+        )
+        .unwrap();
+        let import = |max_depth| {
+            let options = LoadOptions {
+                limits: TreeLimits {
+                    max_depth: Some(max_depth),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            import_ncdu_json_with_options(&path, &options).unwrap()
+        };
+
+        // The root directory counts with all components of its path
+        let tree = import(3);
+        assert!(tree.find_by_path("/data/disk/a.txt").is_some());
+        assert!(tree.find_by_path("/data/disk/sub").is_some());
+        assert!(tree.find_by_path("/data/disk/sub/b.txt").is_none());
+        assert!(tree.find_by_path("/data/disk/sub/deeper").is_none());
+        assert_eq!(tree.len(), 5);
+        let tree = import(1);
+        assert!(tree.find_by_path("/data").is_none());
+        assert_eq!(tree.len(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
 }
@@ -13,7 +13,7 @@ pub fn post_filter(tree: &FileTree, indices: &mut Vec<usize>, query: &str) {
 
     // Filter results based on the query
 
-    indices.retain(|&index| regex.is_match(&tree.get_filename(index)));
+    indices.retain(|&index| regex.is_match(tree.get_filename(index)));
 
     // print!(
     //     "Post-filtering took {} ms, reduced results from {} to {}\n",
@@ -26,29 +26,48 @@ pub fn post_filter(tree: &FileTree, indices: &mut Vec<usize>, query: &str) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::attributes::Attributes;
     use crate::file_tree::FileTree;
 
     #[test]
     fn test_post_filter() {
         let mut tree = FileTree::with_capacity(5);
-        let element1 = tree.add_or_update_recursive(
-            "some/path/file1.txt",
-            Some(1000),
-            Some(4000),
-            Some(3000),
-            0,
-        );
-        let element2 = tree.add_or_update_recursive(
-            "other/path/file2.txt",
-            Some(3000),
-            Some(1000),
-            Some(4000),
-            0,
-        );
-        let element3 =
-            tree.add_or_update_recursive("mydir/file3.txt", Some(2000), Some(2000), Some(2000), 0);
-        let element4 =
-            tree.add_or_update_recursive("C:/file4.txt", Some(4000), Some(3000), Some(1000), 0);
+        let element1 = tree
+            .add_or_update_recursive(
+                "some/path/file1.txt",
+                Some(1000),
+                Some(4000),
+                Some(3000),
+                Attributes::NONE,
+            )
+            .unwrap();
+        let element2 = tree
+            .add_or_update_recursive(
+                "other/path/file2.txt",
+                Some(3000),
+                Some(1000),
+                Some(4000),
+                Attributes::NONE,
+            )
+            .unwrap();
+        let element3 = tree
+            .add_or_update_recursive(
+                "mydir/file3.txt",
+                Some(2000),
+                Some(2000),
+                Some(2000),
+                Attributes::NONE,
+            )
+            .unwrap();
+        let element4 = tree
+            .add_or_update_recursive(
+                "C:/file4.txt",
+                Some(4000),
+                Some(3000),
+                Some(1000),
+                Attributes::NONE,
+            )
+            .unwrap();
         let mut indices = vec![element1, element2, element3, element4];
         post_filter(&tree, &mut indices, "file2");
         assert_eq!(indices, vec![element2]);
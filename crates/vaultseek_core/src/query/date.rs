@@ -1,4 +1,107 @@
 use crate::query::query_parser::*;
+
+// seconds between 1601 and 1970
+const FILETIME_UNIX_EPOCH_OFFSET: i64 = 11644473600;
+
+// Convert a windows FILETIME (100-nanosecond intervals since January 1, 1601) to a unix timestamp
+pub fn filetime_to_timestamp(filetime: i64) -> i64 {
+    filetime / 10_000_000 - FILETIME_UNIX_EPOCH_OFFSET
+}
+
+impl QueryDate {
+    // Checks a unix timestamp against this date using the comparison.
+    // Ranges are inclusive on both ends, so `>` means after the end and `<` before the start.
+    pub fn matches(&self, cmp: &QueryCmp, timestamp: Option<i64>) -> bool {
+        use chrono::{Datelike, Local, TimeZone};
+
+        let Some(timestamp) = timestamp else {
+            // Only the unknown date matches elements without a date
+            return matches!(self, QueryDate::Unknown) && matches!(cmp, QueryCmp::Eq);
+        };
+        match self {
+            QueryDate::Range(start, end) => match cmp {
+                QueryCmp::Eq | QueryCmp::Range => *start <= timestamp && timestamp <= *end,
+                QueryCmp::Gt => timestamp > *end,
+                QueryCmp::Ge => timestamp >= *start,
+                QueryCmp::Lt => timestamp < *start,
+                QueryCmp::Le => timestamp <= *end,
+            },
+            QueryDate::Weekday(weekday) => match Local.timestamp_opt(timestamp, 0).single() {
+                Some(datetime) => cmp.compare(
+                    datetime.weekday().num_days_from_sunday() as i64,
+                    *weekday as i64,
+                ),
+                None => false,
+            },
+            QueryDate::Month(month) => match Local.timestamp_opt(timestamp, 0).single() {
+                Some(datetime) => cmp.compare(datetime.month() as i64, *month as i64),
+                None => false,
+            },
+            QueryDate::Unknown => false,
+        }
+    }
+}
+
+impl TimeOfDay {
+    // Checks the local time of day of a unix timestamp, whatever the date
+    pub fn matches(&self, timestamp: Option<i64>) -> bool {
+        use chrono::{Local, TimeZone, Timelike};
+
+        let datetime = timestamp.and_then(|timestamp| Local.timestamp_opt(timestamp, 0).single());
+        let Some(datetime) = datetime else {
+            return false;
+        };
+        let seconds = datetime.num_seconds_from_midnight();
+        if self.start <= self.end {
+            self.start <= seconds && seconds <= self.end
+        } else {
+            // Wraps around midnight, e.g. 22:00..02:00
+            seconds >= self.start || seconds <= self.end
+        }
+    }
+
+    // Parses 9, 9:30, 9:30:15, 9am or 5:30pm into the first and last second it covers
+    pub fn parse_time(s: &str) -> Option<(u32, u32)> {
+        let s = s.trim().to_lowercase();
+        let (s, meridiem) = if let Some(s) = s.strip_suffix("am") {
+            (s.trim_end(), Some(0))
+        } else if let Some(s) = s.strip_suffix("pm") {
+            (s.trim_end(), Some(12))
+        } else {
+            (s.as_str(), None)
+        };
+
+        let parts: Vec<u32> = s
+            .split(':')
+            .map(|part| part.parse::<u32>().ok())
+            .collect::<Option<_>>()?;
+        let (hour, minute, second) = match parts[..] {
+            [hour] => (hour, None, None),
+            [hour, minute] => (hour, Some(minute), None),
+            [hour, minute, second] => (hour, Some(minute), Some(second)),
+            _ => return None,
+        };
+        let hour = match meridiem {
+            // 12am is midnight, 12pm noon
+            Some(offset) if (1..=12).contains(&hour) => hour % 12 + offset,
+            Some(_) => return None,
+            None if hour < 24 => hour,
+            None => return None,
+        };
+        if minute.is_some_and(|minute| minute > 59) || second.is_some_and(|second| second > 59) {
+            return None;
+        }
+
+        let start = hour * 3600 + minute.unwrap_or(0) * 60 + second.unwrap_or(0);
+        let length = match (minute, second) {
+            (None, _) => 3600,
+            (Some(_), None) => 60,
+            (Some(_), Some(_)) => 1,
+        };
+        Some((start, start + length - 1))
+    }
+}
+
 impl From<&str> for QueryDate {
     fn from(s: &str) -> Self {
         use chrono::{Datelike, Local, NaiveDate, NaiveTime, TimeZone};
@@ -245,17 +348,22 @@ impl From<&str> for QueryDate {
         }
         
         // Try to parse as year only (4 digits)
-        if let Ok(year) = s.parse::<i32>() {
-            if year >= 1970 && year <= 9999 {
-                if let Some(start_date) = NaiveDate::from_ymd_opt(year, 1, 1) {
-                    if let Some(end_date) = NaiveDate::from_ymd_opt(year, 12, 31) {
-                        let (start, end) = date_range_to_timestamps(start_date, end_date);
-                        return QueryDate::Range(start, end);
-                    }
-                }
-            }
+        if let Ok(year) = s.parse::<i32>()
+            && (1970..=9999).contains(&year)
+            && let Some(start_date) = NaiveDate::from_ymd_opt(year, 1, 1)
+            && let Some(end_date) = NaiveDate::from_ymd_opt(year, 12, 31)
+        {
+            let (start, end) = date_range_to_timestamps(start_date, end_date);
+            return QueryDate::Range(start, end);
         }
         
+        // An exact second like 2023-12-25T12:30:45, as written when displaying a query
+        if let Ok(datetime) = chrono::NaiveDateTime::parse_from_str(&s, "%Y-%m-%dt%H:%M:%S")
+            && let Some(datetime) = Local.from_local_datetime(&datetime).earliest()
+        {
+            return QueryDate::Range(datetime.timestamp(), datetime.timestamp());
+        }
+
         // Try various date formats using chrono's parsing
         let date_formats = [
             "%Y-%m-%d",        // 2023-12-25
@@ -272,29 +380,29 @@ impl From<&str> for QueryDate {
         }
         
         // Try to parse MM/YYYY or YYYY/MM format
-        if let Some(captures) = regex::Regex::new(r"^(\d{1,4})/(\d{1,4})$").unwrap().captures(&s) {
-            if let (Ok(first), Ok(second)) = (captures[1].parse::<u32>(), captures[2].parse::<u32>()) {
-                let (year, month) = if first >= 1970 && first <= 9999 && second >= 1 && second <= 12 {
-                    (first as i32, second)
-                } else if second >= 1970 && second <= 9999 && first >= 1 && first <= 12 {
-                    (second as i32, first)
-                } else {
-                    return QueryDate::Range(0, 0);
+        if let Some(captures) = regex::Regex::new(r"^(\d{1,4})/(\d{1,4})$").unwrap().captures(&s)
+            && let (Ok(first), Ok(second)) = (captures[1].parse::<u32>(), captures[2].parse::<u32>())
+        {
+            let (year, month) = if (1970..=9999).contains(&first) && (1..=12).contains(&second) {
+                (first as i32, second)
+            } else if (1970..=9999).contains(&second) && (1..=12).contains(&first) {
+                (second as i32, first)
+            } else {
+                return QueryDate::Range(0, 0);
+            };
+            
+            if let Some(start_date) = NaiveDate::from_ymd_opt(year, month, 1) {
+                // Get last day of month
+                let next_month = if month == 12 { 
+                    NaiveDate::from_ymd_opt(year + 1, 1, 1)
+                } else { 
+                    NaiveDate::from_ymd_opt(year, month + 1, 1)
                 };
                 
-                if let Some(start_date) = NaiveDate::from_ymd_opt(year, month, 1) {
-                    // Get last day of month
-                    let next_month = if month == 12 { 
-                        NaiveDate::from_ymd_opt(year + 1, 1, 1)
-                    } else { 
-                        NaiveDate::from_ymd_opt(year, month + 1, 1)
-                    };
-                    
-                    if let Some(next_month_date) = next_month {
-                        let end_date = next_month_date - chrono::Duration::days(1);
-                        let (start, end) = date_range_to_timestamps(start_date, end_date);
-                        return QueryDate::Range(start, end);
-                    }
+                if let Some(next_month_date) = next_month {
+                    let end_date = next_month_date - chrono::Duration::days(1);
+                    let (start, end) = date_range_to_timestamps(start_date, end_date);
+                    return QueryDate::Range(start, end);
                 }
             }
         }
@@ -0,0 +1,471 @@
+// Renders a parsed query back into query syntax, e.g. to show users how their query was
+// understood. Parsing the rendering again gives the same query. AND is always written as `&`, so
+// the rendering reads the same whatever the space semantics, and blocks are added where the
+// precedence requires them.
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::query::query_parser::{
+    DupeKey, Month, NumRange, QueryCmp, QueryDate, QueryExpr, QueryFunction, QueryLiteral,
+    TimeOfDay, Weekday,
+};
+
+impl Display for QueryExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryExpr::Literal(literal) => write!(f, "{}", literal),
+            QueryExpr::Function(function) => write!(f, "{}", function),
+            // Both are parsed with the same grouping as written here: a & b & c as (a & b) & c,
+            // a | b | c as a | (b | c). Only the other nesting and ORs inside ANDs need blocks.
+            QueryExpr::And(left, right) => {
+                write_operand(f, left, matches!(**left, QueryExpr::Or(..)))?;
+                write!(f, " & ")?;
+                write_operand(
+                    f,
+                    right,
+                    matches!(**right, QueryExpr::And(..) | QueryExpr::Or(..)),
+                )
+            }
+            QueryExpr::Or(left, right) => {
+                write_operand(f, left, matches!(**left, QueryExpr::Or(..)))?;
+                write!(f, " | {}", right)
+            }
+            QueryExpr::Not(operand) => {
+                write!(f, "!")?;
+                write_operand(
+                    f,
+                    operand,
+                    matches!(**operand, QueryExpr::And(..) | QueryExpr::Or(..)),
+                )
+            }
+        }
+    }
+}
+
+fn write_operand(f: &mut Formatter<'_>, expr: &QueryExpr, block: bool) -> fmt::Result {
+    if block {
+        // The space before > keeps it from being read as part of the last condition
+        write!(f, "< {} >", expr)
+    } else {
+        write!(f, "{}", expr)
+    }
+}
+
+impl Display for QueryLiteral {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let modifiers = [
+            ("case:", self.case_sensitive()),
+            ("diacritics:", self.diacritics_sensitive()),
+            ("file:", self.file_only()),
+            ("folder:", self.folder_only()),
+            ("path:", self.match_path()),
+        ];
+        for (modifier, _) in modifiers.iter().filter(|(_, enabled)| *enabled) {
+            write!(f, "{}", modifier)?;
+        }
+        match self {
+            QueryLiteral::Text(text) => {
+                if text.whole_filename {
+                    write!(f, "wfn:")?;
+                }
+                if text.whole_word {
+                    write!(f, "ww:")?;
+                }
+                write_value(f, &text.text)
+            }
+            QueryLiteral::Regex(regex) => {
+                write!(f, "regex:")?;
+                write_value(f, regex.pattern.as_str())
+            }
+        }
+    }
+}
+
+impl QueryLiteral {
+    fn case_sensitive(&self) -> bool {
+        match self {
+            QueryLiteral::Text(text) => text.case_sensitive,
+            QueryLiteral::Regex(regex) => regex.case_sensitive,
+        }
+    }
+    fn diacritics_sensitive(&self) -> bool {
+        match self {
+            QueryLiteral::Text(text) => text.diacritics_sensitive,
+            QueryLiteral::Regex(regex) => regex.diacritics_sensitive,
+        }
+    }
+    fn file_only(&self) -> bool {
+        match self {
+            QueryLiteral::Text(text) => text.file_only,
+            QueryLiteral::Regex(regex) => regex.file_only,
+        }
+    }
+    fn folder_only(&self) -> bool {
+        match self {
+            QueryLiteral::Text(text) => text.folder_only,
+            QueryLiteral::Regex(regex) => regex.folder_only,
+        }
+    }
+    fn match_path(&self) -> bool {
+        match self {
+            QueryLiteral::Text(text) => text.match_path,
+            QueryLiteral::Regex(regex) => regex.match_path,
+        }
+    }
+}
+
+// Quotes values that would otherwise be split up or read as an operator, function or modifier.
+// Quotes can't be escaped, values containing one are written as is.
+fn write_value(f: &mut Formatter<'_>, value: &str) -> fmt::Result {
+    let needs_quotes = value.is_empty()
+        || value.contains(|c: char| c.is_whitespace() || c == ':')
+        || value.starts_with(['<', '>', '=', '!', '|', '&']);
+    if needs_quotes && !value.contains('"') {
+        write!(f, "\"{}\"", value)
+    } else {
+        write!(f, "{}", value)
+    }
+}
+
+// The folder functions carry their own case: modifier
+fn write_case(f: &mut Formatter<'_>, case_sensitive: bool) -> fmt::Result {
+    if case_sensitive {
+        write!(f, "case:")?;
+    }
+    Ok(())
+}
+
+impl Display for QueryFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryFunction::Size(cmp, size) => write!(f, "size:{}{}", cmp_prefix(cmp), size),
+            QueryFunction::SizeRange(start, end) => write!(f, "size:{}..{}", start, end),
+            QueryFunction::DateModified(cmp, date) => {
+                write!(f, "dm:")?;
+                write_date_argument(f, cmp, date)
+            }
+            QueryFunction::DateCreated(cmp, date) => {
+                write!(f, "dc:")?;
+                write_date_argument(f, cmp, date)
+            }
+            QueryFunction::Parent(folder, case_sensitive) => {
+                write_case(f, *case_sensitive)?;
+                write!(f, "parent:")?;
+                write_value(f, folder)
+            }
+            QueryFunction::ParentDirectOnly(folder, case_sensitive) => {
+                write_case(f, *case_sensitive)?;
+                write!(f, "nosubfolders:")?;
+                write_value(f, folder)
+            }
+            QueryFunction::Component(text) => {
+                write!(f, "component:")?;
+                write_value(f, text)
+            }
+            QueryFunction::ParentAt(level, folder, case_sensitive) => {
+                write_case(f, *case_sensitive)?;
+                write!(f, "parentat:{}:", level)?;
+                write_value(f, folder)
+            }
+            QueryFunction::Ext(exts) => {
+                write!(f, "ext:")?;
+                write_value(f, &exts.join(";"))
+            }
+            QueryFunction::Dupe(key) => write!(f, "dupe:{}", key),
+            QueryFunction::Empty => write!(f, "empty:"),
+            QueryFunction::HasDigits => write!(f, "hasdigits:"),
+            QueryFunction::HasSpaces => write!(f, "hasspaces:"),
+            QueryFunction::HasUnicode => write!(f, "hasunicode:"),
+            QueryFunction::NumRange(range) => {
+                write!(f, "numrange:")?;
+                write_value(f, &range.to_string())
+            }
+            QueryFunction::TimeOfDay(time_of_day) => write!(f, "tod:{}", time_of_day),
+            QueryFunction::Hardlinks(cmp, nlink) => {
+                write!(f, "hardlinks:{}{}", cmp_prefix(cmp), nlink)
+            }
+            QueryFunction::Inode(device, inode) => write!(f, "inode:{}:{}", device, inode),
+            QueryFunction::CreatedVsModified(cmp) => write!(f, "cmdate:created{}modified", cmp),
+            QueryFunction::NameLength(cmp, len) => {
+                write!(f, "namelen:{}{}", cmp_prefix(cmp), len)
+            }
+            QueryFunction::Mime(mime) => {
+                write!(f, "mime:")?;
+                write_value(f, mime)
+            }
+            QueryFunction::Children(cmp, count) => {
+                write!(f, "children:{}{}", cmp_prefix(cmp), count)
+            }
+        }
+    }
+}
+
+// Equality is the default comparison of function arguments, and ranges are part of the value
+fn cmp_prefix(cmp: &QueryCmp) -> &'static str {
+    match cmp {
+        QueryCmp::Eq | QueryCmp::Range => "",
+        QueryCmp::Gt => ">",
+        QueryCmp::Ge => ">=",
+        QueryCmp::Lt => "<",
+        QueryCmp::Le => "<=",
+    }
+}
+
+impl Display for QueryCmp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryCmp::Eq => write!(f, "="),
+            QueryCmp::Range => write!(f, ".."),
+            cmp => write!(f, "{}", cmp_prefix(cmp)),
+        }
+    }
+}
+
+impl Display for DupeKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match (self.name, self.size) {
+            (true, true) => write!(f, "name;size"),
+            (false, true) => write!(f, "size"),
+            _ => write!(f, "name"),
+        }
+    }
+}
+
+impl Display for QueryDate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryDate::Range(start, end) => match single_date(*start, *end) {
+                Some(date) => write!(f, "{}", date),
+                None => write!(f, "{}", date_range(*start, *end)),
+            },
+            QueryDate::Weekday(weekday) => write!(f, "{}", weekday_name(*weekday)),
+            QueryDate::Month(month) => write!(f, "{}", month_name(*month)),
+            QueryDate::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+// The argument of dm: and dc:. A comparison against a range only uses one of its ends, so a
+// range that isn't a single date is written as that end.
+fn write_date_argument(f: &mut Formatter<'_>, cmp: &QueryCmp, date: &QueryDate) -> fmt::Result {
+    let QueryDate::Range(start, end) = *date else {
+        return write!(f, "{}{}", cmp_prefix(cmp), date);
+    };
+    let single = single_date(start, end);
+    let value = match cmp {
+        QueryCmp::Range => date_range(start, end),
+        QueryCmp::Eq => single.unwrap_or_else(|| date_range(start, end)),
+        QueryCmp::Gt | QueryCmp::Le => single.unwrap_or_else(|| datetime(end, "%Y-%m-%dT%H:%M:%S")),
+        QueryCmp::Ge | QueryCmp::Lt => {
+            single.unwrap_or_else(|| datetime(start, "%Y-%m-%dT%H:%M:%S"))
+        }
+    };
+    write!(f, "{}", cmp_prefix(cmp))?;
+    write_value(f, &value)
+}
+
+fn local_datetime(timestamp: i64) -> Option<chrono::DateTime<chrono::Local>> {
+    use chrono::TimeZone;
+    chrono::Local.timestamp_opt(timestamp, 0).single()
+}
+
+fn datetime(timestamp: i64, format: &str) -> String {
+    match local_datetime(timestamp) {
+        Some(datetime) => datetime.format(format).to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+fn is_midnight(timestamp: i64) -> bool {
+    use chrono::Timelike;
+    local_datetime(timestamp).is_some_and(|datetime| datetime.num_seconds_from_midnight() == 0)
+}
+
+// Writes a range as one value that parses back to it: a second, a day, a month or a year
+fn single_date(start: i64, end: i64) -> Option<String> {
+    use chrono::Datelike;
+
+    if start == end {
+        return Some(datetime(start, "%Y-%m-%dT%H:%M:%S"));
+    }
+    if !is_midnight(start) || !is_midnight(end + 1) {
+        return None;
+    }
+    let first = local_datetime(start)?.date_naive();
+    let last = local_datetime(end)?.date_naive();
+    let next = last.succ_opt()?;
+    let after = |months| first.checked_add_months(chrono::Months::new(months));
+    if first == last {
+        Some(first.format("%Y-%m-%d").to_string())
+    } else if first.day() == 1 && after(1) == Some(next) {
+        Some(first.format("%Y/%m").to_string())
+    } else if first.ordinal() == 1 && after(12) == Some(next) && first.year() >= 1970 {
+        Some(first.year().to_string())
+    } else {
+        None
+    }
+}
+
+// Ends at midnight are written as dates, which cover the whole day
+fn date_range(start: i64, end: i64) -> String {
+    let start = if is_midnight(start) {
+        datetime(start, "%Y-%m-%d")
+    } else {
+        datetime(start, "%Y-%m-%dT%H:%M:%S")
+    };
+    let end = if is_midnight(end + 1) {
+        datetime(end, "%Y-%m-%d")
+    } else {
+        datetime(end, "%Y-%m-%dT%H:%M:%S")
+    };
+    format!("{}..{}", start, end)
+}
+
+fn weekday_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Sunday => "sunday",
+        Weekday::Monday => "monday",
+        Weekday::Tuesday => "tuesday",
+        Weekday::Wednesday => "wednesday",
+        Weekday::Thursday => "thursday",
+        Weekday::Friday => "friday",
+        Weekday::Saturday => "saturday",
+    }
+}
+
+fn month_name(month: Month) -> &'static str {
+    match month {
+        Month::January => "january",
+        Month::February => "february",
+        Month::March => "march",
+        Month::April => "april",
+        Month::May => "may",
+        Month::June => "june",
+        Month::July => "july",
+        Month::August => "august",
+        Month::September => "september",
+        Month::October => "october",
+        Month::November => "november",
+        Month::December => "december",
+    }
+}
+
+impl Display for NumRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let width = self.width.unwrap_or(0);
+        let (prefix, suffix) = (&self.prefix, &self.suffix);
+        write!(
+            f,
+            "{prefix}{:0width$}{suffix}..{prefix}{:0width$}{suffix}",
+            self.start, self.end
+        )
+    }
+}
+
+impl Display for TimeOfDay {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let time = |seconds: u32| {
+            format!(
+                "{:02}:{:02}:{:02}",
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60
+            )
+        };
+        if self.start == self.end {
+            write!(f, "{}", time(self.start))
+        } else {
+            write!(f, "{}..{}", time(self.start), time(self.end))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query::query_parser::{
+        ParseOptions, SpaceSemantics, parse_query, parse_query_with_options,
+    };
+
+    fn rendered(query: &str) -> String {
+        parse_query(query).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_display_nested_query() {
+        assert_eq!(
+            rendered("case:Report <ext:pdf | !size:>10kb > !<draft | \"old copy\" >"),
+            "case:Report & < ext:pdf | !size:>10240 > & !< draft | \"old copy\" >"
+        );
+        assert_eq!(rendered("a b | regex:^c d"), "a & b | regex:^c & d");
+        assert_eq!(rendered("file:ww:x dupe:"), "file:ww:x & dupe:name");
+        assert_eq!(rendered(""), "\"\"");
+    }
+
+    // Parses, displays and parses again, the ASTs have to be the same
+    fn assert_round_trip(query: &str, options: ParseOptions) {
+        let expr = parse_query_with_options(query, options).unwrap();
+        let rendered = expr.to_string();
+        let reparsed = parse_query(&rendered)
+            .unwrap_or_else(|e| panic!("'{}' rendered as '{}' fails: {}", query, rendered, e));
+        assert_eq!(
+            format!("{:?}", reparsed),
+            format!("{:?}", expr),
+            "'{}' rendered as '{}'",
+            query,
+            rendered
+        );
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let queries = [
+            "report",
+            "\"old copy\" \"a:b\" \"<x\" \"\"",
+            "case:diacritics:Café file:path:wfn:a folder:ww:b",
+            "regex:\"^a b$\" case:regex:[A-Z]+ exact:x&y",
+            "a b c | d | e & f",
+            "<a | b> | c",
+            "a <b c> <d | e> !<f g> !!h",
+            "!<a | b> & !case:x",
+            "size:>=1mb size:1..2kb size:[0..10) size:~100 size:<5",
+            "dm:2023 dm:2023/05 dm:2023-05-17 dc:>2023 dm:<=2023-05-17 dm:2023-01-01..2023-03-31",
+            "dm:[2023-01-01..2023-02-01) dc:>=monday dm:<june dc:unknown",
+            "dm:\">2023-05-17T10:30:00\" dm:\"2023-05-17T10:30:00..2023-05-18\"",
+            "tod:9:00..17:00 tod:22..2 tod:5pm",
+            "parent:\"My Documents\" nosubfolders:src component:\"a b\" parentat:2:src case:parent:Src case:nosubfolders:src case:parentat:1:Src ext:jpg;png dupe: dupe:size;name empty:",
+            "hardlinks: nlink:>=3 inode:5:42 ext:jpg | ext:\"tar gz\"",
+            "namelen:>100 namelen:<=5 namelen:8",
+            "=report.txt | =\"my notes.txt\" = x",
+            "children:>10000 children:0 | children:<=3 folder:",
+            "mime:image/png | mime:image/* !mime:\"*\"",
+            "hasdigits: hasspaces: | !hasunicode:",
+            "numrange:IMG_0001..IMG_0100 numrange:\"take 1.wav..take 20.wav\"",
+            "clockskew: cmdate:modified>=created cmdate:created=modified",
+        ];
+        for query in queries {
+            assert_round_trip(query, ParseOptions::default());
+        }
+        // AND is written explicitly, so the rendering doesn't depend on the space semantics
+        let options = ParseOptions {
+            space_semantics: SpaceSemantics::Or,
+            ..Default::default()
+        };
+        for query in ["a b & c | d", "<a b> c", "!<a b> size:>1"] {
+            assert_round_trip(query, options);
+        }
+    }
+
+    #[test]
+    fn test_display_relative_dates() {
+        // Ranges that aren't a single date are written out, comparisons keep the end they use
+        for query in [
+            "dm:thisweek",
+            "dm:>last3days",
+            "dc:<=lastweek",
+            "dm:past2hours",
+        ] {
+            let rendered = rendered(query);
+            assert_eq!(rendered, parse_query(&rendered).unwrap().to_string());
+        }
+    }
+}
@@ -0,0 +1,1736 @@
+use std::{cell::OnceCell, collections::HashMap, time::Instant};
+
+use crate::{
+    file_tree::{
+        ElementType, FileCategory, FileTree, classify_extension, extension_suffixes, mime_type_of,
+    },
+    indexer::{bigram_index::BigramIndex, parent_names::ParentNameIndex},
+    query::date::filetime_to_timestamp,
+    query::query_parser::{DupeKey, QueryExpr, QueryFunction, QueryLiteral, RegexQuery, TextQuery},
+};
+
+// A query expression prepared for evaluation. Patterns are compiled once per query instead of once per element.
+enum CompiledExpr<'q> {
+    Text(TextMatcher),
+    Regex(&'q RegexQuery),
+    Parent(FolderMatcher),
+    ParentDirectOnly(FolderMatcher),
+    Component(String), // Lowercased like the folded names
+    ParentAt(usize, regex::Regex, String),
+    Ext(ExtMatcher),
+    Function(&'q QueryFunction),
+    And(Box<CompiledExpr<'q>>, Box<CompiledExpr<'q>>),
+    Or(Box<CompiledExpr<'q>>, Box<CompiledExpr<'q>>),
+    Not(Box<CompiledExpr<'q>>),
+}
+
+struct TextMatcher {
+    pattern: Option<regex::Regex>, // None for the empty text, which matches everything
+    folded: Option<String>,        // Lowercased text for plain case-insensitive substring matches
+    seed: Option<String>,          // Lowercased text to look up candidates in the bigram index
+    exact_seed: bool,              // The bigram candidates are already the exact result
+    match_path: bool,
+    element_type: Option<ElementType>,
+}
+impl TextMatcher {
+    fn new(query: &TextQuery, separator_boundaries: Option<&str>) -> Self {
+        let element_type = element_type_filter(query.file_only, query.folder_only);
+        if query.text.is_empty() {
+            return TextMatcher {
+                pattern: None,
+                folded: None,
+                seed: None,
+                exact_seed: false,
+                match_path: query.match_path,
+                element_type,
+            };
+        }
+
+        // Paths use \ as separator, but / in the query should match them too
+        let text = if query.match_path {
+            normalize_separators(&query.text)
+        } else {
+            query.text.clone()
+        };
+        let mut pattern = regex::escape(&text);
+        if query.whole_filename {
+            pattern = format!("^{}$", pattern);
+        } else if query.whole_word
+            && query.match_path
+            && let Some(separators) = separator_boundaries
+        {
+            // \b alone needs a word character next to the text, so ww:path:.git wouldn't match
+            // the .git folder after a separator
+            pattern = format!(r"(?:^|\b|{0}){1}(?:$|\b|{0})", separators, pattern);
+        } else if query.whole_word {
+            pattern = format!(r"\b{}\b", pattern);
+        }
+        let pattern = regex::RegexBuilder::new(&pattern)
+            .case_insensitive(!query.case_sensitive)
+            .build()
+            .expect("Failed to compile regex");
+
+        // Plain case-insensitive substrings are matched against the folded name, which is folded
+        // the same way as the bigram index, so candidates and matches never diverge
+        let plain = !query.case_sensitive && !query.whole_filename && !query.whole_word;
+        let folded = plain.then(|| text.to_lowercase());
+        // The bigram index only covers filenames, path queries need a full scan
+        let seed = (!query.match_path).then(|| query.text.to_lowercase());
+        // Postings of one bigram (or the single char scan) are exact for short case-insensitive substrings
+        let exact_seed = plain
+            && element_type.is_none()
+            && seed.as_ref().is_some_and(|seed| seed.chars().count() <= 2);
+
+        TextMatcher {
+            pattern: Some(pattern),
+            folded,
+            seed,
+            exact_seed,
+            match_path: query.match_path,
+            element_type,
+        }
+    }
+}
+
+// A MIME type against the argument of mime:, which is exact, type/* for any subtype or * for any
+// known type
+fn mime_matches(pattern: &str, mime: &str) -> bool {
+    if pattern == "*" || pattern == "*/*" {
+        return true;
+    }
+    match pattern.strip_suffix("/*") {
+        Some(kind) => mime.split('/').next() == Some(kind),
+        None => pattern == mime,
+    }
+}
+
+// Replaces / with \, the separator of FileTree::get_full_path, so both match the same paths
+fn normalize_separators(path: &str) -> String {
+    path.replace('/', "\\")
+}
+
+// Extensions of ext:, plain ones are compared exactly and ones with * or ? wildcards are
+// matched as globs against the whole extension, so ext:jp* matches jpg and jpeg but not ajp.
+// Compound extensions match too: archive.tar.gz has the extensions gz and tar.gz, see
+// file_tree::extension_suffixes. Wildcards don't match dots, so ext:* is the last extension.
+// Group names like ext:image match all extensions of the category in classify_extension, and
+// still the literal extension.
+struct ExtMatcher {
+    exact: Vec<String>,
+    glob: Option<regex::Regex>,
+    groups: Vec<FileCategory>,
+}
+
+impl ExtMatcher {
+    fn new(exts: &[String]) -> Self {
+        let (globs, exact): (Vec<&String>, Vec<&String>) =
+            exts.iter().partition(|ext| ext.contains(['*', '?']));
+        let glob = (!globs.is_empty()).then(|| {
+            let alternatives = globs
+                .iter()
+                .map(|glob| glob_pattern(glob))
+                .collect::<Vec<_>>()
+                .join("|");
+            regex::RegexBuilder::new(&format!("^(?:{})$", alternatives))
+                .case_insensitive(true)
+                .build()
+                .expect("Failed to compile regex")
+        });
+        let groups = exact
+            .iter()
+            .filter_map(|ext| FileCategory::from_group_name(ext))
+            .collect();
+        ExtMatcher {
+            exact: exact.into_iter().cloned().collect(),
+            glob,
+            groups,
+        }
+    }
+
+    fn matches(&self, ext: &str) -> bool {
+        self.exact.iter().any(|e| e.eq_ignore_ascii_case(ext))
+            || self.glob.as_ref().is_some_and(|glob| glob.is_match(ext))
+            || (!self.groups.is_empty() && self.groups.contains(&classify_extension(ext)))
+    }
+}
+
+// Translates a glob with * (any characters) and ? (one character) into a regex. Neither matches
+// a dot, which separates the parts of compound extensions.
+fn glob_pattern(glob: &str) -> String {
+    let mut pattern = String::new();
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str("[^.]*"),
+            '?' => pattern.push_str("[^.]"),
+            c => pattern.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    pattern
+}
+
+fn element_type_filter(file_only: bool, folder_only: bool) -> Option<ElementType> {
+    if file_only {
+        Some(ElementType::File)
+    } else if folder_only {
+        Some(ElementType::Folder)
+    } else {
+        None
+    }
+}
+
+// With separator_boundaries, a regex alternation of the path separators, the separators always
+// end a whole word in path queries, see QueryEvaluator::with_separator_word_boundaries
+fn compile<'q>(expr: &'q QueryExpr, separator_boundaries: Option<&str>) -> CompiledExpr<'q> {
+    match expr {
+        QueryExpr::Literal(QueryLiteral::Text(text)) => {
+            CompiledExpr::Text(TextMatcher::new(text, separator_boundaries))
+        }
+        QueryExpr::Literal(QueryLiteral::Regex(regex)) => CompiledExpr::Regex(regex),
+        QueryExpr::Function(QueryFunction::Parent(folder, case_sensitive)) => {
+            CompiledExpr::Parent(FolderMatcher::new(folder, *case_sensitive))
+        }
+        QueryExpr::Function(QueryFunction::ParentDirectOnly(folder, case_sensitive)) => {
+            CompiledExpr::ParentDirectOnly(FolderMatcher::new(folder, *case_sensitive))
+        }
+        QueryExpr::Function(QueryFunction::Component(text)) => {
+            CompiledExpr::Component(text.to_lowercase())
+        }
+        QueryExpr::Function(QueryFunction::ParentAt(level, folder, case_sensitive)) => {
+            CompiledExpr::ParentAt(
+                *level,
+                folder_pattern(folder, *case_sensitive),
+                folder_name(folder),
+            )
+        }
+        QueryExpr::Function(QueryFunction::Ext(exts)) => CompiledExpr::Ext(ExtMatcher::new(exts)),
+        QueryExpr::Function(function) => CompiledExpr::Function(function),
+        QueryExpr::And(left, right) => CompiledExpr::And(
+            Box::new(compile(left, separator_boundaries)),
+            Box::new(compile(right, separator_boundaries)),
+        ),
+        QueryExpr::Or(left, right) => CompiledExpr::Or(
+            Box::new(compile(left, separator_boundaries)),
+            Box::new(compile(right, separator_boundaries)),
+        ),
+        QueryExpr::Not(expr) => CompiledExpr::Not(Box::new(compile(expr, separator_boundaries))),
+    }
+}
+
+// Builds a pattern matching a whole folder name, or a whole folder path if the argument contains
+// separators. Both separators are accepted in paths. Case-insensitive unless case: was set.
+fn folder_pattern(folder: &str, case_sensitive: bool) -> regex::Regex {
+    let folder = folder.trim_matches(&['\\', '/'][..]);
+    let pattern = folder
+        .split(&['\\', '/'])
+        .map(regex::escape)
+        .collect::<Vec<_>>()
+        .join(r"[\\/]");
+    regex::RegexBuilder::new(&format!("^{}$", pattern))
+        .case_insensitive(!case_sensitive)
+        .build()
+        .expect("Failed to compile regex")
+}
+
+// The last name in a folder argument. Folders matched by folder_pattern always have this name.
+fn folder_name(folder: &str) -> String {
+    let folder = folder.trim_matches(&['\\', '/'][..]);
+    folder
+        .rsplit(&['\\', '/'])
+        .next()
+        .unwrap_or(folder)
+        .to_string()
+}
+
+// The folder argument of parent: and nosubfolders:. Only arguments with separators are matched
+// against the full path, which has to be built for every folder that is checked.
+struct FolderMatcher {
+    pattern: regex::Regex,
+    name: String, // To look up in the parent name index
+    is_path: bool,
+}
+impl FolderMatcher {
+    fn new(folder: &str, case_sensitive: bool) -> Self {
+        FolderMatcher {
+            pattern: folder_pattern(folder, case_sensitive),
+            name: folder_name(folder),
+            is_path: folder
+                .trim_matches(&['\\', '/'][..])
+                .contains(&['\\', '/'][..]),
+        }
+    }
+
+    fn matches(&self, tree: &FileTree, folder: usize) -> bool {
+        if self.is_path {
+            self.pattern.is_match(&tree.get_full_path(folder))
+        } else {
+            self.pattern.is_match(tree.get_filename(folder))
+        }
+    }
+}
+
+// Whether the expression has folder functions that can use a ParentNameIndex
+pub fn uses_parent_names(expr: &QueryExpr) -> bool {
+    match expr {
+        QueryExpr::Function(
+            QueryFunction::Parent(..)
+            | QueryFunction::ParentDirectOnly(..)
+            | QueryFunction::ParentAt(..),
+        ) => true,
+        QueryExpr::And(left, right) | QueryExpr::Or(left, right) => {
+            uses_parent_names(left) || uses_parent_names(right)
+        }
+        QueryExpr::Not(expr) => uses_parent_names(expr),
+        _ => false,
+    }
+}
+
+// Collects the keys of all dupe: functions in the expression. The parser only accepts them
+// combined with AND, see QueryError::NestedDupe.
+fn dupe_keys(expr: &QueryExpr, keys: &mut Vec<DupeKey>) {
+    match expr {
+        QueryExpr::Function(QueryFunction::Dupe(key)) => keys.push(*key),
+        QueryExpr::And(left, right) => {
+            dupe_keys(left, keys);
+            dupe_keys(right, keys);
+        }
+        _ => {}
+    }
+}
+
+// Intersection of two sorted index lists
+fn intersect(left: &[usize], right: &[usize]) -> Vec<usize> {
+    debug_assert!(left.is_sorted() && right.is_sorted());
+    let mut result = Vec::with_capacity(left.len().min(right.len()));
+    let mut i = 0;
+    let mut j = 0;
+    while i < left.len() && j < right.len() {
+        if left[i] == right[j] {
+            result.push(left[i]);
+            i += 1;
+            j += 1;
+        } else if left[i] < right[j] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    result
+}
+
+// Union of two sorted index lists, without duplicates
+fn union(left: &[usize], right: &[usize]) -> Vec<usize> {
+    debug_assert!(left.is_sorted() && right.is_sorted());
+    let mut result = Vec::with_capacity(left.len() + right.len());
+    let mut i = 0;
+    let mut j = 0;
+    while i < left.len() && j < right.len() {
+        if left[i] == right[j] {
+            result.push(left[i]);
+            i += 1;
+            j += 1;
+        } else if left[i] < right[j] {
+            result.push(left[i]);
+            i += 1;
+        } else {
+            result.push(right[j]);
+            j += 1;
+        }
+    }
+    result.extend_from_slice(&left[i..]);
+    result.extend_from_slice(&right[j..]);
+    result
+}
+
+// Whether the candidates of the expression are already exactly its matches
+fn is_exact(expr: &CompiledExpr) -> bool {
+    match expr {
+        CompiledExpr::Text(matcher) => matcher.exact_seed,
+        CompiledExpr::And(left, right) | CompiledExpr::Or(left, right) => {
+            is_exact(left) && is_exact(right)
+        }
+        _ => false,
+    }
+}
+
+// Whether every match of `expr` is also a match of `previous`, so results of `previous` can be
+// refined instead of searching again. This is the case when a text is extended (e.g. `re` to
+// `rep`) or more conditions are added with AND (e.g. `rep` to `rep ext:pdf`).
+pub fn is_refinement(previous: &QueryExpr, expr: &QueryExpr) -> bool {
+    match (previous, expr) {
+        (
+            QueryExpr::Literal(QueryLiteral::Text(old)),
+            QueryExpr::Literal(QueryLiteral::Text(new)),
+        ) => {
+            // Whole word and whole filename matches of a longer text aren't a subset
+            let same_modifiers = TextQuery {
+                text: new.text.clone(),
+                ..old.clone()
+            } == *new;
+            if !same_modifiers || old.whole_word || old.whole_filename {
+                return false;
+            }
+            if old.case_sensitive {
+                new.text.contains(&old.text)
+            } else {
+                new.text.to_lowercase().contains(&old.text.to_lowercase())
+            }
+        }
+        (_, QueryExpr::And(left, right)) => {
+            is_refinement(previous, left) || is_refinement(previous, right)
+        }
+        _ => false,
+    }
+}
+
+// The forms of an element's name used while matching one element against all conditions.
+// The original forms are what gets displayed, the folded (lowercased) forms are only used for
+// case-insensitive matching. Paths and folded forms are computed on first use.
+pub struct MatchContext<'a> {
+    tree: &'a FileTree,
+    pub index: usize,
+    path: OnceCell<String>,
+    normalized_path: OnceCell<String>,
+    folded_name: OnceCell<String>,
+    folded_path: OnceCell<String>,
+}
+impl<'a> MatchContext<'a> {
+    pub fn new(tree: &'a FileTree, index: usize) -> Self {
+        MatchContext {
+            tree,
+            index,
+            path: OnceCell::new(),
+            normalized_path: OnceCell::new(),
+            folded_name: OnceCell::new(),
+            folded_path: OnceCell::new(),
+        }
+    }
+
+    // The filename in its original case
+    pub fn name(&self) -> &'a str {
+        self.tree.get_filename(self.index)
+    }
+    // The full path in its original case
+    pub fn path(&self) -> &str {
+        self.path
+            .get_or_init(|| self.tree.get_full_path(self.index))
+    }
+    // The filename folded the same way as in the bigram index
+    pub fn folded_name(&self) -> &str {
+        self.folded_name.get_or_init(|| self.name().to_lowercase())
+    }
+    // The full path with only \ as separator, like path text queries
+    pub fn normalized_path(&self) -> &str {
+        self.normalized_path
+            .get_or_init(|| normalize_separators(self.path()))
+    }
+    pub fn folded_path(&self) -> &str {
+        self.folded_path
+            .get_or_init(|| self.normalized_path().to_lowercase())
+    }
+
+    fn original(&self, match_path: bool) -> &str {
+        if match_path { self.path() } else { self.name() }
+    }
+    fn normalized(&self, match_path: bool) -> &str {
+        if match_path {
+            self.normalized_path()
+        } else {
+            self.name()
+        }
+    }
+    fn folded(&self, match_path: bool) -> &str {
+        if match_path {
+            self.folded_path()
+        } else {
+            self.folded_name()
+        }
+    }
+}
+
+// Candidates matched between two checks of the deadline
+const DEADLINE_CHECK_INTERVAL: usize = 1024;
+
+// How an evaluation went, e.g. to tell the user that results may be incomplete
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchDiagnostics {
+    pub candidate_count: usize, // Elements left after narrowing down with the bigram index
+    pub scanned: usize,         // Candidates matched one by one against the query
+    pub truncated: bool,        // The scan budget ran out, so matches may be missing
+    pub timed_out: bool,        // The deadline passed while scanning, so matches may be missing
+}
+
+pub struct QueryEvaluator<'a> {
+    tree: &'a FileTree,
+    bigram_index: &'a BigramIndex,
+    // Compare folders by their own size, not by the rollup size of their contents
+    raw_folder_sizes: bool,
+    // Scan at most this many candidates, for responsiveness on huge trees
+    scan_budget: Option<usize>,
+    // Stop scanning candidates at this point in time, see with_deadline
+    deadline: Option<Instant>,
+    // Path matches don't count inside dot folders like .git or for dotfiles
+    exclude_dot_components: bool,
+    // Compare elements without a size as if they were empty, see with_none_sizes_as_zero
+    none_sizes_as_zero: bool,
+    // Path separators are word boundaries for ww:path:, see with_separator_word_boundaries
+    separator_word_boundaries: bool,
+    // Negated size and date functions skip elements without the field, see with_strict_negation
+    strict_negation: bool,
+    // Look up nosubfolders: and parentat: folders by name instead of walking up from every element
+    parent_names: Option<&'a ParentNameIndex>,
+    // Whether each element is inside the searched subtree, None to search the whole tree
+    scope: Option<&'a [bool]>,
+}
+
+impl<'a> QueryEvaluator<'a> {
+    pub fn new(tree: &'a FileTree, bigram_index: &'a BigramIndex) -> Self {
+        QueryEvaluator {
+            tree,
+            bigram_index,
+            raw_folder_sizes: false,
+            scan_budget: None,
+            deadline: None,
+            exclude_dot_components: false,
+            none_sizes_as_zero: false,
+            separator_word_boundaries: false,
+            strict_negation: false,
+            parent_names: None,
+            scope: None,
+        }
+    }
+
+    // By default size: compares folders by the total size of their contents if the tree has
+    // rollup sizes computed
+    pub fn with_raw_folder_sizes(mut self, raw_folder_sizes: bool) -> Self {
+        self.raw_folder_sizes = raw_folder_sizes;
+        self
+    }
+
+    pub fn with_scan_budget(mut self, scan_budget: Option<usize>) -> Self {
+        self.scan_budget = scan_budget;
+        self
+    }
+
+    // Like the scan budget, but in time: once the deadline has passed the remaining candidates
+    // are dropped, the matches found until then are kept. The deadline is first checked after
+    // DEADLINE_CHECK_INTERVAL candidates, so even a deadline in the past keeps their matches.
+    pub fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    // Only affects path matches, dotfiles are still found by name. This is independent of the
+    // hidden attribute, which the loaders may or may not set for dotfiles.
+    pub fn with_exclude_dot_components(mut self, exclude_dot_components: bool) -> Self {
+        self.exclude_dot_components = exclude_dot_components;
+        self
+    }
+
+    // Elements without a size, like files exported without sizes or folders with raw folder
+    // sizes, never match size: by default, not even size:0 or size:<10. As zero they match
+    // size:0 and size:<10 but still not size:>0. The root is never matched either way.
+    pub fn with_none_sizes_as_zero(mut self, none_sizes_as_zero: bool) -> Self {
+        self.none_sizes_as_zero = none_sizes_as_zero;
+        self
+    }
+
+    // Whole words in path queries are delimited like in names by default, so ww:path:src
+    // matches \src\ but ww:path:.git only after a word character. With separator boundaries a
+    // separator or the start and end of the path also delimit words, so it matches \.git\ too.
+    pub fn with_separator_word_boundaries(mut self, separator_word_boundaries: bool) -> Self {
+        self.separator_word_boundaries = separator_word_boundaries;
+        self
+    }
+
+    // Negation is the complement, so !size:>1000 also matches elements without a size, like
+    // folders with raw folder sizes, and !dm:2023 elements without a date. Strict negation only
+    // negates within the elements that have the compared field, making !size:>1000 the same
+    // as size:<=1000. Negations of other expressions are unaffected.
+    pub fn with_strict_negation(mut self, strict_negation: bool) -> Self {
+        self.strict_negation = strict_negation;
+        self
+    }
+
+    // The separators of the tree as a regex alternation, \ and / and the extra separators like
+    // ! or ::. None without separator boundaries.
+    fn separator_pattern(&self) -> Option<String> {
+        self.separator_word_boundaries.then(|| {
+            ["\\", "/"]
+                .into_iter()
+                .chain(self.tree.extra_separators().iter().map(String::as_str))
+                .map(regex::escape)
+                .collect::<Vec<_>>()
+                .join("|")
+        })
+    }
+
+    // Narrows down nosubfolders: and parentat: to the contents of the folders with that name.
+    // The index has to be built from the same tree.
+    pub fn with_parent_names(mut self, parent_names: &'a ParentNameIndex) -> Self {
+        self.parent_names = Some(parent_names);
+        self
+    }
+
+    // Only elements marked in `scope` can match, it has an entry for every element of the tree
+    pub fn with_scope(mut self, scope: &'a [bool]) -> Self {
+        self.scope = Some(scope);
+        self
+    }
+
+    // Returns the indices of all elements matching the expression, in ascending order
+    pub fn evaluate(&self, expr: &QueryExpr) -> Vec<usize> {
+        self.evaluate_with_diagnostics(expr).0
+    }
+
+    // Like evaluate, but also reports how much had to be scanned. Only the candidates that are
+    // matched one by one count against the scan budget, exact candidates from the bigram index
+    // are never truncated. Past the budget the remaining candidates are dropped.
+    pub fn evaluate_with_diagnostics(&self, expr: &QueryExpr) -> (Vec<usize>, SearchDiagnostics) {
+        let separators = self.separator_pattern();
+        let compiled = compile(expr, separators.as_deref());
+        // Narrow down using the bigram index where possible, otherwise scan all elements
+        let mut indices = self
+            .candidates(&compiled)
+            .unwrap_or_else(|| (1..self.tree.len()).collect());
+        // Or merges its branches with union, so each element is a candidate at most once
+        debug_assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+        // The root is only the anchor of the tree, never a result. The bigram index has its name.
+        indices.retain(|&index| index != 0 && self.scope.is_none_or(|scope| scope[index]));
+        tracing::Span::current().record("candidate_count", indices.len());
+        let mut diagnostics = SearchDiagnostics {
+            candidate_count: indices.len(),
+            ..Default::default()
+        };
+        if !is_exact(&compiled) {
+            if let Some(budget) = self.scan_budget
+                && indices.len() > budget
+            {
+                indices.truncate(budget);
+                diagnostics.truncated = true;
+            }
+            let mut scanned: usize = 0;
+            indices.retain(|&index| {
+                // Checking the clock for every candidate would slow down the scan
+                if diagnostics.timed_out
+                    || (scanned > 0
+                        && scanned.is_multiple_of(DEADLINE_CHECK_INTERVAL)
+                        && self
+                            .deadline
+                            .is_some_and(|deadline| Instant::now() >= deadline))
+                {
+                    diagnostics.timed_out = true;
+                    return false;
+                }
+                scanned += 1;
+                self.matches(&compiled, &MatchContext::new(self.tree, index))
+            });
+            diagnostics.scanned = scanned;
+        }
+
+        // Like in Everything, dupe: applies to the whole result: only elements sharing the key
+        // with another result are kept. So `ext:jpg dupe:size` finds JPGs of the same size.
+        let mut keys = Vec::new();
+        dupe_keys(expr, &mut keys);
+        for key in keys {
+            self.retain_duplicates(&mut indices, key);
+        }
+        (indices, diagnostics)
+    }
+
+    fn retain_duplicates(&self, indices: &mut Vec<usize>, key: DupeKey) {
+        let dupe_key = |index: usize| {
+            let element = &self.tree.elements[index];
+            let size = if key.size { Some(element.size?) } else { None };
+            let name = key.name.then(|| {
+                MatchContext::new(self.tree, index)
+                    .folded_name()
+                    .to_string()
+            });
+            Some((name, size))
+        };
+        let mut counts: HashMap<(Option<String>, Option<i64>), usize> = HashMap::new();
+        for &index in indices.iter() {
+            // Elements without a size can't be duplicates by size
+            if let Some(key) = dupe_key(index) {
+                *counts.entry(key).or_insert(0) += 1;
+            }
+        }
+        indices.retain(|&index| dupe_key(index).is_some_and(|key| counts[&key] > 1));
+    }
+
+    // Keeps only the indices matching the expression, preserving their order. dupe: keeps the
+    // duplicates among the remaining indices, like among all matches in evaluate.
+    pub fn filter(&self, expr: &QueryExpr, indices: &mut Vec<usize>) {
+        let separators = self.separator_pattern();
+        let compiled = compile(expr, separators.as_deref());
+        indices.retain(|&index| {
+            index != 0
+                && self.scope.is_none_or(|scope| scope[index])
+                && self.matches(&compiled, &MatchContext::new(self.tree, index))
+        });
+        let mut keys = Vec::new();
+        dupe_keys(expr, &mut keys);
+        for key in keys {
+            self.retain_duplicates(indices, key);
+        }
+    }
+
+    // Returns a sorted superset of the matches from the bigram index, or None if all elements have to be checked
+    fn candidates(&self, expr: &CompiledExpr) -> Option<Vec<usize>> {
+        match expr {
+            CompiledExpr::Text(matcher) => matcher.seed.as_ref().map(|seed| self.query_index(seed)),
+            CompiledExpr::And(left, right) => match (self.candidates(left), self.candidates(right))
+            {
+                (Some(left), Some(right)) => Some(intersect(&left, &right)),
+                (Some(candidates), None) | (None, Some(candidates)) => Some(candidates),
+                (None, None) => None,
+            },
+            CompiledExpr::Or(left, right) => {
+                let left = self.candidates(left)?;
+                let right = self.candidates(right)?;
+                Some(union(&left, &right))
+            }
+            CompiledExpr::Parent(folder) => self
+                .parent_names
+                .map(|parent_names| parent_names.descendants(self.tree, &folder.name)),
+            CompiledExpr::ParentDirectOnly(folder) => self
+                .parent_names
+                .map(|parent_names| parent_names.descendants_at(self.tree, &folder.name, 1)),
+            CompiledExpr::ParentAt(level, _, name) => self
+                .parent_names
+                .map(|parent_names| parent_names.descendants_at(self.tree, name, *level)),
+            _ => None,
+        }
+    }
+
+    fn query_index(&self, seed: &str) -> Vec<usize> {
+        let mut chars = seed.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => self.bigram_index.query_char(c),
+            _ => self.bigram_index.query_word(seed),
+        }
+    }
+
+    fn matches_type(&self, index: usize, element_type: Option<ElementType>) -> bool {
+        match element_type {
+            Some(element_type) => self.tree.elements[index].element_type() == element_type,
+            None => true,
+        }
+    }
+
+    // Whether a path match has to be skipped, because the element or a folder it's in starts
+    // with a dot
+    fn skips_path(&self, index: usize, match_path: bool) -> bool {
+        if !match_path || !self.exclude_dot_components {
+            return false;
+        }
+        let mut current = index;
+        while current != 0 {
+            if self.tree.get_filename(current).starts_with('.') {
+                return true;
+            }
+            current = self.tree.elements[current].parent;
+        }
+        false
+    }
+
+    fn matches(&self, expr: &CompiledExpr, ctx: &MatchContext) -> bool {
+        let index = ctx.index;
+        match expr {
+            CompiledExpr::Text(matcher) => {
+                self.matches_type(index, matcher.element_type)
+                    && !self.skips_path(index, matcher.match_path)
+                    && match (&matcher.folded, &matcher.pattern) {
+                        (Some(folded), _) => ctx.folded(matcher.match_path).contains(folded),
+                        (None, Some(pattern)) => {
+                            pattern.is_match(ctx.normalized(matcher.match_path))
+                        }
+                        (None, None) => true,
+                    }
+            }
+            CompiledExpr::Regex(query) => {
+                self.matches_type(
+                    index,
+                    element_type_filter(query.file_only, query.folder_only),
+                ) && !self.skips_path(index, query.match_path)
+                    && query.pattern.is_match(ctx.original(query.match_path))
+            }
+            CompiledExpr::Parent(folder) => {
+                // Any ancestor folder, matched by name or by its full path
+                let mut current = self.tree.elements[index].parent;
+                while current != 0 {
+                    if folder.matches(self.tree, current) {
+                        return true;
+                    }
+                    current = self.tree.elements[current].parent;
+                }
+                false
+            }
+            CompiledExpr::ParentDirectOnly(folder) => {
+                // Only the direct parent, matched by name or by its full path like parent:
+                let parent = self.tree.elements[index].parent;
+                index != 0 && parent != 0 && folder.matches(self.tree, parent)
+            }
+            CompiledExpr::Component(text) => {
+                // The element itself and every folder it's in, each name on its own
+                let mut current = index;
+                while current != 0 {
+                    if self
+                        .tree
+                        .get_filename(current)
+                        .to_lowercase()
+                        .contains(text)
+                    {
+                        return true;
+                    }
+                    current = self.tree.elements[current].parent;
+                }
+                false
+            }
+            CompiledExpr::ParentAt(level, pattern, _) => {
+                // Walk up exactly `level` folders. Elements that are less deeply nested
+                // don't match, the root itself is never matched.
+                let mut current = index;
+                for _ in 0..*level {
+                    if current == 0 {
+                        return false;
+                    }
+                    current = self.tree.elements[current].parent;
+                }
+                current != 0 && pattern.is_match(self.tree.get_filename(current))
+            }
+            CompiledExpr::Ext(matcher) => {
+                !self.tree.elements[index].is_dir()
+                    && extension_suffixes(self.tree.get_filename(index))
+                        .any(|ext| matcher.matches(ext))
+            }
+            CompiledExpr::Function(function) => self.matches_function(function, index),
+            CompiledExpr::And(left, right) => self.matches(left, ctx) && self.matches(right, ctx),
+            CompiledExpr::Or(left, right) => self.matches(left, ctx) || self.matches(right, ctx),
+            CompiledExpr::Not(expr) => {
+                if self.strict_negation
+                    && let CompiledExpr::Function(function) = &**expr
+                    && !self.has_compared_field(function, index)
+                {
+                    return false;
+                }
+                !self.matches(expr, ctx)
+            }
+        }
+    }
+
+    fn element_size(&self, index: usize) -> Option<i64> {
+        let element = &self.tree.elements[index];
+        // Folders created implicitly for a path only have children. The root is never matched.
+        let is_folder = index != 0 && (element.is_dir() || !element.children.is_empty());
+        let size = if is_folder && !self.raw_folder_sizes {
+            self.tree.rollup_size(index).or(element.size)
+        } else {
+            element.size
+        };
+        size.or((self.none_sizes_as_zero && index != 0).then_some(0))
+    }
+
+    // Whether the element has the field a size or date function compares, other functions
+    // don't compare a field that can be missing
+    fn has_compared_field(&self, function: &QueryFunction, index: usize) -> bool {
+        let element = &self.tree.elements[index];
+        match function {
+            QueryFunction::Size(..) | QueryFunction::SizeRange(..) => {
+                self.element_size(index).is_some()
+            }
+            QueryFunction::DateModified(..) => element.date_modified.is_some(),
+            QueryFunction::DateCreated(..) => element.date_created.is_some(),
+            _ => true,
+        }
+    }
+
+    fn matches_function(&self, function: &QueryFunction, index: usize) -> bool {
+        let element = &self.tree.elements[index];
+        match function {
+            QueryFunction::Size(cmp, size) => self
+                .element_size(index)
+                .is_some_and(|element_size| cmp.compare(element_size, *size as i64)),
+            QueryFunction::SizeRange(start, end) => self
+                .element_size(index)
+                .is_some_and(|size| *start as i64 <= size && size <= *end as i64),
+            QueryFunction::TimeOfDay(time_of_day) => {
+                time_of_day.matches(element.date_modified.map(filetime_to_timestamp))
+            }
+            // Trees without inode information (EFU) never match
+            QueryFunction::Hardlinks(cmp, nlink) => self
+                .tree
+                .link_info(index)
+                .is_some_and(|link_info| cmp.compare(link_info.nlink, *nlink)),
+            QueryFunction::Inode(device, inode) => self
+                .tree
+                .link_info(index)
+                .is_some_and(|link_info| link_info.device == *device && link_info.inode == *inode),
+            // The root has an empty name but is never matched
+            QueryFunction::NameLength(cmp, len) => {
+                index != 0 && cmp.compare(self.tree.get_filename(index).chars().count(), *len)
+            }
+            QueryFunction::Mime(pattern) => {
+                !self.tree.elements[index].is_dir()
+                    && mime_type_of(self.tree.get_filename(index))
+                        .is_some_and(|mime| mime_matches(pattern, mime))
+            }
+            QueryFunction::Children(cmp, count) => {
+                index != 0 && cmp.compare(self.tree.elements[index].children.len(), *count)
+            }
+            QueryFunction::HasDigits => self
+                .tree
+                .get_filename(index)
+                .chars()
+                .any(|c| c.is_ascii_digit()),
+            QueryFunction::HasSpaces => self
+                .tree
+                .get_filename(index)
+                .chars()
+                .any(char::is_whitespace),
+            QueryFunction::HasUnicode => !self.tree.get_filename(index).is_ascii(),
+            QueryFunction::NumRange(range) => {
+                index != 0 && range.matches(self.tree.get_filename(index))
+            }
+            QueryFunction::CreatedVsModified(cmp) => {
+                match (element.date_created, element.date_modified) {
+                    (Some(created), Some(modified)) => cmp.compare(created, modified),
+                    _ => false,
+                }
+            }
+            QueryFunction::DateModified(cmp, date) => {
+                date.matches(cmp, element.date_modified.map(filetime_to_timestamp))
+            }
+            QueryFunction::DateCreated(cmp, date) => {
+                date.matches(cmp, element.date_created.map(filetime_to_timestamp))
+            }
+            QueryFunction::Parent(..)
+            | QueryFunction::ParentDirectOnly(..)
+            | QueryFunction::Component(_)
+            | QueryFunction::ParentAt(..)
+            | QueryFunction::Ext(_) => {
+                unreachable!("Parent and ext are compiled into patterns")
+            }
+            // Matched on the whole result in evaluate
+            QueryFunction::Dupe(_) => true,
+            // Folders without children and files of size 0
+            QueryFunction::Empty => {
+                if element.is_dir() {
+                    element.children.is_empty()
+                } else {
+                    element.size == Some(0)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attributes::Attributes;
+    use crate::query::query_parser::parse_query;
+
+    fn evaluate(tree: &FileTree, query: &str) -> Vec<usize> {
+        let bigram_index = BigramIndex::new(tree);
+        QueryEvaluator::new(tree, &bigram_index).evaluate(&parse_query(query).unwrap())
+    }
+
+    #[test]
+    fn test_file_folder_filter() {
+        let mut tree = FileTree::with_capacity(5);
+        let reports_dir = tree
+            .add_or_update_recursive("docs/reports", None, None, None, Attributes::DIRECTORY)
+            .unwrap();
+        let report_file = tree
+            .add_or_update_recursive(
+                "docs/report.txt",
+                Some(100),
+                None,
+                None,
+                Attributes::ARCHIVE,
+            )
+            .unwrap();
+        tree.add_or_update_recursive(
+            "docs/reports/q1.txt",
+            Some(200),
+            None,
+            None,
+            Attributes::ARCHIVE,
+        );
+
+        assert_eq!(evaluate(&tree, "report"), vec![reports_dir, report_file]);
+        assert_eq!(evaluate(&tree, "file:report"), vec![report_file]);
+        assert_eq!(evaluate(&tree, "folder:report"), vec![reports_dir]);
+        assert_eq!(evaluate(&tree, "folder:regex:^rep"), vec![reports_dir]);
+        // Without text they select all files or folders, there's no bigram seed to narrow down
+        let q1 = tree.len() - 1;
+        assert_eq!(evaluate(&tree, "folder:"), vec![reports_dir]);
+        assert_eq!(evaluate(&tree, "file: ext:txt"), vec![report_file, q1]);
+        assert_eq!(evaluate(&tree, "size:>150 file:"), vec![q1]);
+        assert_eq!(
+            evaluate(&tree, "folder: | file: ext:txt"),
+            vec![reports_dir, report_file, q1]
+        );
+    }
+
+    #[test]
+    fn test_text_modifiers() {
+        let mut tree = FileTree::with_capacity(5);
+        let upper = tree
+            .add_or_update_recursive("Notes.txt", Some(100), None, None, Attributes::NONE)
+            .unwrap();
+        let lower = tree
+            .add_or_update_recursive("my notes.md", Some(100), None, None, Attributes::NONE)
+            .unwrap();
+        let joined = tree
+            .add_or_update_recursive("footnotes.txt", Some(100), None, None, Attributes::NONE)
+            .unwrap();
+
+        assert_eq!(evaluate(&tree, "notes"), vec![upper, lower, joined]);
+        assert_eq!(evaluate(&tree, "case:Notes"), vec![upper]);
+        assert_eq!(evaluate(&tree, "ww:notes"), vec![upper, lower]);
+        assert_eq!(evaluate(&tree, "wfn:notes.txt"), vec![upper]);
+        assert_eq!(evaluate(&tree, "notes !txt"), vec![lower]);
+        assert_eq!(evaluate(&tree, "wfn:notes.txt | .md"), vec![upper, lower]);
+        // A leading = is the same as wfn:
+        assert_eq!(evaluate(&tree, "=notes.txt"), vec![upper]);
+        assert_eq!(evaluate(&tree, "notes.txt"), vec![upper, joined]);
+    }
+
+    #[test]
+    fn test_functions() {
+        let mut tree = FileTree::with_capacity(5);
+        let small = tree
+            .add_or_update_recursive("src/small.rs", Some(10), None, None, Attributes::NONE)
+            .unwrap();
+        let large = tree
+            .add_or_update_recursive("src/app/large.rs", Some(5000), None, None, Attributes::NONE)
+            .unwrap();
+        let song = tree
+            .add_or_update_recursive("music/song.mp3", Some(3000), None, None, Attributes::NONE)
+            .unwrap();
+
+        assert_eq!(evaluate(&tree, "size:>1000"), vec![large, song]);
+        assert_eq!(evaluate(&tree, "ext:rs"), vec![small, large]);
+        assert_eq!(evaluate(&tree, "parent:src ext:rs"), vec![small, large]);
+        assert_eq!(evaluate(&tree, "parent:src/app"), vec![large]);
+        assert_eq!(evaluate(&tree, "l parent:src"), vec![small, large]);
+    }
+
+    #[test]
+    fn test_ext_wildcards() {
+        let mut tree = FileTree::with_capacity(6);
+        let jpg = tree.add_test_file("a.jpg");
+        let jpeg = tree.add_test_file("b.JPEG");
+        let _txt = tree.add_test_file("c.txt");
+        let ajp = tree.add_test_file("d.ajp");
+        let tif = tree.add_test_file("e.tif");
+
+        assert_eq!(evaluate(&tree, "ext:jp*"), vec![jpg, jpeg]);
+        assert_eq!(evaluate(&tree, "ext:t?f;*jp"), vec![ajp, tif]);
+        assert_eq!(evaluate(&tree, "ext:jp?"), vec![jpg]);
+        // Without wildcards the extension has to match exactly
+        assert!(evaluate(&tree, "ext:jp").is_empty());
+        assert!(evaluate(&tree, "ext:j.g").is_empty());
+    }
+
+    #[test]
+    fn test_ext_groups() {
+        let mut tree = FileTree::with_capacity(8);
+        let jpg = tree.add_test_file("photo.jpg");
+        let png = tree.add_test_file("Screenshot.PNG");
+        let gif = tree.add_test_file("funny.gif");
+        let mp4 = tree.add_test_file("clip.mp4");
+        let rs = tree.add_test_file("main.rs");
+        let image = tree.add_test_file("disk.image");
+        let tar_gz = tree.add_test_file("backup.tar.gz");
+
+        assert_eq!(evaluate(&tree, "ext:image"), vec![jpg, png, gif, image]);
+        assert_eq!(evaluate(&tree, "ext:Video;code"), vec![mp4, rs]);
+        assert_eq!(evaluate(&tree, "ext:archive"), vec![tar_gz]);
+        // Everything except images, every name here has a dot
+        assert_eq!(evaluate(&tree, ". !ext:image"), vec![mp4, rs, tar_gz]);
+        assert_eq!(
+            evaluate(&tree, ". !ext:jpg png gif"),
+            vec![mp4, rs, image, tar_gz]
+        );
+    }
+
+    #[test]
+    fn test_compound_extensions_and_dotfiles() {
+        let mut tree = FileTree::with_capacity(6);
+        let tar_gz = tree.add_test_file("archive.tar.gz");
+        let gz = tree.add_test_file("log.gz");
+        let gitignore = tree.add_test_file(".gitignore");
+        let dot_json = tree.add_test_file(".config.json");
+        let trailing = tree.add_test_file("notes.txt.");
+
+        assert_eq!(evaluate(&tree, "ext:gz"), vec![tar_gz, gz]);
+        assert_eq!(evaluate(&tree, "ext:tar.gz"), vec![tar_gz]);
+        assert_eq!(evaluate(&tree, "ext:TAR.GZ"), vec![tar_gz]);
+        assert_eq!(evaluate(&tree, "ext:tar.*"), vec![tar_gz]);
+        assert!(evaluate(&tree, "ext:ta*").is_empty());
+        // The leading dot is part of the name
+        assert!(evaluate(&tree, "ext:gitignore").is_empty());
+        assert_eq!(evaluate(&tree, "ext:json"), vec![dot_json]);
+        assert!(evaluate(&tree, "ext:config.json").is_empty());
+        // A trailing dot leaves the name without an extension
+        assert!(evaluate(&tree, "ext:txt").is_empty());
+        // Only files without an extension, the root is never a result
+        assert_eq!(evaluate(&tree, "!ext:*"), vec![gitignore, trailing]);
+    }
+
+    #[test]
+    fn test_parent_at() {
+        let mut tree = FileTree::with_capacity(8);
+        let main = tree.add_test_file("repo/src/bin/main.rs");
+        let lib = tree.add_test_file("repo/src/lib.rs");
+        let test = tree.add_test_file("repo/tests/src/test.rs");
+        let path = |path| tree.find_by_path(path).unwrap();
+        let (src, bin) = (path("repo/src"), path("repo/src/bin"));
+        let (tests, tests_src) = (path("repo/tests"), path("repo/tests/src"));
+
+        assert_eq!(evaluate(&tree, "parentat:1:src"), vec![bin, lib, test]);
+        assert_eq!(evaluate(&tree, "parentat:2:src"), vec![main]);
+        assert_eq!(
+            evaluate(&tree, "parentat:2:repo"),
+            vec![bin, lib, tests_src]
+        );
+        assert_eq!(evaluate(&tree, "parentat:3:REPO"), vec![main, test]);
+        assert_eq!(evaluate(&tree, "parentat:1:repo"), vec![src, tests]);
+        // Elements that are not nested deeply enough never match, also not the root
+        assert!(evaluate(&tree, "parentat:4:repo").is_empty());
+        assert!(evaluate(&tree, "parentat:5:root").is_empty());
+    }
+
+    #[test]
+    fn test_component() {
+        let mut tree = FileTree::with_capacity(8);
+        let spanning = tree.add_test_file("C:\\Users\\Share\\notes.txt");
+        let shared = tree.add_test_file("D:\\Shares\\report.txt");
+        let name = tree.add_test_file("D:\\docs\\shared notes.txt");
+        let share = tree.get_elements()[spanning].parent;
+        let users = tree.get_elements()[share].parent;
+
+        // users\share only matches across the separator
+        assert_eq!(evaluate(&tree, "path:rs\\share ext:txt"), vec![spanning]);
+        assert!(evaluate(&tree, "component:rs\\share").is_empty());
+        assert!(evaluate(&tree, "component:rsshare").is_empty());
+        // Any single name, case-insensitive
+        assert_eq!(
+            evaluate(&tree, "component:SHARE ext:txt"),
+            vec![spanning, shared, name]
+        );
+        // Everything inside a matching folder matches too, like with path:
+        assert_eq!(
+            evaluate(&tree, "component:users"),
+            vec![users, share, spanning]
+        );
+        assert_eq!(evaluate(&tree, "component:\"d notes\""), vec![name]);
+    }
+
+    #[test]
+    fn test_nosubfolders() {
+        let mut tree = FileTree::with_capacity(8);
+        let child = tree.add_test_file("repo/src/lib.rs");
+        let grandchild = tree.add_test_file("repo/src/bin/main.rs");
+        let bin = tree.get_elements()[grandchild].parent;
+
+        // Direct children only, while parent: includes the subfolders
+        assert_eq!(evaluate(&tree, "nosubfolders:src"), vec![child, bin]);
+        assert_eq!(evaluate(&tree, "nosubfolders:SRC ext:rs"), vec![child]);
+        assert_eq!(
+            evaluate(&tree, "parent:src ext:rs"),
+            vec![child, grandchild]
+        );
+        assert_eq!(
+            evaluate(&tree, "nosubfolders:repo/src/bin"),
+            vec![grandchild]
+        );
+        assert!(evaluate(&tree, "nosubfolders:repo ext:rs").is_empty());
+    }
+
+    #[test]
+    fn test_case_sensitive_folders() {
+        let mut tree = FileTree::with_capacity(8);
+        let upper = tree.add_test_file("repo/Src/lib.rs");
+        let lower = tree.add_test_file("repo/src/main.rs");
+
+        // Folder names ignore case by default
+        assert_eq!(evaluate(&tree, "parent:Src ext:rs"), vec![upper, lower]);
+        assert_eq!(evaluate(&tree, "nosubfolders:SRC"), vec![upper, lower]);
+        assert_eq!(evaluate(&tree, "parentat:1:src"), vec![upper, lower]);
+        // case: applies to the folder name, also in paths
+        assert_eq!(evaluate(&tree, "case:parent:Src ext:rs"), vec![upper]);
+        assert_eq!(evaluate(&tree, "case:infolder:src ext:rs"), vec![lower]);
+        assert_eq!(evaluate(&tree, "case:parent:repo/Src"), vec![upper]);
+        assert_eq!(evaluate(&tree, "case:nosubfolders:Src"), vec![upper]);
+        assert_eq!(evaluate(&tree, "case:parentat:1:src"), vec![lower]);
+        assert!(evaluate(&tree, "case:parent:SRC").is_empty());
+        assert_eq!(
+            evaluate(&tree, "nocase:parent:SRC ext:rs"),
+            vec![upper, lower]
+        );
+    }
+
+    #[test]
+    fn test_parent_names_match_walk() {
+        let mut tree = FileTree::with_capacity(16);
+        for path in [
+            "repo/src/bin/main.rs",
+            "repo/src/lib.rs",
+            "repo/tests/src/test.rs",
+            "other/SRC/mod.rs",
+            "other/src.rs",
+        ] {
+            tree.add_test_file(path);
+        }
+        let bigram_index = BigramIndex::new(&tree);
+        let parent_names = ParentNameIndex::new(&tree);
+        let walk = QueryEvaluator::new(&tree, &bigram_index);
+        let indexed = QueryEvaluator::new(&tree, &bigram_index).with_parent_names(&parent_names);
+
+        for query in [
+            "nosubfolders:src",
+            "nosubfolders:repo/src",
+            "nosubfolders:\\other\\src\\",
+            "nosubfolders:src ext:rs",
+            "nosubfolders:src | nosubfolders:bin",
+            "!nosubfolders:src",
+            "nosubfolders:missing",
+            "parent:src",
+            "parent:repo/src",
+            "parent:\\repo\\",
+            "parent:src !parent:tests",
+            "case:parent:SRC",
+            "parent:missing",
+            "parentat:1:src",
+            "parentat:2:repo",
+            "parentat:3:REPO",
+            "parentat:2:src | lib",
+            "parentat:9:repo",
+            "case:nosubfolders:SRC",
+            "case:parentat:1:src",
+        ] {
+            let expr = parse_query(query).unwrap();
+            assert_eq!(indexed.evaluate(&expr), walk.evaluate(&expr), "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_dupe_and_empty() {
+        let mut tree = FileTree::with_capacity(10);
+        let a = tree
+            .add_or_update_recursive("photos/a.jpg", Some(100), None, None, Attributes::NONE)
+            .unwrap();
+        let b = tree
+            .add_or_update_recursive("photos/b.jpg", Some(100), None, None, Attributes::NONE)
+            .unwrap();
+        let c = tree
+            .add_or_update_recursive("photos/c.jpg", Some(200), None, None, Attributes::NONE)
+            .unwrap();
+        let copy = tree
+            .add_or_update_recursive("backup/A.JPG", Some(200), None, None, Attributes::NONE)
+            .unwrap();
+        tree.add_or_update_recursive("docs/a.txt", Some(100), None, None, Attributes::NONE);
+        let empty_file = tree
+            .add_or_update_recursive("docs/empty.txt", Some(0), None, None, Attributes::NONE)
+            .unwrap();
+        let empty_dir = tree
+            .add_or_update_recursive("docs/old", None, None, None, Attributes::DIRECTORY)
+            .unwrap();
+
+        // Duplicates only among the other results, a.txt has the same size but isn't a JPG
+        assert_eq!(evaluate(&tree, "ext:jpg dupe:size"), vec![a, b, c, copy]);
+        assert_eq!(evaluate(&tree, "parent:photos dupe:size"), vec![a, b]);
+        assert_eq!(evaluate(&tree, "ext:jpg dupe:name"), vec![a, copy]);
+        assert_eq!(
+            evaluate(&tree, "ext:jpg dupe:name;size"),
+            Vec::<usize>::new()
+        );
+        assert_eq!(evaluate(&tree, "jpg dupe:size;name"), Vec::<usize>::new());
+        assert_eq!(evaluate(&tree, "a dupe:"), vec![a, copy]);
+
+        assert_eq!(evaluate(&tree, "empty:"), vec![empty_file, empty_dir]);
+        assert_eq!(evaluate(&tree, "empty: ext:txt"), vec![empty_file]);
+    }
+
+    #[test]
+    fn test_or_matches_once() {
+        let mut tree = FileTree::with_capacity(8);
+        let both = tree.add_test_file("C:\\reports\\report.txt");
+        let name = tree.add_test_file("D:\\report.txt");
+        let path = tree.add_test_file("C:\\reports\\notes.txt");
+
+        // The path term needs a scan, the name term comes from the bigram index
+        assert_eq!(
+            evaluate(&tree, "path:reports\\ ext:txt | report.txt"),
+            vec![both, name, path]
+        );
+        assert_eq!(
+            evaluate(&tree, "report.txt | nosubfolders:reports"),
+            vec![both, name, path]
+        );
+        // Both index-backed
+        assert_eq!(evaluate(&tree, "report | rep"), evaluate(&tree, "rep"));
+    }
+
+    #[test]
+    fn test_path_separators() {
+        let mut tree = FileTree::with_capacity(8);
+        let efu = tree.add_test_file("C:\\school\\homework\\notes\\week 1.txt");
+        // Paths loaded with / are stored with \ like all others
+        let slashes = tree.add_test_file("/data/homework/notes/week 2.txt");
+
+        assert_eq!(
+            evaluate(&tree, "path:homework/notes/week"),
+            vec![efu, slashes]
+        );
+        assert_eq!(
+            evaluate(&tree, "path:homework\\notes/week"),
+            vec![efu, slashes]
+        );
+        assert_eq!(evaluate(&tree, "path:school/homework/notes/w"), vec![efu]);
+        assert_eq!(
+            evaluate(&tree, "path:data\\homework\\notes\\w"),
+            vec![slashes]
+        );
+        // Case sensitive and whole word matches use the pattern instead of the folded path
+        assert_eq!(
+            evaluate(&tree, "case:path:homework/notes/week"),
+            vec![efu, slashes]
+        );
+        assert_eq!(
+            evaluate(&tree, "ww:path:Homework/Notes/Week"),
+            vec![efu, slashes]
+        );
+        assert!(evaluate(&tree, "path:homework/week").is_empty());
+    }
+
+    #[test]
+    fn test_strict_negation() {
+        let mut tree = FileTree::with_capacity(8);
+        let small = tree
+            .add_or_update_recursive("small.txt", Some(500), None, None, Attributes::NONE)
+            .unwrap();
+        let large = tree
+            .add_or_update_recursive("large.txt", Some(2000), None, None, Attributes::NONE)
+            .unwrap();
+        let unknown = tree
+            .add_or_update_recursive("unknown.txt", None, None, None, Attributes::NONE)
+            .unwrap();
+        let bigram_index = BigramIndex::new(&tree);
+        let complement = QueryEvaluator::new(&tree, &bigram_index);
+        let strict = QueryEvaluator::new(&tree, &bigram_index).with_strict_negation(true);
+        let evaluate = |evaluator: &QueryEvaluator, query: &str| {
+            evaluator.evaluate(&parse_query(query).unwrap())
+        };
+
+        // Without directories only the file without a size differs
+        assert_eq!(
+            evaluate(&complement, "!size:>1000 ext:txt"),
+            vec![small, unknown]
+        );
+        assert_eq!(evaluate(&strict, "!size:>1000 ext:txt"), vec![small]);
+        assert_eq!(
+            evaluate(&strict, "!size:>1000"),
+            evaluate(&strict, "size:<=1000")
+        );
+
+        // Folders without a size are in the complement, but not in the strict negation
+        let nested = tree
+            .add_or_update_recursive("docs/notes.txt", Some(10), None, None, Attributes::NONE)
+            .unwrap();
+        let docs = tree.find_by_path("docs").unwrap();
+        let bigram_index = BigramIndex::new(&tree);
+        let complement = QueryEvaluator::new(&tree, &bigram_index).with_raw_folder_sizes(true);
+        let strict = QueryEvaluator::new(&tree, &bigram_index)
+            .with_raw_folder_sizes(true)
+            .with_strict_negation(true);
+        let complement_matches = evaluate(&complement, "!size:>1000");
+        assert!(complement_matches.contains(&docs) && complement_matches.contains(&unknown));
+        assert!(!complement_matches.contains(&large));
+        assert_eq!(evaluate(&strict, "!size:>1000"), vec![small, nested]);
+        // The same for dates, and other negations still include everything else
+        assert_eq!(evaluate(&strict, "!dm:2023"), Vec::<usize>::new());
+        assert!(evaluate(&strict, "!ext:rs").contains(&docs));
+    }
+
+    #[test]
+    fn test_separator_word_boundaries() {
+        let mut tree = FileTree::with_capacity(8);
+        let lib = tree.add_test_file("repo/src/lib.rs");
+        tree.add_test_file("repo/srcfile.rs");
+        let config = tree.add_test_file("home/.config/app.toml");
+        let src = tree.find_by_path("repo/src").unwrap();
+        let dot_config = tree.find_by_path("home/.config").unwrap();
+        let bigram_index = BigramIndex::new(&tree);
+        let evaluator = QueryEvaluator::new(&tree, &bigram_index);
+        let separators =
+            QueryEvaluator::new(&tree, &bigram_index).with_separator_word_boundaries(true);
+        let evaluate = |evaluator: &QueryEvaluator, query: &str| {
+            evaluator.evaluate(&parse_query(query).unwrap())
+        };
+
+        // The src folder and its contents, but not srcfile.rs
+        assert_eq!(evaluate(&evaluator, "ww:path:src"), vec![src, lib]);
+        assert_eq!(evaluate(&separators, "ww:path:src"), vec![src, lib]);
+        // Text starting with a non-word character needs the separator as boundary
+        assert!(evaluate(&evaluator, "ww:path:.config").is_empty());
+        assert_eq!(
+            evaluate(&separators, "ww:path:.config"),
+            vec![dot_config, config]
+        );
+        assert!(evaluate(&separators, "ww:path:.conf").is_empty());
+        // Names are matched like before
+        assert!(evaluate(&separators, "ww:.config").is_empty());
+
+        // The extra separators of the tree end words too. A name added before they were set
+        // keeps them.
+        let mut tree = FileTree::with_capacity(8);
+        let hidden = tree.add_test_file("archive.zip!.hidden");
+        tree.set_extra_separators(&["!", "::"]);
+        let bigram_index = BigramIndex::new(&tree);
+        let evaluator = QueryEvaluator::new(&tree, &bigram_index);
+        let separators =
+            QueryEvaluator::new(&tree, &bigram_index).with_separator_word_boundaries(true);
+        assert!(evaluate(&evaluator, "ww:path:.hidden").is_empty());
+        assert_eq!(evaluate(&separators, "ww:path:.hidden"), vec![hidden]);
+    }
+
+    #[test]
+    fn test_exclude_dot_components() {
+        let mut tree = FileTree::with_capacity(8);
+        let plain = tree.add_test_file("project\\src\\config.rs");
+        let in_git = tree.add_test_file("project\\.git\\config");
+        let dotfile = tree.add_test_file("project\\.config");
+        let bigram_index = BigramIndex::new(&tree);
+        let evaluator = QueryEvaluator::new(&tree, &bigram_index);
+        let without_dots =
+            QueryEvaluator::new(&tree, &bigram_index).with_exclude_dot_components(true);
+        let evaluate = |evaluator: &QueryEvaluator, query: &str| {
+            evaluator.evaluate(&parse_query(query).unwrap())
+        };
+
+        assert_eq!(
+            evaluate(&evaluator, "path:project\\ file:config"),
+            vec![plain, in_git, dotfile]
+        );
+        assert_eq!(
+            evaluate(&without_dots, "path:project\\ file:config"),
+            vec![plain]
+        );
+        assert!(evaluate(&without_dots, "path:regex:config$").is_empty());
+        assert_eq!(evaluate(&without_dots, "path:regex:config"), vec![plain]);
+        // Names are still matched, only path matches are skipped
+        assert_eq!(
+            evaluate(&without_dots, "file:config"),
+            vec![plain, in_git, dotfile]
+        );
+    }
+
+    #[test]
+    fn test_match_context() {
+        let mut tree = FileTree::with_capacity(4);
+        let file = tree.add_test_file("Docs\\ReadMe.TXT");
+        let ctx = MatchContext::new(&tree, file);
+        assert_eq!(ctx.name(), "ReadMe.TXT");
+        assert_eq!(ctx.path(), "Docs\\ReadMe.TXT");
+        assert_eq!(ctx.folded_name(), "readme.txt");
+        assert_eq!(ctx.folded_path(), "docs\\readme.txt");
+        // Matching doesn't change what is stored for display
+        assert_eq!(evaluate(&tree, "README"), vec![file]);
+        assert_eq!(evaluate(&tree, "path:docs\\readme"), vec![file]);
+        assert_eq!(evaluate(&tree, "case:ReadMe"), vec![file]);
+        assert!(evaluate(&tree, "case:readme").is_empty());
+        assert_eq!(tree.get_filename(file), "ReadMe.TXT");
+    }
+
+    #[test]
+    fn test_range_boundaries() {
+        let mut tree = FileTree::with_capacity(5);
+        let sizes: Vec<usize> = [999, 1000, 1500, 2000, 2001]
+            .iter()
+            .map(|&size| {
+                let name = format!("file{}.bin", size);
+                tree.add_or_update_recursive(&name, Some(size), None, None, Attributes::NONE)
+                    .unwrap()
+            })
+            .collect();
+
+        assert_eq!(evaluate(&tree, "size:1000..2000"), sizes[1..4]);
+        assert_eq!(evaluate(&tree, "size:[1000..2000]"), sizes[1..4]);
+        assert_eq!(evaluate(&tree, "size:[1000..2000)"), sizes[1..3]);
+        assert_eq!(evaluate(&tree, "size:(1000..2000]"), sizes[2..4]);
+        assert_eq!(evaluate(&tree, "size:(1000..2000)"), sizes[2..3]);
+        assert!(evaluate(&tree, "size:[1000..1000)").is_empty());
+    }
+
+    #[test]
+    fn test_created_vs_modified() {
+        let mut tree = FileTree::with_capacity(5);
+        let mut add = |name: &str, date_modified, date_created| {
+            tree.add_or_update_recursive(
+                name,
+                Some(1),
+                date_modified,
+                date_created,
+                Attributes::NONE,
+            )
+            .unwrap()
+        };
+        let copied = add("copied.txt", Some(1_000), Some(2_000));
+        let edited = add("edited.txt", Some(3_000), Some(2_000));
+        let untouched = add("untouched.txt", Some(2_000), Some(2_000));
+        add("no_created.txt", Some(1_000), None);
+        add("undated.txt", None, None);
+
+        assert_eq!(evaluate(&tree, "clockskew:"), vec![copied]);
+        assert_eq!(evaluate(&tree, "cmdate:created>modified"), vec![copied]);
+        assert_eq!(evaluate(&tree, "cmdate:modified>created"), vec![edited]);
+        assert_eq!(evaluate(&tree, "cmdate:created=modified"), vec![untouched]);
+        assert_eq!(
+            evaluate(&tree, "cmdate:created<=modified"),
+            vec![edited, untouched]
+        );
+    }
+
+    #[test]
+    fn test_open_date_ranges() {
+        use chrono::{Local, NaiveDate, TimeZone};
+
+        // FILETIME of local noon on the day
+        let filetime = |year: i32, month: u32, day: u32| {
+            let datetime = NaiveDate::from_ymd_opt(year, month, day)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap();
+            let timestamp = Local
+                .from_local_datetime(&datetime)
+                .earliest()
+                .unwrap()
+                .timestamp();
+            (timestamp + 11_644_473_600) * 10_000_000
+        };
+        let mut tree = FileTree::with_capacity(4);
+        let mut add = |name: &str, date_created| {
+            tree.add_or_update_recursive(name, Some(1), None, Some(date_created), Attributes::NONE)
+                .unwrap()
+        };
+        let old = add("old.txt", filetime(2022, 12, 31));
+        let new_year = add("new_year.txt", filetime(2023, 1, 1));
+        let new = add("new.txt", filetime(2024, 6, 1));
+
+        assert_eq!(evaluate(&tree, "dc:2023-01-01.."), vec![new_year, new]);
+        assert_eq!(evaluate(&tree, "dc:(2023-01-01.."), vec![new]);
+        assert_eq!(evaluate(&tree, "dc:..2023-01-01"), vec![old, new_year]);
+        assert_eq!(evaluate(&tree, "dc:..2023-01-01)"), vec![old]);
+    }
+
+    #[test]
+    fn test_time_of_day() {
+        use chrono::{Local, NaiveDate, TimeZone};
+
+        // FILETIME of a local time, on different days so only the time of day counts
+        let filetime = |day: u32, hour: u32, minute: u32| {
+            let datetime = NaiveDate::from_ymd_opt(2024, 3, day)
+                .unwrap()
+                .and_hms_opt(hour, minute, 0)
+                .unwrap();
+            let timestamp = Local
+                .from_local_datetime(&datetime)
+                .earliest()
+                .unwrap()
+                .timestamp();
+            (timestamp + 11_644_473_600) * 10_000_000
+        };
+        let mut tree = FileTree::with_capacity(6);
+        let mut add = |name: &str, date_modified| {
+            tree.add_or_update_recursive(name, Some(1), Some(date_modified), None, Attributes::NONE)
+                .unwrap()
+        };
+        let early = add("early.txt", filetime(4, 1, 30));
+        let morning = add("morning.txt", filetime(5, 9, 0));
+        let evening = add("evening.txt", filetime(6, 17, 59));
+        let late = add("late.txt", filetime(7, 23, 15));
+        tree.add_test_file("undated.txt");
+
+        assert_eq!(
+            evaluate(&tree, "timeofday:9:00..17:59"),
+            vec![morning, evening]
+        );
+        assert_eq!(evaluate(&tree, "timeofday:[9..17)"), vec![morning]);
+        assert_eq!(evaluate(&tree, "timeofday:22:00..02:00"), vec![early, late]);
+        assert_eq!(evaluate(&tree, "timeofday:9am"), vec![morning]);
+        assert_eq!(evaluate(&tree, "!timeofday:9..18 .txt").len(), 3);
+    }
+
+    #[test]
+    fn test_hardlinks_without_link_info() {
+        // EFU trees have no inode information, so these never match
+        let tree = FileTree::generate_synthetic(1_000, 2);
+        assert!(evaluate(&tree, "hardlinks:").is_empty());
+        assert!(evaluate(&tree, "hardlinks:>=0").is_empty());
+        assert!(evaluate(&tree, "inode:0:0").is_empty());
+        // Everything but the root
+        assert_eq!(evaluate(&tree, "!hardlinks:").len(), tree.len() - 1);
+    }
+
+    #[test]
+    fn test_character_classes() {
+        let mut tree = FileTree::with_capacity(4);
+        let plain = tree.add_test_file("notes.txt");
+        let digits = tree.add_test_file("IMG_2041.jpg");
+        let spaces = tree.add_test_file("my notes.txt");
+        let unicode = tree.add_test_file("café.txt");
+        let all = tree.add_test_file("résumé 2024.pdf");
+
+        assert_eq!(evaluate(&tree, "hasdigits:"), vec![digits, all]);
+        assert_eq!(evaluate(&tree, "hasspaces:"), vec![spaces, all]);
+        assert_eq!(evaluate(&tree, "hasunicode:"), vec![unicode, all]);
+        assert_eq!(evaluate(&tree, "notes !hasspaces:"), vec![plain]);
+    }
+
+    #[test]
+    fn test_numrange() {
+        let mut tree = FileTree::with_capacity(10);
+        let first = tree.add_test_file("shoot/IMG_0001.jpg");
+        let raw = tree.add_test_file("shoot/img_0050.CR2");
+        let last = tree.add_test_file("shoot/IMG_0100.jpg");
+        tree.add_test_file("shoot/IMG_0101.jpg");
+        tree.add_test_file("shoot/IMG_0000.jpg");
+        tree.add_test_file("shoot/IMG_50.jpg");
+        tree.add_test_file("shoot/DSC_0050.jpg");
+        tree.add_test_file("shoot/old IMG_0050.jpg");
+        let take = tree.add_test_file("audio/take 7.wav");
+        tree.add_test_file("audio/take 7.mp3");
+
+        assert_eq!(
+            evaluate(&tree, "numrange:IMG_0001..IMG_0100"),
+            vec![first, raw, last]
+        );
+        assert_eq!(
+            evaluate(&tree, "numrange:IMG_0001..0100 ext:jpg"),
+            vec![first, last]
+        );
+        assert_eq!(
+            evaluate(&tree, "numrange:\"take 1.wav..take 20.wav\""),
+            vec![take]
+        ); // Equal ends match exactly that number
+        assert_eq!(evaluate(&tree, "numrange:IMG_0100..IMG_0100"), vec![last]);
+        assert_eq!(evaluate(&tree, "numrange:\"take 7.wav..7\""), vec![take]);
+    }
+
+    #[test]
+    fn test_name_length() {
+        let mut tree = FileTree::with_capacity(4);
+        let four = tree.add_test_file("abcd");
+        let five = tree.add_test_file("a.txt");
+        // Five characters but six bytes
+        let accented = tree.add_test_file("é.txt");
+        let six = tree.add_test_file("ab.txt");
+
+        assert_eq!(evaluate(&tree, "namelen:5"), vec![five, accented]);
+        assert_eq!(evaluate(&tree, "namelen:<5"), vec![four]);
+        assert_eq!(evaluate(&tree, "namelen:<=5"), vec![four, five, accented]);
+        assert_eq!(evaluate(&tree, "namelen:>5"), vec![six]);
+        assert_eq!(evaluate(&tree, "namelen:>=6"), vec![six]);
+    }
+
+    #[test]
+    fn test_mime() {
+        let mut tree = FileTree::with_capacity(8);
+        let png = tree.add_test_file("photo.png");
+        let jpg = tree.add_test_file("scan.JPG");
+        let pdf = tree.add_test_file("scan.pdf");
+        tree.add_test_file("notes.unknown");
+        tree.add_or_update_recursive("folder.png", None, None, None, Attributes::DIRECTORY);
+
+        assert_eq!(evaluate(&tree, "mime:image/png"), vec![png]);
+        assert_eq!(evaluate(&tree, "mime:image/*"), vec![png, jpg]);
+        assert_eq!(evaluate(&tree, "mime:IMAGE/JPEG"), vec![jpg]);
+        assert_eq!(evaluate(&tree, "mime:*"), vec![png, jpg, pdf]);
+        assert!(evaluate(&tree, "mime:image").is_empty());
+    }
+
+    #[test]
+    fn test_children() {
+        let mut tree = FileTree::with_capacity(32);
+        let wide = tree
+            .add_or_update_recursive("wide", None, None, None, Attributes::DIRECTORY)
+            .unwrap();
+        let narrow = tree
+            .add_or_update_recursive("narrow", None, None, None, Attributes::DIRECTORY)
+            .unwrap();
+        for i in 0..20 {
+            let path = format!("wide\\file {}.txt", i);
+            tree.add_test_file(&path);
+        }
+        tree.add_test_file("narrow\\a.txt");
+
+        assert_eq!(evaluate(&tree, "children:>10"), vec![wide]);
+        assert_eq!(evaluate(&tree, "children:20"), vec![wide]);
+        assert!(evaluate(&tree, "children:>20").is_empty());
+        assert_eq!(evaluate(&tree, "children:<=1 folder:"), vec![narrow]);
+        // Files have no children
+        assert_eq!(evaluate(&tree, "children:0 file:").len(), 21);
+    }
+
+    #[test]
+    fn test_approximate_size() {
+        let mut tree = FileTree::with_capacity(5);
+        let sizes: Vec<usize> = [3_774_873, 3_774_874, 4_194_304, 4_613_734, 4_613_735]
+            .iter()
+            .map(|&size| {
+                let name = format!("file{}.bin", size);
+                tree.add_or_update_recursive(&name, Some(size), None, None, Attributes::NONE)
+                    .unwrap()
+            })
+            .collect();
+        // The band boundaries are included
+        assert_eq!(evaluate(&tree, "size:~4mb"), sizes[1..4]);
+    }
+
+    #[test]
+    fn test_folder_rollup_sizes() {
+        let mut tree = FileTree::with_capacity(7);
+        let big = tree
+            .add_or_update_recursive("big", None, None, None, Attributes::DIRECTORY)
+            .unwrap();
+        let a = tree
+            .add_or_update_recursive("big\\a.bin", Some(700), None, None, Attributes::NONE)
+            .unwrap();
+        let b = tree
+            .add_or_update_recursive(
+                "big\\nested\\b.bin",
+                Some(600),
+                None,
+                None,
+                Attributes::NONE,
+            )
+            .unwrap();
+        let nested = tree.get_elements()[b].parent;
+        let small = tree
+            .add_or_update_recursive("small", None, None, None, Attributes::DIRECTORY)
+            .unwrap();
+        let c = tree
+            .add_or_update_recursive("small\\c.bin", Some(10), None, None, Attributes::NONE)
+            .unwrap();
+
+        // Folders without a size of their own don't match before rollups are computed. The nested
+        // folder is only created implicitly, without the directory attribute.
+        assert!(evaluate(&tree, "size:>1kb").is_empty());
+        tree.compute_rollup_sizes();
+        assert_eq!(tree.rollup_size(big), Some(1300));
+        assert_eq!(evaluate(&tree, "size:>1kb"), vec![big]);
+        assert_eq!(evaluate(&tree, "size:500..700"), vec![a, nested, b]);
+        assert_eq!(evaluate(&tree, "size:<100"), vec![small, c]);
+
+        let bigram_index = BigramIndex::new(&tree);
+        let raw = QueryEvaluator::new(&tree, &bigram_index)
+            .with_raw_folder_sizes(true)
+            .evaluate(&parse_query("size:>=10").unwrap());
+        assert_eq!(raw, vec![a, b, c]);
+    }
+}
@@ -0,0 +1,182 @@
+// Finds the parts of a filename matched by a query, for highlighting them in the UI.
+//
+// Text is matched case- and diacritics-insensitively against a folded form of the name, which
+// can be longer or shorter than the original (ß folds to ss, é to e). Every folded byte remembers
+// the original character it came from, so the spans always point into the original name.
+
+use std::ops::Range;
+
+use unicode_normalization::char::{decompose_canonical, is_combining_mark};
+
+use crate::query::query_parser::{QueryExpr, QueryLiteral, TextQuery};
+
+// A folded string with, for each of its bytes, the byte range of the original character
+struct Folded {
+    text: String,
+    origins: Vec<Range<usize>>,
+}
+
+impl Folded {
+    fn new(original: &str, case_sensitive: bool, diacritics_sensitive: bool) -> Self {
+        let mut folded = Folded {
+            text: String::with_capacity(original.len()),
+            origins: Vec::with_capacity(original.len()),
+        };
+        for (offset, c) in original.char_indices() {
+            let origin = offset..offset + c.len_utf8();
+            if diacritics_sensitive {
+                folded.push_case_folded(c, case_sensitive, &origin);
+            } else {
+                // Decompose é into e and the combining accent, and drop the accent
+                decompose_canonical(c, |c| {
+                    if !is_combining_mark(c) {
+                        folded.push_case_folded(c, case_sensitive, &origin);
+                    }
+                });
+            }
+        }
+        folded
+    }
+
+    fn push_case_folded(&mut self, c: char, case_sensitive: bool, origin: &Range<usize>) {
+        if case_sensitive {
+            self.push(c, origin);
+            return;
+        }
+        for c in c.to_lowercase() {
+            if c == 'ß' {
+                self.push('s', origin);
+                self.push('s', origin);
+            } else {
+                self.push(c, origin);
+            }
+        }
+    }
+
+    fn push(&mut self, c: char, origin: &Range<usize>) {
+        self.text.push(c);
+        for _ in 0..c.len_utf8() {
+            self.origins.push(origin.clone());
+        }
+    }
+
+    // Maps a byte range of the folded text to the original characters it came from. Combining
+    // marks following the match are included, e.g. the dropped accent of "e\u{301}".
+    fn original_range(&self, original: &str, range: Range<usize>) -> Range<usize> {
+        let end = self.origins[range.end - 1].end;
+        let marks: usize = original[end..]
+            .chars()
+            .take_while(|&c| is_combining_mark(c))
+            .map(char::len_utf8)
+            .sum();
+        self.origins[range.start].start..end + marks
+    }
+}
+
+// Returns the byte ranges of `name` matched by the text and regex conditions of the query,
+// sorted and without overlaps. Conditions inside a NOT don't highlight anything.
+pub fn highlight_spans(expr: &QueryExpr, name: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    collect_spans(expr, name, &mut spans);
+
+    spans.sort_by_key(|span| (span.start, span.end));
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(spans.len());
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if span.start <= last.end => last.end = last.end.max(span.end),
+            _ => merged.push(span),
+        }
+    }
+    merged
+}
+
+fn collect_spans(expr: &QueryExpr, name: &str, spans: &mut Vec<Range<usize>>) {
+    match expr {
+        QueryExpr::Literal(QueryLiteral::Text(text)) => text_spans(text, name, spans),
+        QueryExpr::Literal(QueryLiteral::Regex(regex)) => {
+            spans.extend(regex.pattern.find_iter(name).map(|m| m.range()));
+        }
+        QueryExpr::And(left, right) | QueryExpr::Or(left, right) => {
+            collect_spans(left, name, spans);
+            collect_spans(right, name, spans);
+        }
+        QueryExpr::Not(_) | QueryExpr::Function(_) => {}
+    }
+}
+
+fn text_spans(query: &TextQuery, name: &str, spans: &mut Vec<Range<usize>>) {
+    let folded_query = Folded::new(
+        &query.text,
+        query.case_sensitive,
+        query.diacritics_sensitive,
+    );
+    if folded_query.text.is_empty() {
+        return;
+    }
+    let folded_name = Folded::new(name, query.case_sensitive, query.diacritics_sensitive);
+
+    let mut pattern = regex::escape(&folded_query.text);
+    if query.whole_filename {
+        pattern = format!("^{}$", pattern);
+    } else if query.whole_word {
+        pattern = format!(r"\b{}\b", pattern);
+    }
+    let pattern = regex::Regex::new(&pattern).expect("Failed to compile regex");
+    spans.extend(
+        pattern
+            .find_iter(&folded_name.text)
+            .map(|m| folded_name.original_range(name, m.range())),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::query_parser::parse_query;
+
+    fn highlighted(query: &str, name: &str) -> Vec<String> {
+        highlight_spans(&parse_query(query).unwrap(), name)
+            .into_iter()
+            .map(|span| name[span].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_highlight_folding() {
+        assert_eq!(highlighted("cafe", "Le Café.txt"), vec!["Café"]);
+        assert_eq!(highlighted("café", "CAFE\u{301} noir"), vec!["CAFE\u{301}"]);
+        assert_eq!(
+            highlight_spans(&parse_query("cafe").unwrap(), "café"),
+            vec![0..5]
+        );
+
+        // ß folds to two characters, the spans still point at the original bytes
+        assert_eq!(highlighted("strasse", "Die Straße.txt"), vec!["Straße"]);
+        assert_eq!(highlighted("straße", "STRASSE.txt"), vec!["STRASSE"]);
+        assert_eq!(highlighted("sse", "Straße Strasse"), vec!["ße", "sse"]);
+        assert_eq!(highlighted("e.t", "Maße.txt"), vec!["e.t"]);
+        // Matching half of an expansion highlights the whole original character
+        assert_eq!(highlighted("as", "Maß"), vec!["aß"]);
+
+        // İ lowercases to i and a combining dot
+        assert_eq!(highlighted("istanbul", "İstanbul.jpg"), vec!["İstanbul"]);
+    }
+
+    #[test]
+    fn test_highlight_query() {
+        assert_eq!(
+            highlighted("report | sum", "Summary report, REPORT"),
+            vec!["Sum", "report", "REPORT"]
+        );
+        assert_eq!(highlighted("repo report", "report"), vec!["report"]);
+        assert!(highlighted("!report", "report").is_empty());
+        assert_eq!(highlighted("case:Report", "report Report"), vec!["Report"]);
+        assert_eq!(highlighted("diacritics:cafe", "café cafe"), vec!["cafe"]);
+        assert_eq!(highlighted("ww:cat", "cat concat cat"), vec!["cat", "cat"]);
+        assert_eq!(
+            highlighted("regex:[0-9]+", "track 12 of 20"),
+            vec!["12", "20"]
+        );
+        assert!(highlighted("ext:jpg", "photo.jpg").is_empty());
+    }
+}
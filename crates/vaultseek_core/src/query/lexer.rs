@@ -10,6 +10,7 @@ pub enum QueryToken {
     GreaterThanOrEqual,
     Not,
     Or,
+    And,
     StrLit(String),
     Ident(String),
     Whitespace
@@ -25,6 +26,7 @@ impl std::fmt::Display for QueryToken {
             QueryToken::GreaterThanOrEqual => write!(f, ">="),
             QueryToken::Not => write!(f, "!"),
             QueryToken::Or => write!(f, "|"),
+            QueryToken::And => write!(f, "&"),
             QueryToken::StrLit(s) => write!(f, "{}", s),
             QueryToken::Ident(s) => write!(f, "{}", s),
             QueryToken::Whitespace => write!(f, " "),
@@ -98,7 +100,19 @@ impl QueryLexer {
                 }
             }
             '!' => QueryToken::Not,
-            '|' => QueryToken::Or,
+            // || and && are aliases for programmers, a single | is OR like in Everything
+            '|' => {
+                if self.peek_char() == Some('|') {
+                    self.read_char();
+                }
+                QueryToken::Or
+            }
+            '&' => {
+                if self.peek_char() == Some('&') {
+                    self.read_char();
+                }
+                QueryToken::And
+            }
             '"' => {
                 // Read until the next quote, no escape characters exist
                 let str_lit = self.read_while(|c| c != '"');
@@ -218,6 +232,37 @@ mod tests {
             assert_eq!(token, Some(expected));
         }
     }
+    #[test]
+    fn test_lexer_double_operators() {
+        let input = r#"a || b && c | d & e|||f"#;
+        let mut lexer = QueryLexer::new(input);
+        let expected_tokens = vec![
+            QueryToken::Ident("a".into()),
+            QueryToken::Whitespace,
+            QueryToken::Or,
+            QueryToken::Whitespace,
+            QueryToken::Ident("b".into()),
+            QueryToken::Whitespace,
+            QueryToken::And,
+            QueryToken::Whitespace,
+            QueryToken::Ident("c".into()),
+            QueryToken::Whitespace,
+            QueryToken::Or,
+            QueryToken::Whitespace,
+            QueryToken::Ident("d".into()),
+            QueryToken::Whitespace,
+            QueryToken::And,
+            QueryToken::Whitespace,
+            // Inside an identifier they are still part of the text
+            QueryToken::Ident("e|||f".into()),
+        ];
+        for expected in expected_tokens {
+            let token = lexer.next_token();
+            assert_eq!(token, Some(expected));
+        }
+        assert!(lexer.next_token().is_none());
+    }
+
     #[test]
     fn test_lexer_groups() {
         let input = r#"notes.txt < path:homework | size:>100KB >"#;
@@ -1,3 +1,6 @@
 pub mod date;
+pub mod display;
+pub mod evaluator;
+pub mod highlight;
 pub mod query_parser;
 pub mod lexer;
@@ -1,9 +1,8 @@
-use std::fmt::format;
+use serde::{Serialize, Serializer};
 
-use crate::query::date::*;
 use crate::query::lexer;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub struct QueryModifiersTracking {
     pub case_sensitive: bool,
     pub diacritics_sensitive: bool,
@@ -16,23 +15,93 @@ pub struct QueryModifiersTracking {
     pub wildcards: bool,
 }
 
-impl Default for QueryModifiersTracking {
+// How consecutive conditions separated by whitespace are combined. Explicit `|` is always OR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpaceSemantics {
+    #[default]
+    And,
+    Or,
+}
+
+// What a bare number like 2023 or 1000 stands for. By default it's text like any other term,
+// which is ambiguous in a view focused on dates or sizes, so the other modes are opt-in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BareNumbers {
+    #[default]
+    Text,
+    // Numbers that are dates are the modification date, e.g. 2023 as dm:2023
+    DateModified,
+    // Numbers that are sizes are the size, e.g. 1000 as size:1000 and 10mb as size:10mb
+    Size,
+}
+
+// Default limit for the compiled size of user regex patterns, in bytes
+pub const DEFAULT_REGEX_SIZE_LIMIT: usize = 1 << 20;
+// Default tolerance of approximate sizes like size:~4mb, in percent of the size
+pub const DEFAULT_SIZE_TOLERANCE_PERCENT: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParseOptions {
+    pub space_semantics: SpaceSemantics,
+    // Regex patterns that compile to more than this many bytes are rejected with
+    // QueryError::RegexTooLarge, as they'd be too slow to run over every filename
+    pub regex_size_limit: usize,
+    // size:~4mb matches sizes within this many percent of 4mb, in both directions
+    pub size_tolerance_percent: u32,
+    pub bare_numbers: BareNumbers,
+}
+impl Default for ParseOptions {
     fn default() -> Self {
-        QueryModifiersTracking {
-            case_sensitive: false,
-            diacritics_sensitive: false,
-            file_only: false,
-            folder_only: false,
-            match_path: false,
-            regex: false,
-            whole_filename: false,
-            whole_word: false,
-            wildcards: false,
+        ParseOptions {
+            space_semantics: SpaceSemantics::default(),
+            regex_size_limit: DEFAULT_REGEX_SIZE_LIMIT,
+            size_tolerance_percent: DEFAULT_SIZE_TOLERANCE_PERCENT,
+            bare_numbers: BareNumbers::default(),
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
+pub enum QueryError {
+    InvalidRegex { pattern: String, message: String },
+    RegexTooLarge { pattern: String, limit: usize },
+    // dupe: filters the whole result, so it can't be negated or be one side of an OR
+    NestedDupe,
+    // parentat: compares a single folder name, so a path in the argument could never match
+    SeparatorInParentAt(String),
+}
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::InvalidRegex { pattern, message } => {
+                write!(f, "Invalid regex '{}': {}", pattern, message)
+            }
+            QueryError::RegexTooLarge { pattern, limit } => {
+                write!(
+                    f,
+                    "Regex '{}' exceeds the size limit of {} bytes",
+                    pattern, limit
+                )
+            }
+            QueryError::NestedDupe => {
+                write!(
+                    f,
+                    "dupe: applies to all results and can't be negated or used with OR"
+                )
+            }
+            QueryError::SeparatorInParentAt(folder) => {
+                write!(
+                    f,
+                    "parentat: takes a single folder name, not the path '{}'",
+                    folder
+                )
+            }
+        }
+    }
+}
+impl std::error::Error for QueryError {}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct TextQuery {
     pub text: String,
     pub case_sensitive: bool,
@@ -44,21 +113,29 @@ pub struct TextQuery {
     pub whole_word: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct RegexQuery {
+    #[serde(serialize_with = "serialize_regex")]
     pub pattern: regex::Regex,
     pub case_sensitive: bool,
     pub diacritics_sensitive: bool,
+    pub file_only: bool,
+    pub folder_only: bool,
     pub match_path: bool,
 }
 
-#[derive(Debug, Clone)]
+// Serialized as the source of the pattern
+fn serialize_regex<S: Serializer>(regex: &regex::Regex, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(regex.as_str())
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub enum QueryLiteral {
     Text(TextQuery),
     Regex(RegexQuery),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum QueryExpr {
     Literal(QueryLiteral),
     Function(QueryFunction),
@@ -67,16 +144,77 @@ pub enum QueryExpr {
     Not(Box<QueryExpr>),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum QueryFunction {
     Size(QueryCmp, u64),
+    SizeRange(u64, u64), // start, end, both inclusive
     DateModified(QueryCmp, QueryDate),
     DateCreated(QueryCmp, QueryDate),
-    Parent(String),
+    // The folder functions take the folder name and whether case: was set for them, folder
+    // names are matched ignoring case otherwise
+    Parent(String, bool),
+    ParentAt(usize, String, bool), // level, folder name. Level 1 is the direct parent
+    // Directly in the folder, not in its subfolders like Parent. Like in Everything's nosubfolders:
+    ParentDirectOnly(String, bool),
+    // The filename or the name of a folder it's in contains the text, case-insensitive. Unlike
+    // path:, a match never spans a separator.
+    Component(String),
     Ext(Vec<String>),
+    Dupe(DupeKey),
+    Empty,
+    TimeOfDay(TimeOfDay),     // time of the modification date, on any day
+    Hardlinks(QueryCmp, u32), // number of hard links, only known for ncdu imports
+    Inode(u64, u64),          // device, inode. Inode numbers are only unique on one device
+    // Length of the filename in characters (Unicode scalar values), not bytes, so é counts once
+    // whether the name is UTF-8 or not. A decomposed é (e and a combining accent) counts twice.
+    NameLength(QueryCmp, usize),
+    // Compares the creation date to the modification date of the same element, e.g. Gt for
+    // files created after they were modified (copied files, clock skew). Undated never match.
+    CreatedVsModified(QueryCmp),
+    // Characters somewhere in the filename, e.g. for cleaning up names with spaces
+    HasDigits,  // an ASCII digit
+    HasSpaces,  // any whitespace
+    HasUnicode, // any non-ASCII character
+    // A number in the filename within the range, e.g. numrange:IMG_0001..IMG_0100
+    NumRange(NumRange),
+    // Number of direct children, e.g. children:>10000 for huge folders. Files have none.
+    Children(QueryCmp, usize),
+    // MIME type from the extension, lowercase. Exact like image/png, or the whole type with
+    // image/*. Files without a known type never match.
+    Mime(String),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+// What elements have to share to be duplicates, e.g. dupe:name;size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DupeKey {
+    pub name: bool,
+    pub size: bool,
+}
+impl DupeKey {
+    // Parses the argument of dupe:, a `;` separated list of name and size. Defaults to name.
+    fn parse(argument: Option<&str>) -> Option<DupeKey> {
+        let Some(argument) = argument else {
+            return Some(DupeKey {
+                name: true,
+                size: false,
+            });
+        };
+        let mut key = DupeKey {
+            name: false,
+            size: false,
+        };
+        for part in argument.split(';').filter(|part| !part.is_empty()) {
+            match fold_keyword(part).as_str() {
+                "name" => key.name = true,
+                "size" => key.size = true,
+                _ => return None,
+            }
+        }
+        (key.name || key.size).then_some(key)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum QueryCmp {
     Eq,
     Gt,
@@ -85,6 +223,18 @@ pub enum QueryCmp {
     Le,
     Range, // start..end
 }
+impl QueryCmp {
+    // Compares a value against the query argument, e.g. `value > argument` for Gt
+    pub fn compare<T: PartialOrd>(&self, value: T, argument: T) -> bool {
+        match self {
+            QueryCmp::Eq | QueryCmp::Range => value == argument,
+            QueryCmp::Gt => value > argument,
+            QueryCmp::Ge => value >= argument,
+            QueryCmp::Lt => value < argument,
+            QueryCmp::Le => value <= argument,
+        }
+    }
+}
 impl From<&str> for QueryCmp {
     fn from(s: &str) -> Self {
         match s {
@@ -99,7 +249,7 @@ impl From<&str> for QueryCmp {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum Weekday {
     Sunday = 0,
     Monday = 1,
@@ -109,7 +259,7 @@ pub enum Weekday {
     Friday = 5,
     Saturday = 6,
 }
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum Month {
     January = 1,
     February = 2,
@@ -124,13 +274,94 @@ pub enum Month {
     November = 11,
     December = 12,
 }
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum QueryDate {
     Range(i64, i64),  // start, end as timestamps
     Weekday(Weekday), // 0=Sun - 6=Sat
     Month(Month),     // 1=Jan - 12=Dec
     Unknown,
 }
+// Seconds since local midnight, both inclusive. If start > end the range wraps around midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TimeOfDay {
+    pub start: u32,
+    pub end: u32,
+}
+
+// A range of numbered filenames like IMG_0001..IMG_0100. The text around the number has to be the
+// same on both ends, the end can also be just the number, like IMG_0001..0100. Zero-padded
+// numbers only match with the same number of digits, so IMG_50.jpg is not in IMG_0001..IMG_0100.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NumRange {
+    pub prefix: String, // Compared ignoring ASCII case, like the suffix
+    pub start: u64,
+    pub end: u64,
+    pub width: Option<usize>, // Number of digits if the numbers are zero-padded
+    pub suffix: String,
+}
+impl NumRange {
+    fn parse(value: &str) -> Option<NumRange> {
+        let (start, end) = value.split_once("..")?;
+        let (prefix, start_digits, suffix) = split_number(start)?;
+        let end_digits = if !end.is_empty() && end.bytes().all(|b| b.is_ascii_digit()) {
+            end
+        } else {
+            let (end_prefix, end_digits, end_suffix) = split_number(end)?;
+            if !end_prefix.eq_ignore_ascii_case(prefix) || !end_suffix.eq_ignore_ascii_case(suffix)
+            {
+                return None;
+            }
+            end_digits
+        };
+        let padded = |digits: &str| digits.len() > 1 && digits.starts_with('0');
+        let width = if padded(start_digits) || padded(end_digits) {
+            if start_digits.len() != end_digits.len() {
+                return None;
+            }
+            Some(start_digits.len())
+        } else {
+            None
+        };
+        let (start, end) = (start_digits.parse().ok()?, end_digits.parse().ok()?);
+        (start <= end).then(|| NumRange {
+            prefix: prefix.to_string(),
+            start,
+            end,
+            width,
+            suffix: suffix.to_string(),
+        })
+    }
+
+    // The number has to follow the prefix directly at the start of the name
+    pub fn matches(&self, name: &str) -> bool {
+        let Some(rest) = name
+            .get(..self.prefix.len())
+            .filter(|prefix| prefix.eq_ignore_ascii_case(&self.prefix))
+            .map(|_| &name[self.prefix.len()..])
+        else {
+            return false;
+        };
+        let digits_len = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let (digits, rest) = rest.split_at(digits_len);
+        digits_len > 0
+            && self.width.is_none_or(|width| width == digits_len)
+            && rest
+                .get(..self.suffix.len())
+                .is_some_and(|suffix| suffix.eq_ignore_ascii_case(&self.suffix))
+            && digits
+                .parse::<u64>()
+                .is_ok_and(|number| self.start <= number && number <= self.end)
+    }
+}
+
+// Splits at the last number, e.g. IMG_0001.jpg into IMG_, 0001 and .jpg
+fn split_number(text: &str) -> Option<(&str, &str, &str)> {
+    let end = text.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = text[..end]
+        .rfind(|c: char| !c.is_ascii_digit())
+        .map_or(0, |i| i + 1);
+    Some((&text[..start], &text[start..end], &text[end..]))
+}
 
 fn exprs_to_and(exprs: Vec<QueryExpr>) -> QueryExpr {
     if exprs.is_empty() {
@@ -152,6 +383,24 @@ fn exprs_to_and(exprs: Vec<QueryExpr>) -> QueryExpr {
     })
 }
 
+fn exprs_to_or(exprs: Vec<QueryExpr>) -> QueryExpr {
+    let mut iter = exprs.into_iter();
+    match iter.next() {
+        Some(first) => iter.fold(first, |acc, expr| {
+            QueryExpr::Or(Box::new(acc), Box::new(expr))
+        }),
+        None => exprs_to_and(Vec::new()),
+    }
+}
+
+// Combine whitespace separated conditions according to the space semantics
+fn combine_exprs(exprs: Vec<QueryExpr>, options: ParseOptions) -> QueryExpr {
+    match options.space_semantics {
+        SpaceSemantics::And => exprs_to_and(exprs),
+        SpaceSemantics::Or => exprs_to_or(exprs),
+    }
+}
+
 fn get_comparison(lexer: &mut lexer::QueryLexer) -> Option<QueryCmp> {
     if let Some(token) = lexer.peek_token() {
         match token {
@@ -182,25 +431,270 @@ fn get_comparison(lexer: &mut lexer::QueryLexer) -> Option<QueryCmp> {
     }
 }
 
-fn create_query_literal(text: String, modifiers: QueryModifiersTracking) -> QueryLiteral {
+// Reads the comparison and the value of a function argument, e.g. `>1000` in size:>1000.
+// A quoted argument may include the comparison too, e.g. size:">=1000".
+fn get_function_argument(lexer: &mut lexer::QueryLexer) -> Option<(QueryCmp, String)> {
+    if let Some(lexer::QueryToken::StrLit(value)) = lexer.peek_token() {
+        lexer.next_token();
+        // Lex the quoted value on its own to find the comparison in it
+        let mut value_lexer = lexer::QueryLexer::new(&value);
+        let cmp = get_comparison(&mut value_lexer)?;
+        let mut argument = String::new();
+        while let Some(token) = value_lexer.next_token() {
+            argument.push_str(&token.to_string());
+        }
+        return Some((cmp, argument.trim().to_string()));
+    }
+    let cmp = get_comparison(lexer)?;
+    if let Some(lexer::QueryToken::Ident(value) | lexer::QueryToken::StrLit(value)) =
+        lexer.next_token()
+    {
+        return Some((cmp, value));
+    }
+    None
+}
+
+// A range argument like 1000..2000. Brackets choose whether the ends are included,
+// e.g. [1000..2000) excludes 2000. Without brackets both ends are included.
+struct RangeArgument<'a> {
+    start: &'a str,
+    end: &'a str,
+    start_inclusive: bool,
+    end_inclusive: bool,
+}
+impl<'a> RangeArgument<'a> {
+    fn parse(value: &'a str) -> Option<Self> {
+        let value = value.trim();
+        let (value, start_inclusive) = match value.strip_prefix('(') {
+            Some(value) => (value, false),
+            None => (value.strip_prefix('[').unwrap_or(value), true),
+        };
+        let (value, end_inclusive) = match value.strip_suffix(')') {
+            Some(value) => (value, false),
+            None => (value.strip_suffix(']').unwrap_or(value), true),
+        };
+        let (start, end) = value.split_once("..")?;
+        Some(RangeArgument {
+            start: start.trim(),
+            end: end.trim(),
+            start_inclusive,
+            end_inclusive,
+        })
+    }
+}
+
+// Parses a size like 1500, 10kb or 1.5mb into bytes. Units are binary like in Everything,
+// so 1kb is 1024 bytes.
+fn parse_size_value(value: &str) -> Option<u64> {
+    let value = value.trim().to_ascii_lowercase();
+    let (number, multiplier) = [
+        ("tb", 1u64 << 40),
+        ("gb", 1 << 30),
+        ("mb", 1 << 20),
+        ("kb", 1 << 10),
+        ("b", 1),
+    ]
+    .iter()
+    .find_map(|&(unit, multiplier)| Some((value.strip_suffix(unit)?, multiplier)))
+    .unwrap_or((value.as_str(), 1));
+    let number = number.trim_end();
+    if let Ok(number) = number.parse::<u64>() {
+        return number.checked_mul(multiplier);
+    }
+    // Fractions like 1.5mb, rounded down to whole bytes
+    let number = number.parse::<f64>().ok()?;
+    let size = number * multiplier as f64;
+    (number.is_finite() && size >= 0.0 && size < u64::MAX as f64).then_some(size as u64)
+}
+
+// Parses a size argument, which can also be a range like 1000..2000 or [1000..2000), or an
+// approximate size like ~4mb that matches within the size tolerance
+fn parse_size_argument(cmp: QueryCmp, value: &str, options: ParseOptions) -> Option<QueryFunction> {
+    if let Some(approximate) = value.strip_prefix('~').or_else(|| value.strip_prefix('≈')) {
+        let size = parse_size_value(approximate)?;
+        let tolerance = (size as u128 * options.size_tolerance_percent as u128 / 100) as u64;
+        return Some(QueryFunction::SizeRange(
+            size.saturating_sub(tolerance),
+            size.saturating_add(tolerance),
+        ));
+    }
+    if let Some(range) = RangeArgument::parse(value) {
+        let start = parse_size_value(range.start)?;
+        let end = parse_size_value(range.end)?;
+        // Sizes are whole bytes, so excluding an end is the same as including its neighbour.
+        // A range that excludes everything, like [0..0), ends up with start > end.
+        let start = if range.start_inclusive {
+            Some(start)
+        } else {
+            start.checked_add(1)
+        };
+        let end = if range.end_inclusive {
+            Some(end)
+        } else {
+            end.checked_sub(1)
+        };
+        return Some(match (start, end) {
+            (Some(start), Some(end)) => QueryFunction::SizeRange(start, end),
+            _ => QueryFunction::SizeRange(1, 0),
+        });
+    }
+    parse_size_value(value).map(|size| QueryFunction::Size(cmp, size))
+}
+
+// Parses the argument of cmdate:, a comparison of the two dates like created>modified or
+// modified<=created, into the comparison of the creation date to the modification date
+fn parse_date_comparison(value: &str) -> Option<QueryCmp> {
+    let value = value
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase();
+    let at = value.find(['<', '>', '='])?;
+    let (left, rest) = value.split_at(at);
+    let (op, right) = rest.split_at(if rest[1..].starts_with('=') { 2 } else { 1 });
+    let cmp = match op {
+        "=" => QueryCmp::Eq,
+        op => QueryCmp::from(op),
+    };
+    match (left, right) {
+        ("created", "modified") => Some(cmp),
+        // modified>created is created<modified
+        ("modified", "created") => Some(match cmp {
+            QueryCmp::Gt => QueryCmp::Lt,
+            QueryCmp::Ge => QueryCmp::Le,
+            QueryCmp::Lt => QueryCmp::Gt,
+            QueryCmp::Le => QueryCmp::Ge,
+            cmp => cmp,
+        }),
+        _ => None,
+    }
+}
+
+// Parses a time of day like 9:00, 5pm or a range like 9:00..17:00. A single time covers its
+// precision, so 9 is 9:00:00 until 9:59:59. Bracketed ranges work like for sizes.
+fn parse_time_of_day_argument(value: &str) -> Option<TimeOfDay> {
+    const DAY: u32 = 24 * 60 * 60;
+    let Some(range) = RangeArgument::parse(value) else {
+        let (start, end) = TimeOfDay::parse_time(value)?;
+        return Some(TimeOfDay { start, end });
+    };
+    let (start_first, start_last) = TimeOfDay::parse_time(range.start)?;
+    let (end_first, end_last) = TimeOfDay::parse_time(range.end)?;
+    // The same time on both ends is just that time, whatever the brackets, instead of a range
+    // wrapping around the whole day
+    if start_first == end_first {
+        return Some(TimeOfDay {
+            start: start_first,
+            end: end_last,
+        });
+    }
+    let start = if range.start_inclusive {
+        start_first
+    } else {
+        (start_last + 1) % DAY
+    };
+    let end = if range.end_inclusive {
+        end_last
+    } else {
+        (end_first + DAY - 1) % DAY
+    };
+    // Only a range written from a later to an earlier time wraps around midnight, one that the
+    // brackets leave empty is invalid
+    (start <= end || start_first > end_first).then_some(TimeOfDay { start, end })
+}
+
+// Parses a date argument, which can also be a range like 2023-01-01..2023-12-31.
+// An excluded start begins after the start date, an excluded end stops before the end date.
+// Either end can be left open: 2023-01-01.. is from that date on, ..2023-12-31 up to that date.
+fn parse_date_argument(cmp: QueryCmp, value: &str) -> (QueryCmp, QueryDate) {
+    let Some(range) = RangeArgument::parse(value) else {
+        return (cmp, QueryDate::from(value));
+    };
+    let open_end = match (range.start.is_empty(), range.end.is_empty()) {
+        (false, true) if range.start_inclusive => Some((QueryCmp::Ge, range.start)),
+        (false, true) => Some((QueryCmp::Gt, range.start)),
+        (true, false) if range.end_inclusive => Some((QueryCmp::Le, range.end)),
+        (true, false) => Some((QueryCmp::Lt, range.end)),
+        _ => None,
+    };
+    if let Some((cmp, date)) = open_end
+        && let QueryDate::Range(first, last) = QueryDate::from(date)
+        && (first, last) != (0, 0)
+    {
+        return (cmp, QueryDate::Range(first, last));
+    }
+    if let QueryDate::Range(start_first, start_last) = QueryDate::from(range.start)
+        && let QueryDate::Range(end_first, end_last) = QueryDate::from(range.end)
+        && (start_first, start_last) != (0, 0)
+        && (end_first, end_last) != (0, 0)
+    {
+        // Timestamps are whole seconds, so the range can always be stored inclusive
+        let start = if range.start_inclusive {
+            start_first
+        } else {
+            start_last + 1
+        };
+        let end = if range.end_inclusive {
+            end_last
+        } else {
+            end_first - 1
+        };
+        return (QueryCmp::Range, QueryDate::Range(start, end));
+    }
+    (cmp, QueryDate::from(value))
+}
+
+// Reads a bare term starting with a digit as a function if the options ask for it, see
+// BareNumbers. Terms that aren't a valid date or size stay text.
+fn parse_bare_number(text: &str, options: ParseOptions) -> Option<QueryFunction> {
+    if !text.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    match options.bare_numbers {
+        BareNumbers::Text => None,
+        BareNumbers::DateModified => match parse_date_argument(QueryCmp::Eq, text) {
+            (_, QueryDate::Range(0, 0)) => None,
+            (cmp, date) => Some(QueryFunction::DateModified(cmp, date)),
+        },
+        BareNumbers::Size => parse_size_argument(QueryCmp::Eq, text, options),
+    }
+}
+
+fn create_query_literal(
+    text: String,
+    modifiers: QueryModifiersTracking,
+    options: ParseOptions,
+) -> Result<QueryLiteral, QueryError> {
     if modifiers.regex {
         // Create RegexQuery
         let mut regex_builder = regex::RegexBuilder::new(&text);
         if !modifiers.case_sensitive {
             regex_builder.case_insensitive(true);
         }
-        let pattern = regex_builder
-            .build()
-            .unwrap_or_else(|_| regex::Regex::new(".*").unwrap());
-        QueryLiteral::Regex(RegexQuery {
+        regex_builder
+            .size_limit(options.regex_size_limit)
+            .dfa_size_limit(options.regex_size_limit);
+        let pattern = regex_builder.build().map_err(|e| match e {
+            regex::Error::CompiledTooBig(limit) => QueryError::RegexTooLarge {
+                pattern: text.clone(),
+                limit,
+            },
+            e => QueryError::InvalidRegex {
+                pattern: text.clone(),
+                message: e.to_string(),
+            },
+        })?;
+        Ok(QueryLiteral::Regex(RegexQuery {
             pattern,
             case_sensitive: modifiers.case_sensitive,
             diacritics_sensitive: modifiers.diacritics_sensitive,
+            file_only: modifiers.file_only,
+            folder_only: modifiers.folder_only,
             match_path: modifiers.match_path,
-        })
+        }))
     } else {
         // Create TextQuery
-        QueryLiteral::Text(TextQuery {
+        Ok(QueryLiteral::Text(TextQuery {
             text,
             case_sensitive: modifiers.case_sensitive,
             diacritics_sensitive: modifiers.diacritics_sensitive,
@@ -209,67 +703,202 @@ fn create_query_literal(text: String, modifiers: QueryModifiersTracking) -> Quer
             match_path: modifiers.match_path,
             whole_filename: modifiers.whole_filename,
             whole_word: modifiers.whole_word,
-        })
+        }))
     }
 }
 
+// Folds a function or modifier name for keyword matching, independent of the host locale.
+// Only ASCII letters are lowercased, plus the Turkish dotted/dotless i, which a Turkish keyboard
+// produces when typing the ASCII keywords in uppercase (e.g. SİZE).
+fn fold_keyword(ident: &str) -> String {
+    ident
+        .chars()
+        .map(|c| match c {
+            '\u{130}' | '\u{131}' => 'i', // İ and ı
+            c => c.to_ascii_lowercase(),
+        })
+        .collect()
+}
+
 // Parses a function like size:>1000 or datecreated:<2023-01-01
-fn parse_function(lexer: &mut lexer::QueryLexer, name: &str) -> Option<QueryFunction> {
-    let name = name.to_lowercase();
+fn parse_function(
+    lexer: &mut lexer::QueryLexer,
+    name: &str,
+    modifiers: QueryModifiersTracking,
+    options: ParseOptions,
+) -> Option<QueryFunction> {
+    let name = fold_keyword(name);
     let name = name.as_str();
     match name {
         "size" => {
-            let cmp = get_comparison(lexer)?;
-            if let Some(token) = lexer.next_token() {
-                match token {
-                    lexer::QueryToken::Ident(num_str) | lexer::QueryToken::StrLit(num_str) => {
-                        if let Ok(size) = num_str.parse::<u64>() {
-                            return Some(QueryFunction::Size(cmp, size));
-                        }
-                    }
-                    _ => {}
-                }
-            }
-            None
+            let (cmp, num_str) = get_function_argument(lexer)?;
+            parse_size_argument(cmp, &num_str, options)
         }
         "datemodified" | "dm" | "datecreated" | "dc" => {
-            let cmp = get_comparison(lexer)?;
-            if let Some(token) = lexer.next_token() {
-                match token {
-                    lexer::QueryToken::Ident(date_str) | lexer::QueryToken::StrLit(date_str) => {
-                        // use QueryDate::from
-                        let date = QueryDate::from(date_str.as_str());
-                        return Some(if name.starts_with("datecreated") || name == "dc" {
-                            QueryFunction::DateCreated(cmp, date)
-                        } else {
-                            QueryFunction::DateModified(cmp, date)
-                        });
+            let (cmp, date_str) = get_function_argument(lexer)?;
+            let (cmp, date) = parse_date_argument(cmp, &date_str);
+            Some(if name.starts_with("datecreated") || name == "dc" {
+                QueryFunction::DateCreated(cmp, date)
+            } else {
+                QueryFunction::DateModified(cmp, date)
+            })
+        }
+        "timeofday" | "tod" => {
+            // The argument contains colons, e.g. timeofday:9:00..17:00, so it spans several tokens
+            let argument = match lexer.peek_token() {
+                Some(lexer::QueryToken::StrLit(value)) => {
+                    lexer.next_token();
+                    value
+                }
+                _ => {
+                    let mut argument = String::new();
+                    while let Some(
+                        token @ (lexer::QueryToken::Ident(_) | lexer::QueryToken::Colon),
+                    ) = lexer.peek_token()
+                    {
+                        lexer.next_token();
+                        argument.push_str(&token.to_string());
                     }
-                    _ => {}
+                    argument
                 }
+            };
+            parse_time_of_day_argument(&argument).map(QueryFunction::TimeOfDay)
+        }
+        "hardlinks" | "nlink" => {
+            // hardlinks: alone finds files with more than one link
+            if matches!(
+                lexer.peek_token(),
+                None | Some(
+                    lexer::QueryToken::Whitespace | lexer::QueryToken::Or | lexer::QueryToken::And
+                )
+            ) {
+                return Some(QueryFunction::Hardlinks(QueryCmp::Gt, 1));
+            }
+            let (cmp, value) = get_function_argument(lexer)?;
+            value
+                .parse()
+                .ok()
+                .map(|nlink| QueryFunction::Hardlinks(cmp, nlink))
+        }
+        "inode" => {
+            // inode:2049:12345, the device and the inode number
+            if let Some(lexer::QueryToken::Ident(device)) = lexer.next_token()
+                && let Ok(device) = device.parse()
+                && let Some(lexer::QueryToken::Colon) = lexer.next_token()
+                && let Some(lexer::QueryToken::Ident(inode)) = lexer.next_token()
+                && let Ok(inode) = inode.parse()
+            {
+                return Some(QueryFunction::Inode(device, inode));
             }
             None
         }
+        // clockskew: finds elements created after they were modified, typical for copied files
+        "clockskew" => Some(QueryFunction::CreatedVsModified(QueryCmp::Gt)),
+        "cmdate" => {
+            let (_, value) = get_function_argument(lexer)?;
+            parse_date_comparison(&value).map(QueryFunction::CreatedVsModified)
+        }
+        "namelen" => {
+            let (cmp, value) = get_function_argument(lexer)?;
+            value
+                .parse()
+                .ok()
+                .map(|len| QueryFunction::NameLength(cmp, len))
+        }
+        "mime" => {
+            let (_, value) = get_function_argument(lexer)?;
+            Some(QueryFunction::Mime(value.to_lowercase()))
+        }
+        "children" => {
+            let (cmp, value) = get_function_argument(lexer)?;
+            value
+                .parse()
+                .ok()
+                .map(|count| QueryFunction::Children(cmp, count))
+        }
         "parent" | "infolder" | "nosubfolders" => {
-            if let Some(token) = lexer.next_token() {
-                match token {
-                    lexer::QueryToken::Ident(folder) | lexer::QueryToken::StrLit(folder) => {
-                        return Some(QueryFunction::Parent(folder));
-                    }
-                    _ => {}
+            if let Some(lexer::QueryToken::Ident(folder) | lexer::QueryToken::StrLit(folder)) =
+                lexer.next_token()
+            {
+                return Some(if name == "nosubfolders" {
+                    QueryFunction::ParentDirectOnly(folder, modifiers.case_sensitive)
+                } else {
+                    QueryFunction::Parent(folder, modifiers.case_sensitive)
+                });
+            }
+            None
+        }
+        "component" => {
+            if let Some(lexer::QueryToken::Ident(text) | lexer::QueryToken::StrLit(text)) =
+                lexer.next_token()
+            {
+                return Some(QueryFunction::Component(text));
+            }
+            None
+        }
+        "parentat" => {
+            // parentat:2:src, the ancestor at the given level is named src
+            if let Some(lexer::QueryToken::Ident(level)) = lexer.next_token()
+                && let Ok(level) = level.parse::<usize>()
+                && level > 0
+                && let Some(lexer::QueryToken::Colon) = lexer.next_token()
+                && let Some(lexer::QueryToken::Ident(folder) | lexer::QueryToken::StrLit(folder)) =
+                    lexer.next_token()
+            {
+                return Some(QueryFunction::ParentAt(
+                    level,
+                    folder,
+                    modifiers.case_sensitive,
+                ));
+            }
+            None
+        }
+        "dupe" => {
+            // The argument is optional, dupe: alone compares names
+            let argument = match lexer.peek_token() {
+                Some(lexer::QueryToken::Ident(argument) | lexer::QueryToken::StrLit(argument)) => {
+                    lexer.next_token();
+                    Some(argument)
                 }
+                _ => None,
+            };
+            DupeKey::parse(argument.as_deref()).map(QueryFunction::Dupe)
+        }
+        "empty" => Some(QueryFunction::Empty),
+        "hasdigits" => Some(QueryFunction::HasDigits),
+        "hasspaces" => Some(QueryFunction::HasSpaces),
+        "hasunicode" => Some(QueryFunction::HasUnicode),
+        "numrange" => {
+            if let Some(lexer::QueryToken::Ident(range) | lexer::QueryToken::StrLit(range)) =
+                lexer.next_token()
+            {
+                return NumRange::parse(&range).map(QueryFunction::NumRange);
             }
             None
         }
         "ext" => {
+            // A list of extensions like ext:jpg;png or ext:jpg png. The list ends at the next
+            // function or modifier (e.g. dupe: in `ext:jpg dupe:size`) or operator.
             let mut exts = Vec::new();
-            while let Some(token) = lexer.next_token() {
-                match token {
-                    lexer::QueryToken::Ident(ext) | lexer::QueryToken::StrLit(ext) => {
-                        exts.push(ext);
-                    }
-                    _ => {}
+            loop {
+                let mut next = lexer.clone();
+                while let Some(lexer::QueryToken::Whitespace) = next.peek_token() {
+                    next.next_token();
+                }
+                let Some(lexer::QueryToken::Ident(ext) | lexer::QueryToken::StrLit(ext)) =
+                    next.next_token()
+                else {
+                    break;
+                };
+                if let Some(lexer::QueryToken::Colon) = next.peek_token() {
+                    break;
                 }
+                *lexer = next;
+                exts.extend(
+                    ext.split(';')
+                        .filter(|ext| !ext.is_empty())
+                        .map(|ext| ext.to_string()),
+                );
             }
             if !exts.is_empty() {
                 return Some(QueryFunction::Ext(exts));
@@ -282,12 +911,12 @@ fn parse_function(lexer: &mut lexer::QueryLexer, name: &str) -> Option<QueryFunc
 
 // Parses a modifier like case:query or file:query
 fn parse_modifier(
-    ident: &String,
+    ident: &str,
     modifiers: QueryModifiersTracking,
 ) -> Option<QueryModifiersTracking> {
     let mut modifiers = modifiers;
 
-    let lower_ident = ident.to_lowercase();
+    let lower_ident = fold_keyword(ident);
     match lower_ident.as_str() {
         "case" => modifiers.case_sensitive = true,
         "nocase" => modifiers.case_sensitive = false,
@@ -324,7 +953,11 @@ fn parse_modifier(
 // Parses a single condition, which could be a function, a text query, or a negation
 // e.g. size:>1000, "example.txt", file:case:"ExAmplE.txt", !ext:tmp
 // extreme cases: !case:!file:"!"tmp  // double negation with query !tmp
-fn parse_condition(lexer: &mut lexer::QueryLexer, modifiers: QueryModifiersTracking) -> QueryExpr {
+fn parse_condition(
+    lexer: &mut lexer::QueryLexer,
+    modifiers: QueryModifiersTracking,
+    options: ParseOptions,
+) -> Result<QueryExpr, QueryError> {
     if let Some(token) = lexer.next_token() {
         let mut search_text = token.to_string();
         match token {
@@ -334,13 +967,30 @@ fn parse_condition(lexer: &mut lexer::QueryLexer, modifiers: QueryModifiersTrack
                     // Consume Colon
                     lexer.next_token();
                     // Try parse function
-                    if let Some(func) = parse_function(lexer, &ident) {
-                        return QueryExpr::Function(func);
-                    } else if let Some(new_modifiers) =
-                        parse_modifier(&ident, modifiers)
-                    {
+                    if let Some(func) = parse_function(lexer, ident, modifiers, options) {
+                        if let QueryFunction::ParentAt(_, folder, _) = &func
+                            && folder.contains(['/', '\\'])
+                        {
+                            return Err(QueryError::SeparatorInParentAt(folder.clone()));
+                        }
+                        return Ok(QueryExpr::Function(func));
+                    } else if let Some(new_modifiers) = parse_modifier(ident, modifiers) {
+                        // A modifier without text like `folder:` selects all folders
+                        if matches!(
+                            lexer.peek_token(),
+                            None | Some(
+                                lexer::QueryToken::Whitespace
+                                    | lexer::QueryToken::Or
+                                    | lexer::QueryToken::And
+                                    | lexer::QueryToken::GreaterThan
+                            )
+                        ) {
+                            let literal =
+                                create_query_literal(String::new(), new_modifiers, options)?;
+                            return Ok(QueryExpr::Literal(literal));
+                        }
                         // If it's a modifier, update modifiers and continue
-                        return parse_condition(lexer, new_modifiers);
+                        return parse_condition(lexer, new_modifiers, options);
                     } else {
                         // Otherwise, treat as text query
                         // we consumed the Colon, so include it in the search text
@@ -351,15 +1001,28 @@ fn parse_condition(lexer: &mut lexer::QueryLexer, modifiers: QueryModifiersTrack
                 // Otherwise, treat as text query
             }
             lexer::QueryToken::Not => {
-                let sub_expr = parse_condition(lexer, modifiers);
-                return QueryExpr::Not(Box::new(sub_expr));
+                let sub_expr = parse_condition(lexer, modifiers, options)?;
+                return Ok(QueryExpr::Not(Box::new(sub_expr)));
             }
             lexer::QueryToken::Whitespace => {
                 unreachable!("Whitespace should be handled in parse_expression");
             }
             lexer::QueryToken::LessThan => {
                 // start of block
-                return parse_expression(lexer, modifiers);
+                return parse_expression(lexer, modifiers, options);
+            }
+            // =report.txt is short for wfn:report.txt, a lone = is still text
+            lexer::QueryToken::Equal
+                if matches!(
+                    lexer.peek_token(),
+                    Some(lexer::QueryToken::Ident(_) | lexer::QueryToken::StrLit(_))
+                ) =>
+            {
+                let new_modifiers = QueryModifiersTracking {
+                    whole_filename: true,
+                    ..modifiers
+                };
+                return parse_condition(lexer, new_modifiers, options);
             }
             _ => {
                 // Otherwise, treat as text query
@@ -369,7 +1032,9 @@ fn parse_condition(lexer: &mut lexer::QueryLexer, modifiers: QueryModifiersTrack
         
         while let Some(next_token) = lexer.peek_token() {
             match next_token {
-                lexer::QueryToken::Whitespace | lexer::QueryToken::Or => break,
+                lexer::QueryToken::Whitespace | lexer::QueryToken::Or | lexer::QueryToken::And => {
+                    break;
+                }
                 _ => {
                     // Consume token and append to search_text
                     if let Some(t) = lexer.next_token() {
@@ -378,11 +1043,18 @@ fn parse_condition(lexer: &mut lexer::QueryLexer, modifiers: QueryModifiersTrack
                 }
             }
         }
-        let literal = create_query_literal(search_text, modifiers);
-        return QueryExpr::Literal(literal);
+        // Only unquoted terms without modifiers, so "2023" or file:2023 searches the text
+        if matches!(token, lexer::QueryToken::Ident(_))
+            && modifiers == QueryModifiersTracking::default()
+            && let Some(function) = parse_bare_number(&search_text, options)
+        {
+            return Ok(QueryExpr::Function(function));
+        }
+        let literal = create_query_literal(search_text, modifiers, options)?;
+        return Ok(QueryExpr::Literal(literal));
     }
     // Default to empty text query if nothing matched
-    QueryExpr::Literal(QueryLiteral::Text(TextQuery {
+    Ok(QueryExpr::Literal(QueryLiteral::Text(TextQuery {
         text: "".to_string(),
         case_sensitive: false,
         diacritics_sensitive: false,
@@ -391,10 +1063,14 @@ fn parse_condition(lexer: &mut lexer::QueryLexer, modifiers: QueryModifiersTrack
         match_path: false,
         whole_filename: false,
         whole_word: false,
-    }))
+    })))
 }
 
-fn parse_expression(lexer: &mut lexer::QueryLexer, modifiers: QueryModifiersTracking) -> QueryExpr {
+fn parse_expression(
+    lexer: &mut lexer::QueryLexer,
+    modifiers: QueryModifiersTracking,
+    options: ParseOptions,
+) -> Result<QueryExpr, QueryError> {
     let mut exprs = Vec::new();
     while let Some(token) = lexer.peek_token() {
         match token {
@@ -405,9 +1081,30 @@ fn parse_expression(lexer: &mut lexer::QueryLexer, modifiers: QueryModifiersTrac
             lexer::QueryToken::Or => {
                 // Parse next condition and combine with Or
                 lexer.next_token(); // consume Or
-                let right_expr = parse_expression(lexer, modifiers);
-                let left_expr = exprs_to_and(exprs);
-                return QueryExpr::Or(Box::new(left_expr), Box::new(right_expr));
+                let right_expr = parse_expression(lexer, modifiers, options)?;
+                let left_expr = combine_exprs(exprs, options);
+                return Ok(QueryExpr::Or(Box::new(left_expr), Box::new(right_expr)));
+            }
+            lexer::QueryToken::And => {
+                // Explicit AND binds the previous and the next condition, regardless of the
+                // space semantics. It binds tighter than OR, so a & b | c is (a AND b) OR c
+                lexer.next_token(); // consume And
+                while let Some(lexer::QueryToken::Whitespace) = lexer.peek_token() {
+                    lexer.next_token();
+                }
+                match lexer.peek_token() {
+                    None | Some(lexer::QueryToken::Or | lexer::QueryToken::GreaterThan) => {}
+                    Some(_) => {
+                        let right_expr = parse_condition(lexer, modifiers, options)?;
+                        let expr = match exprs.pop() {
+                            Some(left_expr) => {
+                                QueryExpr::And(Box::new(left_expr), Box::new(right_expr))
+                            }
+                            None => right_expr, // Leading &, nothing to combine with
+                        };
+                        exprs.push(expr);
+                    }
+                }
             }
             lexer::QueryToken::GreaterThan => {
                 // end of block
@@ -415,18 +1112,25 @@ fn parse_expression(lexer: &mut lexer::QueryLexer, modifiers: QueryModifiersTrac
                 break;
             }
             _ => {
-                exprs.push(parse_condition(lexer, modifiers));
+                exprs.push(parse_condition(lexer, modifiers, options)?);
             }
         }
     }
-    exprs_to_and(exprs)
+    Ok(combine_exprs(exprs, options))
+}
+
+pub fn parse_query(input: &str) -> Result<QueryExpr, QueryError> {
+    parse_query_with_options(input, ParseOptions::default())
 }
 
-pub fn parse_query(input: &str) -> QueryExpr {
+pub fn parse_query_with_options(
+    input: &str,
+    options: ParseOptions,
+) -> Result<QueryExpr, QueryError> {
     let trimmed = input.trim();
     if trimmed.is_empty() {
         // Return a default empty query or handle as needed
-        return QueryExpr::Literal(QueryLiteral::Text(TextQuery {
+        return Ok(QueryExpr::Literal(QueryLiteral::Text(TextQuery {
             text: "".to_string(),
             case_sensitive: false,
             diacritics_sensitive: false,
@@ -435,10 +1139,871 @@ pub fn parse_query(input: &str) -> QueryExpr {
             match_path: false,
             whole_filename: false,
             whole_word: false,
-        }));
+        })));
     }
 
     let mut lexer = lexer::QueryLexer::new(input);
     let modifiers = QueryModifiersTracking::default();
-    parse_expression(&mut lexer, modifiers)
+    let expr = parse_expression(&mut lexer, modifiers, options)?;
+    if has_nested_dupe(&expr, false) {
+        return Err(QueryError::NestedDupe);
+    }
+    Ok(expr)
+}
+
+// Whether a dupe: function is negated or inside an OR, where it can't apply to the whole result
+fn has_nested_dupe(expr: &QueryExpr, nested: bool) -> bool {
+    match expr {
+        QueryExpr::Function(QueryFunction::Dupe(_)) => nested,
+        QueryExpr::And(left, right) => {
+            has_nested_dupe(left, nested) || has_nested_dupe(right, nested)
+        }
+        QueryExpr::Or(left, right) => has_nested_dupe(left, true) || has_nested_dupe(right, true),
+        QueryExpr::Not(expr) => has_nested_dupe(expr, true),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(expr: &QueryExpr) -> &str {
+        match expr {
+            QueryExpr::Literal(QueryLiteral::Text(query)) => &query.text,
+            _ => panic!("Expected a text literal, got {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn test_space_semantics() {
+        let and = parse_query("foo bar").unwrap();
+        let QueryExpr::And(left, right) = &and else {
+            panic!("Expected And, got {:?}", and);
+        };
+        assert_eq!((text(left), text(right)), ("foo", "bar"));
+
+        let options = ParseOptions {
+            space_semantics: SpaceSemantics::Or,
+            ..Default::default()
+        };
+        let or = parse_query_with_options("foo bar", options).unwrap();
+        let QueryExpr::Or(left, right) = &or else {
+            panic!("Expected Or, got {:?}", or);
+        };
+        assert_eq!((text(left), text(right)), ("foo", "bar"));
+
+        // Explicit | stays an OR, and blocks follow the same semantics
+        let expr = parse_query_with_options("a b | <c d>", options).unwrap();
+        let QueryExpr::Or(left, right) = &expr else {
+            panic!("Expected Or, got {:?}", expr);
+        };
+        assert!(matches!(**left, QueryExpr::Or(_, _)));
+        assert!(matches!(**right, QueryExpr::Or(_, _)));
+    }
+
+    fn function(expr: QueryExpr) -> QueryFunction {
+        match expr {
+            QueryExpr::Function(function) => function,
+            _ => panic!("Expected a function, got {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn test_quoted_function_values() {
+        let expected = QueryFunction::Size(QueryCmp::Ge, 1000);
+        assert_eq!(function(parse_query("size:>=1000").unwrap()), expected);
+        assert_eq!(function(parse_query("size:\">=1000\"").unwrap()), expected);
+        assert_eq!(function(parse_query("size:>=\"1000\"").unwrap()), expected);
+        assert_eq!(
+            function(parse_query("size:\" >= 1000 \"").unwrap()),
+            expected
+        );
+        assert_eq!(
+            function(parse_query("size:\"1000\"").unwrap()),
+            QueryFunction::Size(QueryCmp::Eq, 1000)
+        );
+        assert_eq!(
+            function(parse_query("size:\"<5\"").unwrap()),
+            QueryFunction::Size(QueryCmp::Lt, 5)
+        );
+
+        let QueryFunction::DateModified(cmp, date) =
+            function(parse_query("datemodified:\"2023-01-01..2023-12-31\"").unwrap())
+        else {
+            panic!("Expected a date modified function");
+        };
+        assert_eq!(cmp, QueryCmp::Range);
+        let QueryDate::Range(start, end) = QueryDate::from("2023") else {
+            panic!("Expected a date range");
+        };
+        assert_eq!(date, QueryDate::Range(start, end));
+        // Unquoted ranges work the same
+        assert_eq!(
+            function(parse_query("dm:2023-01-01..2023-12-31").unwrap()),
+            QueryFunction::DateModified(QueryCmp::Range, QueryDate::Range(start, end))
+        );
+        assert_eq!(
+            function(parse_query("dc:\"<2023\"").unwrap()),
+            QueryFunction::DateCreated(QueryCmp::Lt, QueryDate::Range(start, end))
+        );
+
+        assert_eq!(
+            function(parse_query("parent:\"Program Files\"").unwrap()),
+            QueryFunction::Parent("Program Files".to_string(), false)
+        );
+    }
+
+    #[test]
+    fn test_keyword_case_folding() {
+        for query in [
+            "size:1000",
+            "SIZE:1000",
+            "Size:1000",
+            "sIzE:1000",
+            "S\u{130}ZE:1000",
+        ] {
+            assert_eq!(
+                function(parse_query(query).unwrap()),
+                QueryFunction::Size(QueryCmp::Eq, 1000),
+                "query '{}'",
+                query
+            );
+        }
+        assert_eq!(
+            function(parse_query("\u{131}nfolder:Docs").unwrap()),
+            QueryFunction::Parent("Docs".to_string(), false)
+        );
+        assert!(matches!(
+            function(parse_query("DateModified:2023").unwrap()),
+            QueryFunction::DateModified(QueryCmp::Eq, _)
+        ));
+
+        // Modifiers fold the same way, while the search term keeps its casing
+        for query in ["case:ReadMe", "CASE:ReadMe", "Case:ReadMe"] {
+            let QueryExpr::Literal(QueryLiteral::Text(text)) = parse_query(query).unwrap() else {
+                panic!("Expected a text literal for '{}'", query);
+            };
+            assert_eq!(text.text, "ReadMe");
+            assert!(text.case_sensitive);
+        }
+        let QueryExpr::Literal(QueryLiteral::Text(text)) =
+            parse_query("WFN:\u{130}stanbul").unwrap()
+        else {
+            panic!("Expected a text literal");
+        };
+        assert_eq!(text.text, "\u{130}stanbul");
+        assert!(text.whole_filename);
+
+        // Other non-ASCII letters don't fold into keywords, e.g. the Kelvin sign or long s
+        assert_eq!(text_of("\u{17f}ize:1000"), "\u{17f}ize:1000");
+        assert_eq!(text_of("\u{212a}ase:x"), "\u{212a}ase:x");
+    }
+
+    fn text_of(query: &str) -> String {
+        text(&parse_query(query).unwrap()).to_string()
+    }
+
+    #[test]
+    fn test_double_operators() {
+        // && and || parse exactly like & and |
+        let render = |query| parse_query(query).unwrap().to_string();
+        assert_eq!(render("a && b || c"), render("a & b | c"));
+        assert_eq!(render("a&&b || !c"), render("a&&b | !c"));
+        // Inside an identifier they are part of the text
+        assert_eq!(text_of("a||b"), "a||b");
+        let expr = parse_query("<a || b > && !c").unwrap();
+        let QueryExpr::And(left, right) = &expr else {
+            panic!("Expected And, got {:?}", expr);
+        };
+        assert!(matches!(**left, QueryExpr::Or(_, _)));
+        assert!(matches!(**right, QueryExpr::Not(_)));
+    }
+
+    #[test]
+    fn test_modifiers_without_text() {
+        // The modifiers are kept on an empty text, not dropped
+        for query in [
+            "folder:",
+            "folder: | file:",
+            "<folder:> ext:txt",
+            "!file: size:>1",
+        ] {
+            let rendered = parse_query(query).unwrap().to_string();
+            assert!(
+                rendered.contains("folder:\"\"") || rendered.contains("file:\"\""),
+                "{}",
+                rendered
+            );
+        }
+        let expr = parse_query("folder: ext:txt").unwrap();
+        let QueryExpr::And(left, _) = &expr else {
+            panic!("Expected And, got {:?}", expr);
+        };
+        let QueryExpr::Literal(QueryLiteral::Text(text)) = &**left else {
+            panic!("Expected text, got {:?}", left);
+        };
+        assert!(text.folder_only && text.text.is_empty());
+    }
+
+    #[test]
+    fn test_equal_prefix() {
+        for query in ["=report.txt", "=\"report.txt\"", "wfn:report.txt"] {
+            let QueryExpr::Literal(QueryLiteral::Text(text)) = parse_query(query).unwrap() else {
+                panic!("Expected a text literal for '{}'", query);
+            };
+            assert_eq!(text.text, "report.txt");
+            assert!(text.whole_filename, "{}", query);
+        }
+        let QueryExpr::Literal(QueryLiteral::Text(text)) = parse_query("report.txt").unwrap()
+        else {
+            panic!("Expected a text literal");
+        };
+        assert!(!text.whole_filename);
+        // Without text after it the = is searched for
+        assert_eq!(text_of("="), "=");
+    }
+
+    #[test]
+    fn test_explicit_and() {
+        // a & b | c resolves as (a AND b) OR c
+        let expr = parse_query("a & b | c").unwrap();
+        let QueryExpr::Or(left, right) = &expr else {
+            panic!("Expected Or, got {:?}", expr);
+        };
+        let QueryExpr::And(a, b) = &**left else {
+            panic!("Expected And, got {:?}", left);
+        };
+        assert_eq!((text(a), text(b), text(right)), ("a", "b", "c"));
+
+        // c | a & b resolves as c OR (a AND b)
+        let expr = parse_query("c | a&b").unwrap();
+        let QueryExpr::Or(left, right) = &expr else {
+            panic!("Expected Or, got {:?}", expr);
+        };
+        assert_eq!(text(left), "c");
+        // The & inside an identifier is part of the text, only a leading & is an operator
+        assert_eq!(text(right), "a&b");
+        let expr = parse_query("c | \"a\"&b").unwrap();
+        let QueryExpr::Or(_, right) = &expr else {
+            panic!("Expected Or, got {:?}", expr);
+        };
+        assert!(matches!(**right, QueryExpr::And(_, _)));
+
+        // With OR space semantics, & still means AND and binds tighter than the spaces
+        let options = ParseOptions {
+            space_semantics: SpaceSemantics::Or,
+            ..Default::default()
+        };
+        let expr = parse_query_with_options("a b & c", options).unwrap();
+        let QueryExpr::Or(left, right) = &expr else {
+            panic!("Expected Or, got {:?}", expr);
+        };
+        assert_eq!(text(left), "a");
+        let QueryExpr::And(b, c) = &**right else {
+            panic!("Expected And, got {:?}", right);
+        };
+        assert_eq!((text(b), text(c)), ("b", "c"));
+
+        // Chained, leading and trailing operators
+        let expr = parse_query("a & b & c").unwrap();
+        let QueryExpr::And(left, c) = &expr else {
+            panic!("Expected And, got {:?}", expr);
+        };
+        assert!(matches!(**left, QueryExpr::And(_, _)));
+        assert_eq!(text(c), "c");
+        assert_eq!(text(&parse_query("& a").unwrap()), "a");
+        assert_eq!(text(&parse_query("a &").unwrap()), "a");
+    }
+
+    #[test]
+    fn test_regex_size_limit() {
+        // Counted repetitions of a unicode class compile to a huge program
+        let query = "regex:\\w{1000}\\w{1000}";
+        let Err(QueryError::RegexTooLarge { pattern, limit }) = parse_query(query) else {
+            panic!("Expected the oversized regex to be rejected");
+        };
+        assert_eq!(pattern, "\\w{1000}\\w{1000}");
+        assert_eq!(limit, DEFAULT_REGEX_SIZE_LIMIT);
+
+        // The limit is configurable
+        let options = ParseOptions {
+            regex_size_limit: 100,
+            ..Default::default()
+        };
+        assert!(matches!(
+            parse_query_with_options("regex:\\w{10}", options),
+            Err(QueryError::RegexTooLarge { limit: 100, .. })
+        ));
+        assert!(parse_query("regex:\\w{10}").is_ok());
+        assert!(parse_query("\\w{1000}\\w{1000}").is_ok()); // Not a regex query
+
+        // Invalid patterns are errors too instead of matching everything
+        assert!(matches!(
+            parse_query("regex:foo(").unwrap_err(),
+            QueryError::InvalidRegex { .. }
+        ));
+    }
+
+    #[test]
+    fn test_parent_at() {
+        assert_eq!(
+            function(parse_query("parentat:2:src").unwrap()),
+            QueryFunction::ParentAt(2, "src".to_string(), false)
+        );
+        assert_eq!(
+            function(parse_query("PARENTAT:1:\"My Folder\"").unwrap()),
+            QueryFunction::ParentAt(1, "My Folder".to_string(), false)
+        );
+        // The level has to be a positive number
+        assert!(matches!(
+            parse_query("parentat:0:src").unwrap(),
+            QueryExpr::Literal(_)
+        ));
+        assert!(matches!(
+            parse_query("parentat:x:src").unwrap(),
+            QueryExpr::Literal(_)
+        ));
+        // The folder is a single name, a path would never match
+        for query in ["parentat:2:a/src", "parentat:1:\"a\\\\src\""] {
+            assert!(
+                matches!(parse_query(query), Err(QueryError::SeparatorInParentAt(_))),
+                "{}",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn test_component() {
+        assert_eq!(
+            function(parse_query("component:share").unwrap()),
+            QueryFunction::Component("share".to_string())
+        );
+        assert_eq!(
+            function(parse_query("Component:\"my share\"").unwrap()),
+            QueryFunction::Component("my share".to_string())
+        );
+    }
+
+    #[test]
+    fn test_nosubfolders() {
+        assert_eq!(
+            function(parse_query("nosubfolders:src").unwrap()),
+            QueryFunction::ParentDirectOnly("src".to_string(), false)
+        );
+        assert_eq!(
+            function(parse_query("NoSubfolders:\"My Folder\"").unwrap()),
+            QueryFunction::ParentDirectOnly("My Folder".to_string(), false)
+        );
+        assert_eq!(
+            function(parse_query("infolder:src").unwrap()),
+            QueryFunction::Parent("src".to_string(), false)
+        );
+        // case: is kept for the folder name
+        assert_eq!(
+            function(parse_query("case:parent:Src").unwrap()),
+            QueryFunction::Parent("Src".to_string(), true)
+        );
+        assert_eq!(
+            function(parse_query("case:nosubfolders:Src").unwrap()),
+            QueryFunction::ParentDirectOnly("Src".to_string(), true)
+        );
+    }
+
+    #[test]
+    fn test_dupe_and_empty() {
+        let dupe = |name, size| QueryFunction::Dupe(DupeKey { name, size });
+        assert_eq!(function(parse_query("dupe:").unwrap()), dupe(true, false));
+        assert_eq!(
+            function(parse_query("dupe:name").unwrap()),
+            dupe(true, false)
+        );
+        assert_eq!(
+            function(parse_query("dupe:size").unwrap()),
+            dupe(false, true)
+        );
+        assert_eq!(
+            function(parse_query("dupe:name;size").unwrap()),
+            dupe(true, true)
+        );
+        assert_eq!(
+            function(parse_query("DUPE:Size;Name").unwrap()),
+            dupe(true, true)
+        );
+        assert!(matches!(
+            parse_query("dupe:color").unwrap(),
+            QueryExpr::Literal(_)
+        ));
+
+        // Without an argument the next term is a separate condition
+        let expr = parse_query("dupe: ext:jpg").unwrap();
+        let QueryExpr::And(left, right) = expr else {
+            panic!("Expected And");
+        };
+        assert_eq!(function(*left), dupe(true, false));
+        assert_eq!(
+            function(*right),
+            QueryFunction::Ext(vec!["jpg".to_string()])
+        );
+
+        assert_eq!(
+            function(parse_query("empty:").unwrap()),
+            QueryFunction::Empty
+        );
+
+        // Negated or in an OR it can't filter the whole result
+        for query in [
+            "!dupe:size",
+            "a | dupe:size",
+            "ext:jpg !<dupe: a>",
+            "<dupe: | a> b",
+        ] {
+            assert_eq!(
+                parse_query(query).unwrap_err(),
+                QueryError::NestedDupe,
+                "{}",
+                query
+            );
+        }
+        assert!(parse_query("<a dupe:size> b").is_ok());
+    }
+
+    #[test]
+    fn test_ext_list() {
+        let exts = |exts: &[&str]| QueryFunction::Ext(exts.iter().map(|e| e.to_string()).collect());
+        assert_eq!(function(parse_query("ext:jpg").unwrap()), exts(&["jpg"]));
+        assert_eq!(
+            function(parse_query("ext:jpg;png").unwrap()),
+            exts(&["jpg", "png"])
+        );
+        assert_eq!(
+            function(parse_query("ext:jpg png  gif").unwrap()),
+            exts(&["jpg", "png", "gif"])
+        );
+
+        // The list stops at the next function, modifier or operator
+        let QueryExpr::And(left, right) = parse_query("ext:jpg png size:>10").unwrap() else {
+            panic!("Expected And");
+        };
+        assert_eq!(function(*left), exts(&["jpg", "png"]));
+        assert_eq!(function(*right), QueryFunction::Size(QueryCmp::Gt, 10));
+        let QueryExpr::Or(left, right) = parse_query("ext:jpg | case:x").unwrap() else {
+            panic!("Expected Or");
+        };
+        assert_eq!(function(*left), exts(&["jpg"]));
+        assert_eq!(text(&right), "x");
+        assert!(matches!(
+            parse_query("ext:jpg !png").unwrap(),
+            QueryExpr::And(_, _)
+        ));
+    }
+
+    #[test]
+    fn test_range_brackets() {
+        let size = |query| function(parse_query(query).unwrap());
+        assert_eq!(
+            size("size:1000..2000"),
+            QueryFunction::SizeRange(1000, 2000)
+        );
+        assert_eq!(
+            size("size:[1000..2000]"),
+            QueryFunction::SizeRange(1000, 2000)
+        );
+        assert_eq!(
+            size("size:[1000..2000)"),
+            QueryFunction::SizeRange(1000, 1999)
+        );
+        assert_eq!(
+            size("size:(1000..2000]"),
+            QueryFunction::SizeRange(1001, 2000)
+        );
+        assert_eq!(
+            size("size:(1000..2000)"),
+            QueryFunction::SizeRange(1001, 1999)
+        );
+        assert_eq!(
+            size("size:\"[1000..2000)\""),
+            QueryFunction::SizeRange(1000, 1999)
+        );
+        assert_eq!(size("size:[0..0)"), QueryFunction::SizeRange(1, 0));
+
+        let QueryDate::Range(start_2023, end_2023) = QueryDate::from("2023") else {
+            panic!("Expected a date range");
+        };
+        let QueryDate::Range(start_2024, end_2024) = QueryDate::from("2024") else {
+            panic!("Expected a date range");
+        };
+        let date = |query| match function(parse_query(query).unwrap()) {
+            QueryFunction::DateModified(QueryCmp::Range, QueryDate::Range(start, end)) => {
+                (start, end)
+            }
+            other => panic!("Expected a date range, got {:?}", other),
+        };
+        assert_eq!(date("dm:2023..2024"), (start_2023, end_2024));
+        assert_eq!(date("dm:[2023..2024]"), (start_2023, end_2024));
+        assert_eq!(date("dm:[2023..2024)"), (start_2023, start_2024 - 1));
+        assert_eq!(date("dm:(2023..2024]"), (end_2023 + 1, end_2024));
+        assert_eq!(date("dm:(2023..2024)"), (end_2023 + 1, start_2024 - 1));
+    }
+
+    #[test]
+    fn test_open_date_ranges() {
+        let date = |query| function(parse_query(query).unwrap());
+        let QueryDate::Range(start, end) = QueryDate::from("2023-01-01") else {
+            panic!("Expected a date range");
+        };
+        let new_year = QueryDate::Range(start, end);
+        assert_eq!(
+            date("dc:2023-01-01.."),
+            QueryFunction::DateCreated(QueryCmp::Ge, new_year.clone())
+        );
+        assert_eq!(
+            date("dc:..2023-01-01"),
+            QueryFunction::DateCreated(QueryCmp::Le, new_year.clone())
+        );
+        assert_eq!(
+            date("dm:(2023-01-01.."),
+            QueryFunction::DateModified(QueryCmp::Gt, new_year.clone())
+        );
+        assert_eq!(
+            date("dm:\"..2023-01-01)\""),
+            QueryFunction::DateModified(QueryCmp::Lt, new_year)
+        );
+        // Both ends open isn't a date
+        assert_eq!(
+            date("dm:.."),
+            QueryFunction::DateModified(QueryCmp::Eq, QueryDate::Range(0, 0))
+        );
+    }
+
+    #[test]
+    fn test_date_comparison() {
+        let cmdate = |query| function(parse_query(query).unwrap());
+        let created_vs_modified = QueryFunction::CreatedVsModified;
+        assert_eq!(cmdate("clockskew:"), created_vs_modified(QueryCmp::Gt));
+        assert_eq!(
+            cmdate("cmdate:created>modified"),
+            created_vs_modified(QueryCmp::Gt)
+        );
+        assert_eq!(
+            cmdate("cmdate:Modified>Created"),
+            created_vs_modified(QueryCmp::Lt)
+        );
+        assert_eq!(
+            cmdate("cmdate:modified<=created"),
+            created_vs_modified(QueryCmp::Ge)
+        );
+        assert_eq!(
+            cmdate("cmdate:created=modified"),
+            created_vs_modified(QueryCmp::Eq)
+        );
+        assert_eq!(
+            cmdate("cmdate:\"created < modified\""),
+            created_vs_modified(QueryCmp::Lt)
+        );
+        assert!(parse_date_comparison("created>size").is_none());
+        assert!(parse_date_comparison("created").is_none());
+    }
+
+    #[test]
+    fn test_time_of_day() {
+        let time_of_day = |query| match function(parse_query(query).unwrap()) {
+            QueryFunction::TimeOfDay(time_of_day) => (time_of_day.start, time_of_day.end),
+            other => panic!("Expected a time of day, got {:?}", other),
+        };
+        let hour = 3600;
+        assert_eq!(
+            time_of_day("timeofday:9:00..17:00"),
+            (9 * hour, 17 * hour + 59)
+        );
+        assert_eq!(time_of_day("tod:9am..5pm"), (9 * hour, 18 * hour - 1));
+        assert_eq!(
+            time_of_day("timeofday:\"9:00..17:00\""),
+            (9 * hour, 17 * hour + 59)
+        );
+        assert_eq!(time_of_day("timeofday:[9..17)"), (9 * hour, 17 * hour - 1));
+        assert_eq!(
+            time_of_day("timeofday:22:00..02:00"),
+            (22 * hour, 2 * hour + 59)
+        );
+        assert_eq!(time_of_day("TimeOfDay:12am"), (0, hour - 1));
+        let second = 12 * hour + 30 * 60 + 15;
+        assert_eq!(time_of_day("timeofday:12:30:15"), (second, second));
+        // Equal ends never wrap around
+        assert_eq!(time_of_day("tod:9..9"), (9 * hour, 10 * hour - 1));
+        assert_eq!(time_of_day("tod:[9..9)"), (9 * hour, 10 * hour - 1));
+        assert_eq!(time_of_day("tod:(12:30:15..12:30:15]"), (second, second));
+
+        // The argument ends at whitespace, the rest is another condition
+        let query = parse_query("timeofday:9:00..17:00 report").unwrap();
+        let QueryExpr::And(left, right) = query else {
+            panic!("Expected And");
+        };
+        assert!(matches!(
+            *left,
+            QueryExpr::Function(QueryFunction::TimeOfDay(_))
+        ));
+        assert!(matches!(*right, QueryExpr::Literal(_)));
+
+        for query in [
+            "timeofday:25:00",
+            "timeofday:9:60",
+            "timeofday:13pm",
+            "timeofday:",
+            "timeofday:(9:00:00..9:00:01)",
+        ] {
+            assert!(
+                !matches!(
+                    parse_query(query).unwrap(),
+                    QueryExpr::Function(QueryFunction::TimeOfDay(_))
+                ),
+                "query '{}'",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn test_hardlinks_and_inode() {
+        let hardlinks = |query| function(parse_query(query).unwrap());
+        assert_eq!(
+            hardlinks("hardlinks:"),
+            QueryFunction::Hardlinks(QueryCmp::Gt, 1)
+        );
+        assert_eq!(
+            hardlinks("hardlinks:>=3"),
+            QueryFunction::Hardlinks(QueryCmp::Ge, 3)
+        );
+        assert_eq!(
+            hardlinks("nlink:2"),
+            QueryFunction::Hardlinks(QueryCmp::Eq, 2)
+        );
+        assert_eq!(
+            hardlinks("inode:2049:12345"),
+            QueryFunction::Inode(2049, 12345)
+        );
+        // Without the device the inode number is ambiguous
+        assert!(matches!(
+            parse_query("inode:12345").unwrap(),
+            QueryExpr::Literal(_)
+        ));
+
+        let expr = parse_query("hardlinks: ext:txt").unwrap();
+        let QueryExpr::And(left, _) = expr else {
+            panic!("Expected And");
+        };
+        assert_eq!(function(*left), QueryFunction::Hardlinks(QueryCmp::Gt, 1));
+    }
+
+    #[test]
+    fn test_name_length() {
+        let namelen = |query| function(parse_query(query).unwrap());
+        assert_eq!(
+            namelen("namelen:>100"),
+            QueryFunction::NameLength(QueryCmp::Gt, 100)
+        );
+        assert_eq!(
+            namelen("namelen:<=5"),
+            QueryFunction::NameLength(QueryCmp::Le, 5)
+        );
+        assert_eq!(
+            namelen("NameLen:8"),
+            QueryFunction::NameLength(QueryCmp::Eq, 8)
+        );
+    }
+
+    #[test]
+    fn test_children() {
+        let children = |query| function(parse_query(query).unwrap());
+        assert_eq!(
+            children("children:>10000"),
+            QueryFunction::Children(QueryCmp::Gt, 10000)
+        );
+        assert_eq!(
+            children("children:0"),
+            QueryFunction::Children(QueryCmp::Eq, 0)
+        );
+    }
+
+    #[test]
+    fn test_mime() {
+        let mime = |query| function(parse_query(query).unwrap());
+        assert_eq!(
+            mime("mime:Image/PNG"),
+            QueryFunction::Mime("image/png".into())
+        );
+        assert_eq!(mime("mime:image/*"), QueryFunction::Mime("image/*".into()));
+    }
+
+    #[test]
+    fn test_character_classes() {
+        let has = |query| function(parse_query(query).unwrap());
+        assert_eq!(has("hasdigits:"), QueryFunction::HasDigits);
+        assert_eq!(has("HasSpaces:"), QueryFunction::HasSpaces);
+        assert_eq!(has("hasunicode:"), QueryFunction::HasUnicode);
+    }
+
+    #[test]
+    fn test_numrange() {
+        let range = |query| match function(parse_query(query).unwrap()) {
+            QueryFunction::NumRange(range) => range,
+            other => panic!("Expected a number range, got {:?}", other),
+        };
+        let frames = range("numrange:IMG_0001..IMG_0100");
+        assert_eq!(
+            (frames.prefix.as_str(), frames.suffix.as_str()),
+            ("IMG_", "")
+        );
+        assert_eq!((frames.start, frames.end, frames.width), (1, 100, Some(4)));
+        let lowercase = NumRange {
+            prefix: "img_".to_string(),
+            ..frames
+        };
+        assert_eq!(range("NumRange:img_0001..0100"), lowercase);
+        let unpadded = range("numrange:\"take 1.wav..take 20.wav\"");
+        assert_eq!(
+            (unpadded.start, unpadded.end, unpadded.width),
+            (1, 20, None)
+        );
+        assert_eq!(unpadded.suffix, ".wav");
+
+        // The text around the numbers has to match, and the range can't be reversed
+        for query in [
+            "numrange:IMG_0001..DSC_0100",
+            "numrange:IMG_001..IMG_0100",
+            "numrange:IMG_0100..IMG_0001",
+            "numrange:IMG..IMG",
+        ] {
+            assert!(
+                !matches!(parse_query(query).unwrap(), QueryExpr::Function(_)),
+                "{}",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn test_size_units_and_approximate() {
+        let size = |query| function(parse_query(query).unwrap());
+        assert_eq!(
+            size("size:10kb"),
+            QueryFunction::Size(QueryCmp::Eq, 10 * 1024)
+        );
+        assert_eq!(
+            size("size:>1.5MB"),
+            QueryFunction::Size(QueryCmp::Gt, 1_572_864)
+        );
+        assert_eq!(size("size:2gb"), QueryFunction::Size(QueryCmp::Eq, 2 << 30));
+        assert_eq!(size("size:512b"), QueryFunction::Size(QueryCmp::Eq, 512));
+        assert_eq!(
+            size("size:1kb..1mb"),
+            QueryFunction::SizeRange(1024, 1 << 20)
+        );
+
+        // 4mb is 4194304 bytes, 10% of it 419430.4 rounded down
+        let band = QueryFunction::SizeRange(4_194_304 - 419_430, 4_194_304 + 419_430);
+        assert_eq!(size("size:~4mb"), band);
+        assert_eq!(size("size:≈4mb"), band);
+        assert_eq!(size("size:\"~4mb\""), band);
+        assert_eq!(size("size:~0"), QueryFunction::SizeRange(0, 0));
+
+        let options = ParseOptions {
+            size_tolerance_percent: 50,
+            ..Default::default()
+        };
+        assert_eq!(
+            function(parse_query_with_options("size:~100", options).unwrap()),
+            QueryFunction::SizeRange(50, 150)
+        );
+        assert!(matches!(
+            parse_query("size:~lots").unwrap(),
+            QueryExpr::Literal(_)
+        ));
+    }
+
+    #[test]
+    fn test_bare_numbers() {
+        let parse = |query, bare_numbers| {
+            let options = ParseOptions {
+                bare_numbers,
+                ..Default::default()
+            };
+            parse_query_with_options(query, options).unwrap()
+        };
+        let QueryDate::Range(start, end) = QueryDate::from("2023") else {
+            panic!("Expected a date range");
+        };
+        let year = QueryFunction::DateModified(QueryCmp::Eq, QueryDate::Range(start, end));
+
+        // Text by default
+        assert_eq!(text(&parse("2023", BareNumbers::Text)), "2023");
+        assert_eq!(text(&parse("1000", BareNumbers::Text)), "1000");
+
+        assert_eq!(function(parse("2023", BareNumbers::DateModified)), year);
+        assert_eq!(
+            function(parse("2023..2023", BareNumbers::DateModified)),
+            QueryFunction::DateModified(QueryCmp::Range, QueryDate::Range(start, end))
+        );
+        // 1000 isn't a date, so it stays text
+        assert_eq!(text(&parse("1000", BareNumbers::DateModified)), "1000");
+
+        assert_eq!(
+            function(parse("1000", BareNumbers::Size)),
+            QueryFunction::Size(QueryCmp::Eq, 1000)
+        );
+        assert_eq!(
+            function(parse("10kb", BareNumbers::Size)),
+            QueryFunction::Size(QueryCmp::Eq, 10 * 1024)
+        );
+        assert_eq!(
+            function(parse("2023", BareNumbers::Size)),
+            QueryFunction::Size(QueryCmp::Eq, 2023)
+        );
+
+        // Other terms, quoted numbers and numbers with modifiers stay text
+        assert_eq!(text(&parse("report", BareNumbers::Size)), "report");
+        assert_eq!(text(&parse("1st", BareNumbers::Size)), "1st");
+        assert_eq!(text(&parse("\"2023\"", BareNumbers::DateModified)), "2023");
+        assert_eq!(text(&parse("file:2023", BareNumbers::DateModified)), "2023");
+        let QueryExpr::And(left, right) = parse("report 2023", BareNumbers::DateModified) else {
+            panic!("Expected And");
+        };
+        assert_eq!(text(&left), "report");
+        assert_eq!(function(*right), year);
+    }
+
+    #[test]
+    fn test_serialize_query() {
+        let expr = parse_query("a | <case:regex:^b$ !size:>1kb dm:2023>").unwrap();
+        let json = serde_json::to_string(&expr).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        let [left, right] = value["Or"].as_array().unwrap().as_slice() else {
+            panic!("Expected Or, got {}", json);
+        };
+        assert_eq!(left["Literal"]["Text"]["text"], "a");
+        assert_eq!(left["Literal"]["Text"]["case_sensitive"], false);
+        let [regex_and_size, date] = right["And"].as_array().unwrap().as_slice() else {
+            panic!("Expected And, got {}", json);
+        };
+        let [regex, size] = regex_and_size["And"].as_array().unwrap().as_slice() else {
+            panic!("Expected And, got {}", json);
+        };
+        // The pattern is serialized as its source
+        assert_eq!(regex["Literal"]["Regex"]["pattern"], "^b$");
+        assert_eq!(regex["Literal"]["Regex"]["case_sensitive"], true);
+        assert_eq!(
+            size["Not"]["Function"]["Size"],
+            serde_json::json!(["Gt", 1024])
+        );
+        assert_eq!(date["Function"]["DateModified"][0], "Eq");
+        assert_eq!(
+            date["Function"]["DateModified"][1]["Range"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+    }
 }
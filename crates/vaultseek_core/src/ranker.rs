@@ -0,0 +1,146 @@
+use crate::query::query_parser::{QueryExpr, QueryLiteral};
+use crate::searcher::edit_distance;
+
+// A match at the start of the name, like report in report.pdf
+const START_OF_NAME_BOOST: f32 = 1.0;
+// A match that is a whole word of the name, like report in annual report.pdf
+const WHOLE_WORD_BOOST: f32 = 0.5;
+// The most a term that isn't in the name can score, for a name word one edit away. Always less
+// than the score of an exact match.
+const FUZZY_WEIGHT: f32 = 0.5;
+// Per character of the name not covered by a term, so shorter names rank first
+const LENGTH_PENALTY: f32 = 0.02;
+
+// Scores filenames by how well they match the text terms of a query, higher is better. Only
+// plain text that isn't negated counts, functions and regexes don't affect the score.
+pub struct RelevanceRanker {
+    terms: Vec<String>, // Lowercase
+}
+impl RelevanceRanker {
+    pub fn new(expr: &QueryExpr) -> Self {
+        let mut terms = Vec::new();
+        collect_terms(expr, &mut terms);
+        RelevanceRanker { terms }
+    }
+
+    // Without text terms every name scores the same
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    // The average score of the terms, with the length penalty applied. Each term found in the
+    // name scores 1 plus its boosts, a term that isn't found scores by its edit distance to the
+    // closest word of the name.
+    pub fn score(&self, name: &str) -> f32 {
+        if self.terms.is_empty() {
+            return 0.0;
+        }
+        let name = name.to_lowercase();
+        let mut total = 0.0;
+        let mut covered = 0;
+        for term in &self.terms {
+            match exact_score(&name, term) {
+                Some(score) => {
+                    total += score;
+                    covered += term.chars().count();
+                }
+                None => total += fuzzy_score(&name, term),
+            }
+        }
+        let uncovered = name.chars().count().saturating_sub(covered);
+        total / self.terms.len() as f32 / (1.0 + LENGTH_PENALTY * uncovered as f32)
+    }
+}
+
+fn collect_terms(expr: &QueryExpr, terms: &mut Vec<String>) {
+    match expr {
+        QueryExpr::Literal(QueryLiteral::Text(query)) if !query.text.is_empty() => {
+            terms.push(query.text.to_lowercase());
+        }
+        QueryExpr::And(left, right) | QueryExpr::Or(left, right) => {
+            collect_terms(left, terms);
+            collect_terms(right, terms);
+        }
+        _ => {}
+    }
+}
+
+// The best score among the occurrences of the term in the name, None if it doesn't occur
+fn exact_score(name: &str, term: &str) -> Option<f32> {
+    name.match_indices(term)
+        .map(|(start, _)| {
+            let before = name[..start].chars().next_back();
+            let after = name[start + term.len()..].chars().next();
+            let mut score = 1.0;
+            if start == 0 {
+                score += START_OF_NAME_BOOST;
+            }
+            if !before.is_some_and(char::is_alphanumeric)
+                && !after.is_some_and(char::is_alphanumeric)
+            {
+                score += WHOLE_WORD_BOOST;
+            }
+            score
+        })
+        .max_by(f32::total_cmp)
+}
+
+fn fuzzy_score(name: &str, term: &str) -> f32 {
+    let term_len = term.chars().count();
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let len = term_len.max(word.chars().count());
+            let similarity = 1.0 - edit_distance(term, word) as f32 / len as f32;
+            FUZZY_WEIGHT * similarity.max(0.0)
+        })
+        .fold(0.0, f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::query_parser::parse_query;
+
+    fn ranker(query: &str) -> RelevanceRanker {
+        RelevanceRanker::new(&parse_query(query).unwrap())
+    }
+
+    #[test]
+    fn test_scores_follow_match_quality() {
+        let ranker = ranker("report");
+        // Each name matches worse than the one before it
+        let names = [
+            "report",
+            "report.pdf",
+            "report_final_version.pdf",
+            "annual report.pdf",
+            "annualreport.pdf",
+            "reprot.pdf",
+            "invoice.pdf",
+        ];
+        let scores: Vec<f32> = names.iter().map(|name| ranker.score(name)).collect();
+        for (pair, scores) in names.windows(2).zip(scores.windows(2)) {
+            assert!(scores[0] > scores[1], "{:?} {:?}", pair, scores);
+        }
+        // Case doesn't matter
+        assert_eq!(ranker.score("Report.PDF"), ranker.score("report.pdf"));
+    }
+
+    #[test]
+    fn test_scores_average_the_terms() {
+        let ranker = ranker("annual report");
+        assert!(ranker.score("annual report.pdf") > ranker.score("report.pdf"));
+        assert!(ranker.score("report.pdf") > ranker.score("monthly.pdf"));
+    }
+
+    #[test]
+    fn test_only_text_terms_count() {
+        assert_eq!(
+            ranker("report !draft ext:pdf regex:^a").terms,
+            vec!["report"]
+        );
+        assert!(ranker("ext:pdf size:>1mb").is_empty());
+        assert_eq!(ranker("ext:pdf").score("report.pdf"), 0.0);
+    }
+}
@@ -1,73 +1,1545 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
 use crate::{
-    file_tree::{self, FileTree},
-    indexer::bigram_index::BigramIndex,
-    post_filter,
-    sorter::{SortField, SortOrder, Sorter},
+    file_tree::{self, ElementType, FileTree},
+    ignore_list::IgnoreList,
+    indexer::{bigram_index::BigramIndex, parent_names::ParentNameIndex},
+    query::{
+        evaluator::{QueryEvaluator, SearchDiagnostics, is_refinement, uses_parent_names},
+        query_parser::{
+            BareNumbers, DEFAULT_REGEX_SIZE_LIMIT, DEFAULT_SIZE_TOLERANCE_PERCENT, ParseOptions,
+            QueryError, QueryExpr, SpaceSemantics, parse_query_with_options,
+        },
+    },
+    ranker::RelevanceRanker,
+    sorter::{NullsOrder, SortField, SortOrder, Sorter},
 };
 
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchOptions {
+    pub sort_by: Option<SortField>,
+    pub sort_order: Option<SortOrder>,
+    // Where elements without the sort field go, see NullsOrder
+    pub nulls: Option<NullsOrder>,
+    pub element_type: Option<ElementType>, // Only return files or only folders
+    pub space_semantics: SpaceSemantics,   // Whether `foo bar` means foo AND bar or foo OR bar
+    pub regex_size_limit: Option<usize>,   // Defaults to DEFAULT_REGEX_SIZE_LIMIT
+    pub size_tolerance_percent: Option<u32>, // Defaults to DEFAULT_SIZE_TOLERANCE_PERCENT
+    pub raw_folder_sizes: bool, // Compare folders by their own size instead of their contents
+    pub bare_numbers: BareNumbers, // Whether a bare 2023 is text, a date or a size
+    // Match at most this many candidates one by one, see try_search_with_diagnostics
+    pub scan_budget: Option<usize>,
+    // Like the scan budget, but in time: returns the matches found until then, sorted as usual
+    pub timeout: Option<Duration>,
+    // Path queries skip elements in dot folders like .git, see QueryEvaluator
+    pub exclude_dot_components: bool,
+    // size: compares elements without a size as 0 instead of never matching them, see
+    // QueryEvaluator::with_none_sizes_as_zero
+    pub none_sizes_as_zero: bool,
+    // Directories before files, each sorted by sort_by if given, see Sorter::group_dirs_first
+    pub dirs_first: bool,
+    // ww:path: also ends words at separators, see QueryEvaluator::with_separator_word_boundaries
+    pub separator_word_boundaries: bool,
+    // !size:>1000 and !dm: skip elements without a size or date instead of including them, see
+    // QueryEvaluator::with_strict_negation
+    pub strict_negation: bool,
+    // Without a sort field, order the results by relevance to the text of the query instead of
+    // by index, see RelevanceRanker
+    pub rank_by_relevance: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchError {
+    Query(QueryError),
+    // A caller provided index that doesn't point to an element of the tree
+    IndexOutOfRange { index: usize, len: usize },
+}
+impl std::fmt::Display for SearchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchError::Query(e) => write!(f, "{}", e),
+            SearchError::IndexOutOfRange { index, len } => {
+                write!(f, "Index {} is out of range for {} elements", index, len)
+            }
+        }
+    }
+}
+impl std::error::Error for SearchError {}
+impl From<QueryError> for SearchError {
+    fn from(e: QueryError) -> Self {
+        SearchError::Query(e)
+    }
+}
+
+// Building the index took longer than the deadline of from_file_tree_with_deadline
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeadlineExceeded {
+    pub elements: usize,
+    pub elapsed: Duration,
+}
+impl std::fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Indexing {} elements was aborted after {:?}, past the deadline",
+            self.elements, self.elapsed
+        )
+    }
+}
+impl std::error::Error for DeadlineExceeded {}
+
 pub struct Searcher {
     pub file_tree: FileTree,
     pub bigram_index: BigramIndex,
     pub sorter: Sorter,
+    // Elements hidden from all searches, see set_ignore_list. Empty if nothing is ignored.
+    ignored: Vec<bool>,
+    // Built on the first nosubfolders: or parentat: query, unused otherwise
+    parent_names: OnceLock<ParentNameIndex>,
+    // Elements inside the scope root, see with_scope_root. Empty if the whole tree is searched.
+    scope: Vec<bool>,
+    // Built on the first suggest_correction call, unused otherwise
+    suggestion_terms: OnceLock<Vec<String>>,
+}
+
+// How many filenames suggest_correction looks at for terms at most
+const SUGGESTION_SAMPLE_SIZE: usize = 10_000;
+
+// The term with the smallest edit distance to the word, among the terms sharing the most bigrams
+// with it. At most a third of the word may differ.
+fn closest_term<'a>(word: &str, terms: &'a [String]) -> Option<&'a str> {
+    let bigrams = |text: &str| -> Vec<(char, char)> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut bigrams: Vec<_> = chars.windows(2).map(|pair| (pair[0], pair[1])).collect();
+        bigrams.sort_unstable();
+        bigrams.dedup();
+        bigrams
+    };
+    let word_bigrams = bigrams(word);
+    let max_distance = (word.chars().count() / 3).max(1);
+    let mut scored: Vec<(usize, &str)> = terms
+        .iter()
+        .map(|term| {
+            let overlap = bigrams(term)
+                .iter()
+                .filter(|bigram| word_bigrams.binary_search(bigram).is_ok())
+                .count();
+            (overlap, term.as_str())
+        })
+        .filter(|&(overlap, _)| overlap > 0)
+        .collect();
+    // Edit distance is expensive, only compare the best candidates by bigram overlap
+    scored.sort_unstable_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+    scored
+        .into_iter()
+        .take(50)
+        .map(|(_, term)| (edit_distance(word, term), term))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, term)| term)
+}
+
+// Levenshtein distance in characters
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (diagonal + usize::from(ca != cb))
+                .min(above + 1)
+                .min(row[j] + 1);
+            diagonal = above;
+        }
+    }
+    row[b.len()]
 }
 
 impl Searcher {
-    pub fn from_file_tree(tree: FileTree) -> Self {
+    pub fn from_file_tree(mut tree: FileTree) -> Self {
+        // Folder sizes are often missing, e.g. in EFU exports without folder sizes
+        tree.compute_rollup_sizes();
         let bigram_index = BigramIndex::new(&tree);
         let sorter = Sorter::new();
         Searcher {
             file_tree: tree,
             bigram_index,
             sorter,
+            ignored: Vec::new(),
+            parent_names: OnceLock::new(),
+            scope: Vec::new(),
+            suggestion_terms: OnceLock::new(),
+        }
+    }
+
+    // Like from_file_tree, but gives up building the index once the deadline has passed, so a
+    // misconfigured huge file list can't hang the startup
+    pub fn from_file_tree_with_deadline(
+        mut tree: FileTree,
+        deadline: Instant,
+    ) -> Result<Self, DeadlineExceeded> {
+        let start = Instant::now();
+        tree.compute_rollup_sizes();
+        let Some(bigram_index) = BigramIndex::with_deadline(&tree, deadline) else {
+            return Err(DeadlineExceeded {
+                elements: tree.len(),
+                elapsed: start.elapsed(),
+            });
+        };
+        Ok(Searcher {
+            file_tree: tree,
+            bigram_index,
+            sorter: Sorter::new(),
+            ignored: Vec::new(),
+            parent_names: OnceLock::new(),
+            scope: Vec::new(),
+            suggestion_terms: OnceLock::new(),
+        })
+    }
+
+    // Searches only the contents of the folder at `root`, e.g. a project folder, as if every
+    // query had parent: with its path. The root folder itself is not included.
+    pub fn with_scope_root(mut self, root: usize) -> Self {
+        let mut scope = vec![false; self.file_tree.len()];
+        for index in self.file_tree.collect_all_children(root) {
+            scope[index] = true;
         }
+        self.scope = scope;
+        self
     }
 
+    // Hide elements matching the ignore list, and everything inside them, from all searches
+    pub fn set_ignore_list(&mut self, ignore_list: &IgnoreList) {
+        self.ignored = if ignore_list.is_empty() {
+            Vec::new()
+        } else {
+            ignore_list.ignored_elements(&self.file_tree)
+        };
+    }
+
+    // Without a sort field the results are in ascending index order, the order of the file list.
+    // The same query on the same tree always returns the same order.
     pub fn search<T: AsRef<str>>(
         &self,
         query: T,
         sort_by: Option<SortField>,
         sort_order: Option<SortOrder>,
     ) -> Vec<usize> {
-        let mut indices: Vec<usize>;
-
-        // Normalize the query to lowercase for case-insensitive search
-        let query = query.as_ref().to_lowercase();
-        let query_len = query.chars().count();
-
-        // Search
-        if query.is_empty() {
-            // query is empty, return all indices
-            indices = (0..self.file_tree.len()).collect::<Vec<usize>>();
-        } else if query_len < 2 {
-            // query is 1 character
-            indices = self.bigram_index.query_char(query.chars().next().unwrap());
-        } else {
-            // query is longer than 1 character
-            indices = self.bigram_index.query_word(&query);
-            if query_len > 2 {
-                // If the query is longer than 2 characters, apply post-filtering
-                post_filter::post_filter(&self.file_tree, &mut indices, &query);
-            }
-        }
+        self.search_with_options(
+            query,
+            &SearchOptions {
+                sort_by,
+                sort_order,
+                ..Default::default()
+            },
+        )
+    }
 
-        println!(
-            "Found {} matching records for query '{}'",
-            indices.len(),
-            query
+    pub fn search_with_options<T: AsRef<str>>(
+        &self,
+        query: T,
+        options: &SearchOptions,
+    ) -> Vec<usize> {
+        // Invalid queries don't match anything, use try_search_with_options to get the error
+        self.try_search_with_options(query, options)
+            .unwrap_or_else(|e| {
+                tracing::warn!("Error parsing query: {}", e);
+                Vec::new()
+            })
+    }
+
+    // Parses the query like a search with these options would, e.g. to explain how it was read
+    pub fn parse_query<T: AsRef<str>>(
+        &self,
+        query: T,
+        options: &SearchOptions,
+    ) -> Result<QueryExpr, QueryError> {
+        // Normalize the query the same way the filenames were normalized when loading
+        let query = self.file_tree.normalize(query.as_ref());
+        parse_query_with_options(&query, self.parse_options(options))
+    }
+
+    pub fn try_search_with_options<T: AsRef<str>>(
+        &self,
+        query: T,
+        options: &SearchOptions,
+    ) -> Result<Vec<usize>, QueryError> {
+        self.try_search_with_diagnostics(query, options)
+            .map(|(indices, _)| indices)
+    }
+
+    // Like try_search_with_options, but also reports whether the scan budget of the options
+    // cut the search short, so a UI can show that the results may be incomplete
+    pub fn try_search_with_diagnostics<T: AsRef<str>>(
+        &self,
+        query: T,
+        options: &SearchOptions,
+    ) -> Result<(Vec<usize>, SearchDiagnostics), QueryError> {
+        // The evaluator records candidate_count, how many elements the index narrowed it down to
+        let span = tracing::info_span!(
+            "search",
+            query = query.as_ref(),
+            candidate_count = tracing::field::Empty,
+            result_count = tracing::field::Empty,
+            elapsed = tracing::field::Empty,
         );
-        // Sort results if a sort field is provided
+        let _entered = span.enter();
+        let start = Instant::now();
+        let expr = self.parse_query(&query, options)?;
+        let mut evaluator = self
+            .evaluator(options)
+            .with_scan_budget(options.scan_budget)
+            .with_deadline(options.timeout.map(|timeout| start + timeout));
+        if uses_parent_names(&expr) {
+            evaluator = evaluator.with_parent_names(
+                self.parent_names
+                    .get_or_init(|| ParentNameIndex::new(&self.file_tree)),
+            );
+        }
+        // The evaluator merges all branches into one de-duplicated list, so sorting happens once below
+        let (mut indices, diagnostics) = evaluator.evaluate_with_diagnostics(&expr);
+        debug_assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+
+        self.finish_search(&expr, &mut indices, options);
+        span.record("result_count", indices.len());
+        span.record("elapsed", tracing::field::debug(start.elapsed()));
+        if diagnostics.truncated {
+            tracing::info!(
+                "Scan budget exhausted after {} of {} candidates",
+                diagnostics.scanned,
+                diagnostics.candidate_count
+            );
+        }
+        if diagnostics.timed_out {
+            tracing::info!(
+                "Timed out after {} of {} candidates",
+                diagnostics.scanned,
+                diagnostics.candidate_count
+            );
+        }
+        tracing::info!("Found {} matching records", indices.len());
+        Ok((indices, diagnostics))
+    }
+
+    // Searches only the given elements, e.g. results the user selected. Unlike refine_search, the
+    // candidates can be any elements. Without a sort field the results are in ascending order.
+    pub fn search_within<T: AsRef<str>>(
+        &self,
+        query: T,
+        candidates: &[usize],
+        options: &SearchOptions,
+    ) -> Result<Vec<usize>, SearchError> {
+        if let Some(&index) = candidates
+            .iter()
+            .find(|&&index| index >= self.file_tree.len())
+        {
+            return Err(SearchError::IndexOutOfRange {
+                index,
+                len: self.file_tree.len(),
+            });
+        }
+        let expr = self.parse_query(&query, options)?;
+        let mut indices = candidates.to_vec();
+        indices.sort_unstable();
+        indices.dedup();
+        self.evaluator(options).filter(&expr, &mut indices);
+        self.finish_search(&expr, &mut indices, options);
+        Ok(indices)
+    }
+
+    // One page of all elements except the root, with the total, for listing everything without
+    // a query. Sorted without sorting a list of every index, unless elements are hidden by the
+    // ignore list or outside the scope: then only the remaining ones are listed and sorted.
+    pub fn all_page(
+        &self,
+        offset: usize,
+        len: usize,
+        sort_by: Option<SortField>,
+        sort_order: Option<SortOrder>,
+    ) -> (Vec<usize>, usize) {
+        let sort_order = sort_order.unwrap_or(SortOrder::Ascending);
+        if self.ignored.is_empty() && self.scope.is_empty() {
+            let total = self.file_tree.len().saturating_sub(1);
+            let page = match sort_by {
+                Some(sort_by) => {
+                    self.sorter
+                        .page(&self.file_tree, sort_by, sort_order, offset, len)
+                }
+                None => (offset.saturating_add(1)..self.file_tree.len())
+                    .take(len)
+                    .collect(),
+            };
+            return (page, total);
+        }
+
+        let mut indices: Vec<usize> = (1..self.file_tree.len())
+            .filter(|&index| {
+                (self.ignored.is_empty() || !self.ignored[index])
+                    && (self.scope.is_empty() || self.scope[index])
+            })
+            .collect();
         if let Some(sort_by) = sort_by {
-            let sort_order = sort_order.unwrap_or(SortOrder::Ascending);
-            self.sorter
-                .sort_by(&self.file_tree, indices.as_mut_slice(), sort_by, sort_order);
+            self.sorter.sort_by_with_nulls(
+                &self.file_tree,
+                &mut indices,
+                sort_by,
+                sort_order,
+                None,
+            );
+        }
+        let total = indices.len();
+        let page = indices.into_iter().skip(offset).take(len).collect();
+        (page, total)
+    }
+
+    // Applies the type filter, the ignore list and the sorting of the options to the matches
+    fn finish_search(&self, expr: &QueryExpr, indices: &mut Vec<usize>, options: &SearchOptions) {
+        // The root is never a result, like in all_page
+        indices.retain(|&index| index != 0);
+        if let Some(element_type) = options.element_type {
+            indices.retain(|&index| self.file_tree.elements[index].element_type() == element_type);
+        }
+        if !self.ignored.is_empty() {
+            indices.retain(|&index| !self.ignored[index]);
+        }
+        // Sort results if a sort field is provided
+        if let Some(sort_by) = options.sort_by {
+            let sort_order = options.sort_order.unwrap_or(SortOrder::Ascending);
+            self.sorter.sort_by_with_nulls(
+                &self.file_tree,
+                indices.as_mut_slice(),
+                sort_by,
+                sort_order,
+                options.nulls,
+            );
+        } else if let Some(ranker) = self.relevance_ranker(expr, options) {
+            self.rank(&ranker, indices);
+        }
+        if options.dirs_first {
+            self.sorter.group_dirs_first(&self.file_tree, indices);
+        }
+    }
+
+    // The ranker for the query if its results are ordered by relevance, None with an explicit
+    // sort field or without text to rank by
+    pub fn relevance_ranker_for<T: AsRef<str>>(
+        &self,
+        query: T,
+        options: &SearchOptions,
+    ) -> Option<RelevanceRanker> {
+        let expr = self.parse_query(query, options).ok()?;
+        self.relevance_ranker(&expr, options)
+    }
+
+    fn relevance_ranker(
+        &self,
+        expr: &QueryExpr,
+        options: &SearchOptions,
+    ) -> Option<RelevanceRanker> {
+        if !options.rank_by_relevance || options.sort_by.is_some() {
+            return None;
+        }
+        let ranker = RelevanceRanker::new(expr);
+        (!ranker.is_empty()).then_some(ranker)
+    }
+
+    // Best score first, ties in ascending index order so the order doesn't depend on the input
+    fn rank(&self, ranker: &RelevanceRanker, indices: &mut [usize]) {
+        let mut scored: Vec<(f32, usize)> = indices
+            .iter()
+            .map(|&index| (ranker.score(self.file_tree.get_filename(index)), index))
+            .collect();
+        scored.sort_unstable_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        for (index, (_, scored)) in indices.iter_mut().zip(scored) {
+            *index = scored;
+        }
+    }
+
+    pub fn facet_by_parent(&self, indices: &[usize], depth: usize) -> Vec<(String, usize)> {
+        // Roll the results up to their ancestor folder at the given depth (1 = top level folder)
+        // and count the matches per folder. Results that are less deeply nested than `depth` are
+        // counted in their own parent folder, results directly in the root under "". Folders
+        // above the scope root aren't counted, like in containing_folders.
+        let mut counts: HashMap<usize, usize> = HashMap::new();
+        let mut ancestors = Vec::new();
+        for &index in indices {
+            let folder = self.ancestor_at_depth(index, depth, &mut ancestors);
+            if self.scope.is_empty() || self.scope[folder] {
+                *counts.entry(folder).or_insert(0) += 1;
+            }
+        }
+
+        let mut facets: Vec<(String, usize)> = counts
+            .into_iter()
+            .map(|(folder, count)| (self.file_tree.get_full_path(folder), count))
+            .collect();
+        // Most matches first, ties by path so the order is stable
+        facets.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        facets
+    }
+
+    // The distinct folders that contain at least one file matching the query, e.g. to jump to a
+    // folder instead of a file. With a depth the folders are rolled up like in facet_by_parent.
+    // Files directly in the root have no folder. Without a sort field the folders are in
+    // ascending index order.
+    pub fn matching_folders<T: AsRef<str>>(
+        &self,
+        query: T,
+        options: &SearchOptions,
+        depth: Option<usize>,
+    ) -> Result<Vec<usize>, QueryError> {
+        let files = self.try_search_with_options(
+            query,
+            &SearchOptions {
+                sort_by: None,
+                element_type: Some(ElementType::File),
+                ..options.clone()
+            },
+        )?;
+        Ok(self.containing_folders(&files, depth, options))
+    }
+
+    // The distinct parent folders of the files among the results, see matching_folders
+    pub fn containing_folders(
+        &self,
+        indices: &[usize],
+        depth: Option<usize>,
+        options: &SearchOptions,
+    ) -> Vec<usize> {
+        let mut ancestors = Vec::new();
+        let mut folders: Vec<usize> = indices
+            .iter()
+            .filter(|&&index| !self.file_tree.elements[index].is_dir())
+            .map(|&index| match depth {
+                Some(depth) => self.ancestor_at_depth(index, depth, &mut ancestors),
+                None => self.file_tree.elements[index].parent,
+            })
+            // Rolled up folders can be above the scope root
+            .filter(|&folder| folder != 0 && (self.scope.is_empty() || self.scope[folder]))
+            .collect();
+        folders.sort_unstable();
+        folders.dedup();
+        if let Some(sort_by) = options.sort_by {
+            let sort_order = options.sort_order.unwrap_or(SortOrder::Ascending);
+            self.sorter.sort_by_with_nulls(
+                &self.file_tree,
+                &mut folders,
+                sort_by,
+                sort_order,
+                options.nulls,
+            );
+        }
+        folders
+    }
+
+    // How many of the files among the results have each extension, most common first. Extensions
+    // are lowercased, files without one are counted under "". Folders aren't counted.
+    pub fn facet_by_extension(&self, indices: &[usize]) -> Vec<(String, usize)> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for &index in indices {
+            // Folders created implicitly for a path only have children
+            let element = &self.file_tree.elements[index];
+            if element.is_dir() || !element.children.is_empty() {
+                continue;
+            }
+            let extension = file_tree::extension(self.file_tree.get_filename(index));
+            *counts
+                .entry(extension.unwrap_or_default().to_lowercase())
+                .or_default() += 1;
+        }
+        let mut facets: Vec<(String, usize)> = counts.into_iter().collect();
+        // Ties by extension, so the order doesn't depend on the hash map
+        facets.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        facets
+    }
+
+    // The ancestor folder of the element at the depth (1 = top level folder), or its parent if
+    // it's less deeply nested. `ancestors` is a buffer reused between calls.
+    fn ancestor_at_depth(&self, index: usize, depth: usize, ancestors: &mut Vec<usize>) -> usize {
+        ancestors.clear();
+        let mut current_index = self.file_tree.elements[index].parent;
+        while current_index != 0 {
+            ancestors.push(current_index);
+            current_index = self.file_tree.elements[current_index].parent;
+        }
+        // ancestors is ordered from the parent up to the top level folder
+        match depth {
+            0 => 0,
+            _ if ancestors.len() >= depth => ancestors[ancestors.len() - depth],
+            _ => ancestors.first().copied().unwrap_or(0),
+        }
+    }
+
+    // Refines the results of a previous search with the same options, for search as you type.
+    // Returns None if the new query can match elements the previous one didn't, in which case
+    // a full search is needed. The order of the previous results is kept.
+    pub fn refine_search<T: AsRef<str>, U: AsRef<str>>(
+        &self,
+        previous_query: T,
+        previous_indices: &[usize],
+        query: U,
+        options: &SearchOptions,
+    ) -> Option<Vec<usize>> {
+        let parse_options = self.parse_options(options);
+        let previous_query = self.file_tree.normalize(previous_query.as_ref());
+        let query = self.file_tree.normalize(query.as_ref());
+        let previous = parse_query_with_options(&previous_query, parse_options).ok()?;
+        let expr = parse_query_with_options(&query, parse_options).ok()?;
+        if !is_refinement(&previous, &expr) {
+            return None;
+        }
+
+        let mut indices = previous_indices.to_vec();
+        self.evaluator(options).filter(&expr, &mut indices);
+        // The scores depend on the new query's text
+        if let Some(ranker) = self.relevance_ranker(&expr, options) {
+            self.rank(&ranker, &mut indices);
+            if options.dirs_first {
+                self.sorter.group_dirs_first(&self.file_tree, &mut indices);
+            }
+        }
+        Some(indices)
+    }
+
+    // A query with misspelled words replaced by the closest terms from the filenames, for a
+    // "did you mean" hint. Only meant for queries without results, None if nothing close enough
+    // is found or the corrected query doesn't match anything with the same options either.
+    // Functions, operators and quoted text are never rewritten.
+    pub fn suggest_correction<T: AsRef<str>>(
+        &self,
+        query: T,
+        options: &SearchOptions,
+    ) -> Option<String> {
+        let query = self.file_tree.normalize(query.as_ref());
+        let terms = self.suggestion_terms();
+        let mut corrected = false;
+        let mut in_quotes = false;
+        let words: Vec<String> = query
+            .split_whitespace()
+            .map(|word| {
+                let quoted = in_quotes || word.contains('"');
+                in_quotes ^= word.matches('"').count() % 2 == 1;
+                let lowercase = word.to_lowercase();
+                if quoted
+                    || word.chars().count() < 3
+                    || word.contains(&[':', '<', '>', '|', '&', '!'][..])
+                    || terms.iter().any(|term| term.contains(&lowercase))
+                {
+                    return word.to_string();
+                }
+                // The terms are only a sample of the filenames, so the word may still match
+                if self
+                    .try_search_with_options(word, options)
+                    .is_ok_and(|indices| !indices.is_empty())
+                {
+                    return word.to_string();
+                }
+                match closest_term(&lowercase, terms) {
+                    Some(term) => {
+                        corrected = true;
+                        term.to_string()
+                    }
+                    None => word.to_string(),
+                }
+            })
+            .collect();
+        if !corrected {
+            return None;
+        }
+        let suggestion = words.join(" ");
+        self.try_search_with_options(&suggestion, options)
+            .is_ok_and(|indices| !indices.is_empty())
+            .then_some(suggestion)
+    }
+
+    // The distinct lowercase words of up to SUGGESTION_SAMPLE_SIZE filenames, spread evenly over
+    // the tree so large trees stay cheap. Collected once and cached.
+    fn suggestion_terms(&self) -> &[String] {
+        self.suggestion_terms
+            .get_or_init(|| self.collect_suggestion_terms())
+    }
+
+    fn collect_suggestion_terms(&self) -> Vec<String> {
+        let len = self.file_tree.len();
+        let step = len.div_ceil(SUGGESTION_SAMPLE_SIZE).max(1);
+        let mut terms: Vec<String> = (1..len)
+            .step_by(step)
+            .flat_map(|index| {
+                self.file_tree
+                    .get_filename(index)
+                    .split(|c: char| !c.is_alphanumeric())
+                    .filter(|term| term.chars().count() >= 3)
+                    .map(str::to_lowercase)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        terms.sort_unstable();
+        terms.dedup();
+        terms
+    }
+
+    fn evaluator(&self, options: &SearchOptions) -> QueryEvaluator<'_> {
+        let evaluator = QueryEvaluator::new(&self.file_tree, &self.bigram_index)
+            .with_raw_folder_sizes(options.raw_folder_sizes)
+            .with_exclude_dot_components(options.exclude_dot_components)
+            .with_none_sizes_as_zero(options.none_sizes_as_zero)
+            .with_separator_word_boundaries(options.separator_word_boundaries)
+            .with_strict_negation(options.strict_negation);
+        if self.scope.is_empty() {
+            evaluator
+        } else {
+            evaluator.with_scope(&self.scope)
+        }
+    }
+
+    fn parse_options(&self, options: &SearchOptions) -> ParseOptions {
+        ParseOptions {
+            space_semantics: options.space_semantics,
+            regex_size_limit: options.regex_size_limit.unwrap_or(DEFAULT_REGEX_SIZE_LIMIT),
+            size_tolerance_percent: options
+                .size_tolerance_percent
+                .unwrap_or(DEFAULT_SIZE_TOLERANCE_PERCENT),
+            bare_numbers: options.bare_numbers,
         }
-        indices
     }
 
     pub fn get_file_tree(&self) -> &FileTree {
         &self.file_tree
     }
+    // Whether the element is hidden by the ignore list, see set_ignore_list
+    pub fn is_ignored(&self, index: usize) -> bool {
+        self.ignored.get(index).copied().unwrap_or(false)
+    }
     pub fn get(&self, index: usize) -> Option<&file_tree::Element> {
         self.file_tree.get(index)
     }
+    // Elements for a page of results, None for indices out of range
+    pub fn get_many(&self, indices: &[usize]) -> Vec<Option<&file_tree::Element>> {
+        let elements = self.file_tree.get_elements();
+        indices.iter().map(|&index| elements.get(index)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attributes::Attributes;
+    use crate::file_tree::Normalization;
+
+    #[test]
+    fn test_search_normalization() {
+        let nfd_name = "docs/cafe\u{301}.txt"; // "e" followed by a combining acute accent
+        let nfc_query = "caf\u{e9}";
+
+        // Without normalization the bytes are kept and the NFC query doesn't match
+        let mut tree = FileTree::with_capacity(3);
+        tree.add_or_update_recursive(nfd_name, Some(100), None, None, Attributes::NONE);
+        let searcher = Searcher::from_file_tree(tree);
+        assert!(searcher.search(nfc_query, None, None).is_empty());
+
+        let mut tree = FileTree::with_capacity(3);
+        tree.set_normalization(Some(Normalization::Nfc));
+        let file = tree
+            .add_or_update_recursive(nfd_name, Some(100), None, None, Attributes::NONE)
+            .unwrap();
+        assert_eq!(tree.get_filename(file), "caf\u{e9}.txt");
+        let searcher = Searcher::from_file_tree(tree);
+        assert_eq!(searcher.search(nfc_query, None, None), vec![file]);
+        // Queries are normalized too, so an NFD query matches the NFC name
+        assert_eq!(searcher.search("cafe\u{301}", None, None), vec![file]);
+    }
+
+    #[test]
+    fn test_search_space_semantics() {
+        let mut tree = FileTree::with_capacity(4);
+        let foo = tree.add_test_file("foo.txt");
+        let both = tree.add_test_file("foo bar.txt");
+        let bar = tree.add_test_file("bar.txt");
+        let searcher = Searcher::from_file_tree(tree);
+
+        assert_eq!(searcher.search("foo bar", None, None), vec![both]);
+        let options = SearchOptions {
+            space_semantics: SpaceSemantics::Or,
+            ..Default::default()
+        };
+        assert_eq!(
+            searcher.search_with_options("foo bar", &options),
+            vec![foo, both, bar]
+        );
+    }
+
+    #[test]
+    fn test_facet_by_parent() {
+        let mut tree = FileTree::with_capacity(10);
+        for path in [
+            "C:\\Documents\\report.txt",
+            "C:\\Documents\\work\\report 2.txt",
+            "C:\\Documents\\work\\report 3.txt",
+            "C:\\Downloads\\report.pdf",
+            "report.md",
+        ] {
+            tree.add_test_file(path);
+        }
+        let searcher = Searcher::from_file_tree(tree);
+        let results = searcher.search("report", None, None);
+        assert_eq!(results.len(), 5);
+
+        assert_eq!(
+            searcher.facet_by_parent(&results, 2),
+            vec![
+                ("C:\\Documents".to_string(), 3),
+                ("".to_string(), 1),
+                ("C:\\Downloads".to_string(), 1),
+            ]
+        );
+        assert_eq!(
+            searcher.facet_by_parent(&results, 3),
+            vec![
+                ("C:\\Documents\\work".to_string(), 2),
+                ("".to_string(), 1),
+                ("C:\\Documents".to_string(), 1),
+                ("C:\\Downloads".to_string(), 1),
+            ]
+        );
+        assert_eq!(
+            searcher.facet_by_parent(&results, 1),
+            vec![("C:".to_string(), 4), ("".to_string(), 1)]
+        );
+        assert_eq!(
+            searcher.facet_by_parent(&results, 0),
+            vec![("".to_string(), 5)]
+        );
+        assert!(searcher.facet_by_parent(&[], 1).is_empty());
+    }
+
+    #[test]
+    fn test_scan_budget() {
+        let mut tree = FileTree::with_capacity(12);
+        let reports: Vec<usize> = (1..=5)
+            .map(|i| {
+                let path = format!("docs\\report {}.txt", i);
+                tree.add_test_file(&path)
+            })
+            .collect();
+        for i in 1..=5 {
+            let path = format!("docs\\notes {}.txt", i);
+            tree.add_test_file(&path);
+        }
+        let searcher = Searcher::from_file_tree(tree);
+        let budget = |scan_budget| SearchOptions {
+            scan_budget: Some(scan_budget),
+            ..Default::default()
+        };
+
+        // Path queries scan every element, a tiny budget stops early and says so
+        let (indices, diagnostics) = searcher
+            .try_search_with_diagnostics("path:report", &budget(4))
+            .unwrap();
+        assert!(diagnostics.truncated);
+        assert_eq!(diagnostics.scanned, 4);
+        assert!(indices.len() < reports.len());
+        let (indices, diagnostics) = searcher
+            .try_search_with_diagnostics("path:report", &SearchOptions::default())
+            .unwrap();
+        assert!(!diagnostics.truncated);
+        assert_eq!(indices, reports);
+
+        // The bigram index narrows report down to the five reports, which fit the budget
+        let (indices, diagnostics) = searcher
+            .try_search_with_diagnostics("report", &budget(5))
+            .unwrap();
+        assert!(!diagnostics.truncated);
+        assert_eq!(indices, reports);
+        // Exact candidates aren't scanned at all
+        let (indices, diagnostics) = searcher
+            .try_search_with_diagnostics("re", &budget(1))
+            .unwrap();
+        assert!(!diagnostics.truncated);
+        assert_eq!(diagnostics.scanned, 0);
+        assert_eq!(indices, reports);
+    }
+
+    #[test]
+    fn test_search_timeout() {
+        let mut tree = FileTree::with_capacity(3000);
+        for i in 0..3000 {
+            let path = format!("docs\\report {}.txt", i);
+            tree.add_or_update_recursive(&path, Some(i), None, None, Attributes::NONE);
+        }
+        let searcher = Searcher::from_file_tree(tree);
+        let options = |timeout| SearchOptions {
+            timeout: Some(timeout),
+            sort_by: Some(SortField::Size),
+            sort_order: Some(SortOrder::Descending),
+            ..Default::default()
+        };
+
+        // A regex scans every element, a zero timeout stops it at the first check
+        let (indices, diagnostics) = searcher
+            .try_search_with_diagnostics("regex:report.*txt", &options(Duration::ZERO))
+            .unwrap();
+        assert!(diagnostics.timed_out);
+        assert_eq!(diagnostics.scanned, 1024);
+        assert!(indices.len() > 1000 && indices.len() < 3000);
+        // The partial results are still sorted
+        let sizes: Vec<_> = indices
+            .iter()
+            .map(|&index| searcher.get(index).unwrap().size)
+            .collect();
+        assert!(sizes.windows(2).all(|pair| pair[0] >= pair[1]));
+
+        let (indices, diagnostics) = searcher
+            .try_search_with_diagnostics("regex:report.*txt", &options(Duration::from_secs(60)))
+            .unwrap();
+        assert!(!diagnostics.timed_out);
+        assert_eq!(indices.len(), 3000);
+    }
+
+    #[test]
+    fn test_from_file_tree_with_deadline() {
+        let tree = || {
+            let mut tree = FileTree::with_capacity(4);
+            tree.add_test_file("report.txt");
+            tree
+        };
+        let report = 1;
+
+        // A deadline that already passed aborts before indexing anything
+        let error = Searcher::from_file_tree_with_deadline(tree(), Instant::now()).err();
+        assert_eq!(error.map(|e| e.elements), Some(2));
+
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let searcher = Searcher::from_file_tree_with_deadline(tree(), deadline).unwrap();
+        assert_eq!(searcher.search("report", None, None), vec![report]);
+        let complete = Searcher::from_file_tree(tree());
+        assert_eq!(
+            searcher.search("r", None, None),
+            complete.search("r", None, None)
+        );
+    }
+
+    #[test]
+    fn test_none_sizes_as_zero() {
+        let mut tree = FileTree::with_capacity(8);
+        let folder = tree
+            .add_or_update_recursive("docs", None, None, None, Attributes::DIRECTORY)
+            .unwrap();
+        let unknown = tree
+            .add_or_update_recursive("docs\\a.txt", None, None, None, Attributes::NONE)
+            .unwrap();
+        let empty = tree
+            .add_or_update_recursive("docs\\b.txt", Some(0), None, None, Attributes::NONE)
+            .unwrap();
+        let full = tree
+            .add_or_update_recursive("docs\\c.txt", Some(5), None, None, Attributes::NONE)
+            .unwrap();
+        let searcher = Searcher::from_file_tree(tree);
+        // Raw folder sizes, so the folder has no size either
+        let search = |query: &str, none_sizes_as_zero| {
+            let options = SearchOptions {
+                raw_folder_sizes: true,
+                none_sizes_as_zero,
+                ..Default::default()
+            };
+            searcher.search_with_options(query, &options)
+        };
+
+        assert_eq!(search("size:0", false), vec![empty]);
+        assert_eq!(search("size:>0", false), vec![full]);
+        assert_eq!(search("size:0", true), vec![folder, unknown, empty]);
+        assert_eq!(search("size:>0", true), vec![full]);
+        assert_eq!(search("size:<=5", true), vec![folder, unknown, empty, full]);
+        // With rollup sizes the folder has a size of its own
+        let options = SearchOptions {
+            none_sizes_as_zero: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            searcher.search_with_options("size:>0", &options),
+            vec![folder, full]
+        );
+    }
+
+    #[test]
+    fn test_get_many() {
+        let mut tree = FileTree::with_capacity(4);
+        let a = tree.add_test_file("a.txt");
+        let b = tree
+            .add_or_update_recursive("b.txt", Some(2), None, None, Attributes::NONE)
+            .unwrap();
+        let searcher = Searcher::from_file_tree(tree);
+        let len = searcher.get_file_tree().len();
+
+        let elements = searcher.get_many(&[b, len, a, usize::MAX]);
+        assert_eq!(elements.len(), 4);
+        assert_eq!(elements[0].and_then(|element| element.size), Some(2));
+        assert!(elements[1].is_none());
+        assert_eq!(elements[2].and_then(|element| element.size), Some(1));
+        assert!(elements[3].is_none());
+        assert!(searcher.get_many(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_facet_by_extension() {
+        let mut tree = FileTree::with_capacity(12);
+        for path in [
+            "photos/a.jpg",
+            "photos/b.JPG",
+            "photos/c.png",
+            "docs/report.pdf",
+            "docs/notes.txt",
+            "docs/todo.txt",
+            "docs/data.jpg",
+            "docs/Makefile",
+        ] {
+            tree.add_test_file(path);
+        }
+        tree.add_or_update_recursive("docs", None, None, None, Attributes::DIRECTORY);
+        let searcher = Searcher::from_file_tree(tree);
+        let results = searcher.search("", None, None);
+
+        let facets = searcher.facet_by_extension(&results);
+        let facets: Vec<(&str, usize)> = facets.iter().map(|(ext, n)| (ext.as_str(), *n)).collect();
+        // The folders don't count, ties are ordered by extension
+        assert_eq!(
+            facets,
+            vec![("jpg", 3), ("txt", 2), ("", 1), ("pdf", 1), ("png", 1)]
+        );
+        let photos = searcher.search("parent:photos", None, None);
+        assert_eq!(
+            searcher.facet_by_extension(&photos),
+            vec![("jpg".to_string(), 2), ("png".to_string(), 1)]
+        );
+        assert!(searcher.facet_by_extension(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_matching_folders() {
+        let mut tree = FileTree::with_capacity(12);
+        let mut add = |path: &str, size: i64| {
+            tree.add_or_update_recursive(path, Some(size), None, None, Attributes::NONE)
+                .unwrap()
+        };
+        let first = add("C:\\Documents\\work\\report 1.txt", 10);
+        add("C:\\Documents\\work\\report 2.txt", 10);
+        let downloads = add("C:\\Downloads\\report.pdf", 500);
+        add("C:\\Documents\\report folder\\notes.txt", 1);
+        add("report.md", 1);
+        tree.add_or_update_recursive(
+            "C:\\Documents\\report folder",
+            None,
+            None,
+            None,
+            Attributes::DIRECTORY,
+        );
+        let searcher = Searcher::from_file_tree(tree);
+        let tree = searcher.get_file_tree();
+        let work = tree.elements[first].parent;
+        let documents = tree.elements[work].parent;
+        let downloads = tree.elements[downloads].parent;
+        let options = SearchOptions::default();
+
+        // Both reports in work collapse to one folder, the matching "report folder" itself and
+        // the file in the root don't count
+        assert_eq!(
+            searcher.matching_folders("report", &options, None),
+            Ok(vec![work, downloads])
+        );
+        assert_eq!(
+            searcher.matching_folders("report", &options, Some(2)),
+            Ok(vec![documents, downloads])
+        );
+        let options = SearchOptions {
+            sort_by: Some(SortField::Size),
+            sort_order: Some(SortOrder::Descending),
+            ..Default::default()
+        };
+        assert_eq!(
+            searcher.matching_folders("report", &options, None),
+            Ok(vec![downloads, work])
+        );
+        assert!(
+            searcher
+                .matching_folders("regex:(", &options, None)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_search_or_sorted() {
+        let mut tree = FileTree::with_capacity(6);
+        let big_ab = tree
+            .add_or_update_recursive("alpha beta.txt", Some(500), None, None, Attributes::NONE)
+            .unwrap();
+        let small_a = tree
+            .add_or_update_recursive("alpha.txt", Some(10), None, None, Attributes::NONE)
+            .unwrap();
+        let mid_b = tree
+            .add_or_update_recursive("beta.txt", Some(200), None, None, Attributes::NONE)
+            .unwrap();
+        let huge_a = tree
+            .add_or_update_recursive("alpha 2.txt", Some(9000), None, None, Attributes::NONE)
+            .unwrap();
+        tree.add_test_file("gamma.txt");
+        let searcher = Searcher::from_file_tree(tree);
+
+        // "alpha beta.txt" matches both branches, but is only returned once
+        assert_eq!(
+            searcher.search(
+                "alpha | beta",
+                Some(SortField::Size),
+                Some(SortOrder::Ascending)
+            ),
+            vec![small_a, mid_b, big_ab, huge_a]
+        );
+        assert_eq!(
+            searcher.search(
+                "beta | alpha",
+                Some(SortField::Size),
+                Some(SortOrder::Descending)
+            ),
+            vec![huge_a, big_ab, mid_b, small_a]
+        );
+    }
+
+    #[test]
+    fn test_refine_search() {
+        let searcher = Searcher::from_file_tree(FileTree::generate_synthetic(5_000, 9));
+        let options = SearchOptions {
+            sort_by: Some(SortField::Size),
+            ..Default::default()
+        };
+        let previous = searcher.search_with_options("re", &options);
+
+        // Refine-eligible: extended text, more AND conditions, the text appearing anywhere
+        for query in [
+            "rep",
+            "REPORT",
+            "re ext:pdf",
+            "size:>1000 report",
+            "are",
+            "re dupe:size",
+        ] {
+            let refined = searcher.refine_search("re", &previous, query, &options);
+            assert_eq!(
+                refined,
+                Some(searcher.search_with_options(query, &options)),
+                "query '{}'",
+                query
+            );
+        }
+
+        // dupe: keeps only the duplicates among the refined results
+        let duplicates = searcher.refine_search("re", &previous, "re dupe:size", &options);
+        assert!(duplicates.is_some_and(|duplicates| duplicates.len() < previous.len()));
+
+        // Ineligible: could match elements the previous query didn't
+        for (previous_query, query) in [
+            ("re", "r"),
+            ("re", "re | pdf"),
+            ("re", "!re"),
+            ("re", "case:rep"),
+            ("re", "path:rep"),
+            ("re", "regex:rep"),
+            ("ww:re", "ww:rep"),
+            ("wfn:re", "wfn:rep"),
+            ("re pdf", "rep"),
+            ("re", "regex:rep("),
+        ] {
+            assert_eq!(
+                searcher.refine_search(previous_query, &previous, query, &options),
+                None,
+                "'{}' to '{}'",
+                previous_query,
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn test_search_invalid_regex() {
+        let mut tree = FileTree::with_capacity(2);
+        tree.add_test_file("aaa.txt");
+        let searcher = Searcher::from_file_tree(tree);
+        let options = SearchOptions {
+            regex_size_limit: Some(100),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            searcher.try_search_with_options("regex:a{100}", &options),
+            Err(QueryError::RegexTooLarge { limit: 100, .. })
+        ));
+        // The infallible variant returns no results instead of everything
+        assert!(
+            searcher
+                .search_with_options("regex:a{100}", &options)
+                .is_empty()
+        );
+        assert_eq!(
+            searcher.search_with_options("regex:a{3}", &options).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_search_matches_linear_scan() {
+        let searcher = Searcher::from_file_tree(FileTree::generate_synthetic(20_000, 1));
+        let tree = searcher.get_file_tree();
+        for query in ["r", "日", "re", "report", "café", "summary_0", "übersicht"] {
+            let expected: Vec<usize> = (1..tree.len())
+                .filter(|&index| tree.get_filename(index).to_lowercase().contains(query))
+                .collect();
+            assert!(!expected.is_empty());
+            assert_eq!(
+                searcher.search(query, None, None),
+                expected,
+                "query '{}'",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn test_root_is_never_a_result() {
+        let mut tree = FileTree::with_capacity(3);
+        let report = tree.add_test_file("C:\\report.txt");
+        let searcher = Searcher::from_file_tree(tree);
+        // The root matches negations and functions, and "ro" is in its name
+        for query in ["!report", "file:", "dm:unknown", "ro"] {
+            assert!(
+                !searcher.search(query, None, None).contains(&0),
+                "{}",
+                query
+            );
+        }
+        // Only the C: folder is left
+        let drive = searcher.file_tree.elements[report].parent;
+        assert_eq!(searcher.search("!report", None, None), vec![drive]);
+        let everything: Vec<usize> = (0..searcher.file_tree.len()).collect();
+        assert!(
+            !searcher
+                .search_within("!report", &everything, &SearchOptions::default())
+                .unwrap()
+                .contains(&0)
+        );
+    }
+
+    #[test]
+    fn test_search_ignore_list() {
+        let mut tree = FileTree::with_capacity(10);
+        let kept = [
+            tree.add_test_file("C:\\notes.txt"),
+            tree.add_test_file("D:\\git\\notes.md"),
+        ];
+        for path in [
+            "C:\\$Recycle.Bin\\S-1-5\\notes.txt",
+            "D:\\Projects\\.git\\notes",
+            "D:\\Projects\\notes.tmp",
+        ] {
+            tree.add_test_file(path);
+        }
+        let mut searcher = Searcher::from_file_tree(tree);
+        assert_eq!(searcher.search("notes", None, None).len(), 5);
+
+        searcher.set_ignore_list(&IgnoreList::parse("$recycle.bin;.git;*.tmp").unwrap());
+        assert_eq!(searcher.search("notes", None, None), kept);
+        // Ignored folders themselves are hidden too
+        assert!(searcher.search(".git", None, None).is_empty());
+        assert!(searcher.search("S-1-5", None, None).is_empty());
+        assert_eq!(searcher.search("git", None, None).len(), 1);
+
+        searcher.set_ignore_list(&IgnoreList::parse("").unwrap());
+        assert_eq!(searcher.search("notes", None, None).len(), 5);
+    }
+
+    #[test]
+    fn test_scope_root() {
+        let mut tree = FileTree::with_capacity(10);
+        let inside = [
+            tree.add_test_file("C:\\project\\notes.txt"),
+            tree.add_test_file("C:\\project\\docs\\notes.md"),
+        ];
+        tree.add_test_file("C:\\other\\notes.txt");
+        tree.add_test_file("C:\\notes.txt");
+        let project = tree.find_by_path("C:\\project").unwrap();
+        let docs = tree.find_by_path("C:\\project\\docs").unwrap();
+        let searcher = Searcher::from_file_tree(tree).with_scope_root(project);
+
+        assert_eq!(searcher.search("notes", None, None), inside);
+        let mut all = inside.to_vec();
+        all.push(docs);
+        all.sort_unstable();
+        for query in [
+            "",
+            "regex:.*",
+            "path:c:",
+            "parent:other | project",
+            "size:>0 | !size:>0",
+        ] {
+            let results = searcher.search(query, None, None);
+            assert!(results.iter().all(|index| all.contains(index)), "{}", query);
+        }
+        assert_eq!(searcher.search("", None, None), all);
+        assert_eq!(
+            searcher.all_page(0, 10, None, None),
+            (all.clone(), all.len())
+        );
+        assert_eq!(
+            searcher.all_page(1, 10, Some(SortField::Filename), None).1,
+            all.len()
+        );
+        assert!(searcher.search("parent:other", None, None).is_empty());
+        // Facets only count folders inside the scope, the scope root itself isn't part of it
+        let notes = searcher.search("notes", None, None);
+        assert_eq!(
+            searcher.facet_by_parent(&notes, 3),
+            vec![("C:\\project\\docs".to_string(), 1)]
+        );
+        assert!(searcher.facet_by_parent(&notes, 2).is_empty());
+        assert!(searcher.facet_by_parent(&notes, 1).is_empty());
+        // Candidates outside the scope are dropped as well
+        let everything: Vec<usize> = (0..searcher.file_tree.len()).collect();
+        assert_eq!(
+            searcher
+                .search_within("notes", &everything, &SearchOptions::default())
+                .unwrap(),
+            inside
+        );
+        // Folders are never rolled up above the scope root
+        let folders = searcher.matching_folders("notes", &SearchOptions::default(), Some(1));
+        assert!(folders.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_search_unsorted_is_ascending() {
+        let searcher = Searcher::from_file_tree(FileTree::generate_synthetic(20_000, 5));
+        let queries = [
+            "r",
+            "report | summary | r",
+            "e | !a",
+            "<photo | 日> size:>1000",
+            "ext:txt;jpg | café",
+            "dupe:size report",
+        ];
+        for query in queries {
+            let first = searcher.search(query, None, None);
+            assert!(!first.is_empty(), "query '{}'", query);
+            assert!(
+                first.windows(2).all(|pair| pair[0] < pair[1]),
+                "query '{}'",
+                query
+            );
+            assert_eq!(
+                searcher.search(query, None, None),
+                first,
+                "query '{}'",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn test_rank_by_relevance() {
+        let mut tree = FileTree::with_capacity(8);
+        let [annual, reports, report] = ["C:\\annual report.pdf", "C:\\reports", "C:\\report.pdf"]
+            .map(|path| tree.add_test_file(path));
+        let searcher = Searcher::from_file_tree(tree);
+        let ranked = SearchOptions {
+            rank_by_relevance: true,
+            ..SearchOptions::default()
+        };
+
+        let results = searcher.try_search_with_options("report", &ranked).unwrap();
+        assert_eq!(results, vec![report, reports, annual]);
+        // Refining ranks by the new query, the same as searching it
+        let refined = searcher
+            .refine_search("report", &results, "report annual", &ranked)
+            .unwrap();
+        assert_eq!(
+            refined,
+            searcher
+                .try_search_with_options("report annual", &ranked)
+                .unwrap()
+        );
+        assert_eq!(refined, vec![annual]);
+        // An explicit sort or a query without text keeps the usual order
+        let sorted = SearchOptions {
+            sort_by: Some(SortField::Filename),
+            ..ranked.clone()
+        };
+        assert_eq!(
+            searcher.try_search_with_options("report", &sorted).unwrap(),
+            vec![annual, report, reports]
+        );
+        assert!(searcher.relevance_ranker_for("report", &sorted).is_none());
+        assert!(searcher.relevance_ranker_for("ext:pdf", &ranked).is_none());
+        assert!(
+            searcher
+                .relevance_ranker_for("report", &SearchOptions::default())
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_suggest_correction() {
+        let mut tree = FileTree::with_capacity(8);
+        for path in [
+            "C:\\Documents\\Invoice 2023.pdf",
+            "C:\\Documents\\holiday_photos.zip",
+            "C:\\Music\\playlist.m3u",
+        ] {
+            tree.add_test_file(path);
+        }
+        let searcher = Searcher::from_file_tree(tree);
+        let options = SearchOptions::default();
+
+        assert_eq!(
+            searcher.suggest_correction("invoise", &options).as_deref(),
+            Some("invoice")
+        );
+        // Words that match are kept, only the misspelled one is replaced
+        assert_eq!(
+            searcher
+                .suggest_correction("holliday zip", &options)
+                .as_deref(),
+            Some("holiday zip")
+        );
+        assert_eq!(
+            searcher
+                .suggest_correction("ext:pdf Invioce", &options)
+                .as_deref(),
+            Some("ext:pdf invoice")
+        );
+        // Nothing close enough
+        assert_eq!(searcher.suggest_correction("xylophone", &options), None);
+        // Correct words aren't replaced, so there is nothing to suggest
+        assert_eq!(
+            searcher.suggest_correction("playlist ext:pdf", &options),
+            None
+        );
+        // Function arguments and quoted text keep their case and spelling
+        assert_eq!(
+            searcher
+                .suggest_correction("regex:^Inv invoise", &options)
+                .as_deref(),
+            Some("regex:^Inv invoice")
+        );
+        assert_eq!(
+            searcher.suggest_correction("\"holliday photos\"", &options),
+            None
+        );
+        // The corrected query has to match with the same options
+        let folders = SearchOptions {
+            element_type: Some(ElementType::Folder),
+            ..SearchOptions::default()
+        };
+        assert_eq!(searcher.suggest_correction("invoise", &folders), None);
+    }
+
+    #[test]
+    fn test_search_within() {
+        let mut tree = FileTree::with_capacity(6);
+        let mut add = |name: &str, size: i64| {
+            tree.add_or_update_recursive(name, Some(size), None, None, Attributes::NONE)
+                .unwrap()
+        };
+        let big_report = add("big report.pdf", 3000);
+        let report = add("report.txt", 10);
+        let other_report = add("other report.txt", 20);
+        let notes = add("notes.txt", 30);
+        let searcher = Searcher::from_file_tree(tree);
+
+        // Only the selected elements are searched, duplicates and order don't matter
+        let selection = [notes, big_report, report, big_report];
+        let options = SearchOptions::default();
+        assert_eq!(
+            searcher.search_within("report", &selection, &options),
+            Ok(vec![big_report, report])
+        );
+        let options = SearchOptions {
+            sort_by: Some(SortField::Size),
+            ..Default::default()
+        };
+        assert_eq!(
+            searcher.search_within("txt", &[other_report, notes, report], &options),
+            Ok(vec![report, other_report, notes])
+        );
+        assert_eq!(
+            searcher.search_within("report", &[report, 99], &options),
+            Err(SearchError::IndexOutOfRange { index: 99, len: 5 })
+        );
+        assert!(matches!(
+            searcher.search_within("regex:(", &[report], &options),
+            Err(SearchError::Query(QueryError::InvalidRegex { .. }))
+        ));
+    }
+
+    // Collects the fields recorded on search spans
+    #[derive(Clone, Default)]
+    struct SpanFields(std::sync::Arc<std::sync::Mutex<HashMap<String, String>>>);
+    impl tracing::field::Visit for SpanFields {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            let value = format!("{:?}", value);
+            self.0
+                .lock()
+                .unwrap()
+                .insert(field.name().to_string(), value);
+        }
+    }
+    impl<S> tracing_subscriber::Layer<S> for SpanFields
+    where
+        S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            _id: &tracing::span::Id,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            if attrs.metadata().name() == "search" {
+                attrs.record(&mut self.clone());
+            }
+        }
+        fn on_record(
+            &self,
+            id: &tracing::span::Id,
+            values: &tracing::span::Record<'_>,
+            ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            if ctx
+                .metadata(id)
+                .is_some_and(|metadata| metadata.name() == "search")
+            {
+                values.record(&mut self.clone());
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_span_fields() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let mut tree = FileTree::with_capacity(4);
+        for name in ["report.pdf", "report.txt", "notes.txt"] {
+            tree.add_test_file(name);
+        }
+        let searcher = Searcher::from_file_tree(tree);
+        let fields = SpanFields::default();
+        let subscriber = tracing_subscriber::registry().with(fields.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            searcher.search("report ext:pdf", None, None);
+        });
+
+        let fields = fields.0.lock().unwrap();
+        assert_eq!(fields["query"], "\"report ext:pdf\"");
+        // The bigram index narrows the query down to both reports
+        assert_eq!(fields["candidate_count"], "2");
+        assert_eq!(fields["result_count"], "1");
+        assert!(fields.contains_key("elapsed"));
+    }
 }
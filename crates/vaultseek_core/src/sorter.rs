@@ -1,5 +1,5 @@
 use crate::file_tree::FileTree;
-use std::sync::Mutex;
+use std::sync::{Mutex, MutexGuard};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SortField {
@@ -14,11 +14,98 @@ pub enum SortOrder {
     Ascending,
     Descending,
 }
+// Where elements without the sort field go, e.g. files without a size. Without a policy they
+// sort before all others, so first ascending and last descending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NullsOrder {
+    First,
+    Last,
+}
+
+// The position of every element when sorted ascending by a field
+pub struct OrderList {
+    pub ranks: Vec<usize>,
+    pub nulls: usize, // Elements without the field, they have the lowest ranks
+}
+impl OrderList {
+    fn new(sorted: &[usize], nulls: usize) -> Self {
+        let mut ranks = vec![0; sorted.len()];
+        for (i, &index) in sorted.iter().enumerate() {
+            ranks[index] = i;
+        }
+        OrderList { ranks, nulls }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranks.is_empty()
+    }
+
+    // The position of the element in the sorted list of all elements
+    fn position(&self, index: usize, order: SortOrder, nulls: Option<NullsOrder>) -> usize {
+        let rank = self.ranks[index];
+        let is_null = rank < self.nulls;
+        match (order, nulls) {
+            (SortOrder::Ascending, Some(NullsOrder::Last)) if is_null => {
+                self.len() - self.nulls + rank
+            }
+            (SortOrder::Ascending, Some(NullsOrder::Last)) => rank - self.nulls,
+            (SortOrder::Descending, Some(NullsOrder::First)) if is_null => rank,
+            (SortOrder::Descending, Some(NullsOrder::First)) => self.len() - 1 - rank + self.nulls,
+            (SortOrder::Ascending, _) => rank,
+            (SortOrder::Descending, _) => self.len() - 1 - rank,
+        }
+    }
+}
+
+// Common sort orders by name, e.g. for a "recent files" view that doesn't know the fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortSpec {
+    Newest,
+    Oldest,
+    Largest,
+    Smallest,
+    NameAZ,
+    NameZA,
+}
+impl SortSpec {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "newest" => Some(SortSpec::Newest),
+            "oldest" => Some(SortSpec::Oldest),
+            "largest" => Some(SortSpec::Largest),
+            "smallest" => Some(SortSpec::Smallest),
+            "name_az" => Some(SortSpec::NameAZ),
+            "name_za" => Some(SortSpec::NameZA),
+            _ => None,
+        }
+    }
+
+    // Newest and oldest are by modified date
+    pub fn field_and_order(self) -> (SortField, SortOrder) {
+        match self {
+            SortSpec::Newest => (SortField::DateModified, SortOrder::Descending),
+            SortSpec::Oldest => (SortField::DateModified, SortOrder::Ascending),
+            SortSpec::Largest => (SortField::Size, SortOrder::Descending),
+            SortSpec::Smallest => (SortField::Size, SortOrder::Ascending),
+            SortSpec::NameAZ => (SortField::Filename, SortOrder::Ascending),
+            SortSpec::NameZA => (SortField::Filename, SortOrder::Descending),
+        }
+    }
+}
+
+#[derive(Default)]
 pub struct Sorter {
-    pub filename_order: Mutex<Option<Vec<usize>>>,
-    pub date_modified_order: Mutex<Option<Vec<usize>>>,
-    pub date_created_order: Mutex<Option<Vec<usize>>>,
-    pub size_order: Mutex<Option<Vec<usize>>>,
+    pub filename_order: Mutex<Option<OrderList>>,
+    pub date_modified_order: Mutex<Option<OrderList>>,
+    pub date_created_order: Mutex<Option<OrderList>>,
+    pub size_order: Mutex<Option<OrderList>>,
+    // Sort by filename ignoring case, so apple comes before Zebra. Uses its own order list.
+    pub case_insensitive_names: bool,
+    pub filename_nocase_order: Mutex<Option<OrderList>>,
 }
 impl Sorter {
     pub fn new() -> Self {
@@ -27,9 +114,16 @@ impl Sorter {
             date_modified_order: Mutex::new(None),
             date_created_order: Mutex::new(None),
             size_order: Mutex::new(None),
+            case_insensitive_names: false,
+            filename_nocase_order: Mutex::new(None),
         }
     }
 
+    pub fn with_case_insensitive_names(mut self, case_insensitive_names: bool) -> Self {
+        self.case_insensitive_names = case_insensitive_names;
+        self
+    }
+
     pub fn sort_by(
         &self,
         tree: &FileTree,
@@ -37,46 +131,137 @@ impl Sorter {
         field: SortField,
         order: SortOrder,
     ) {
+        self.sort_by_with_nulls(tree, elements, field, order, None);
+    }
+
+    // Like sort_by, but elements without the field go first or last regardless of the order
+    pub fn sort_by_with_nulls(
+        &self,
+        tree: &FileTree,
+        elements: &mut [usize],
+        field: SortField,
+        order: SortOrder,
+        nulls: Option<NullsOrder>,
+    ) {
+        let order_list = self.order_list(tree, field);
+        self.sort_by_order_list(elements, order_list.as_ref().unwrap(), order, nulls);
+    }
+    // Moves the directories in front of the files like a file manager, keeping the order within
+    // both, so after sort_by each group is still sorted. Only elements with the directory
+    // attribute count, like in folder: queries.
+    pub fn group_dirs_first(&self, tree: &FileTree, elements: &mut [usize]) {
+        elements.sort_by_key(|&index| !tree.elements[index].is_dir()); // Stable
+    }
+
+    // Returns one page of all elements except the root, sorted by the field, without sorting a
+    // list of every index. The order list is scanned once and only the page is allocated.
+    pub fn page(
+        &self,
+        tree: &FileTree,
+        field: SortField,
+        order: SortOrder,
+        offset: usize,
+        len: usize,
+    ) -> Vec<usize> {
+        let order_list = self.order_list(tree, field);
+        let order_list = order_list.as_ref().unwrap();
+        let total = order_list.len().saturating_sub(1); // Without the root
+        let len = len.min(total.saturating_sub(offset));
+        if len == 0 {
+            return Vec::new();
+        }
+
+        let position = |index: usize| order_list.position(index, order, None);
+        let root_position = position(0);
+        let mut page = vec![usize::MAX; len];
+        for index in 1..order_list.len() {
+            let mut position = position(index);
+            // Close the gap left by the root
+            if position > root_position {
+                position -= 1;
+            }
+            if (offset..offset + len).contains(&position) {
+                page[position - offset] = index;
+            }
+        }
+        page
+    }
+
+    // Builds all order lists now instead of on the first sort by each field, e.g. at startup.
+    // Each list has its own lock, so they are built in parallel.
+    pub fn warm_all(&self, tree: &FileTree) {
+        std::thread::scope(|scope| {
+            // Only the filename order the sorter uses
+            scope.spawn(|| drop(self.order_list(tree, SortField::Filename)));
+            scope.spawn(|| self.prepare_date_modified_order(tree));
+            scope.spawn(|| self.prepare_date_created_order(tree));
+            self.prepare_size_order(tree);
+        });
+    }
+
+    fn order_list(&self, tree: &FileTree, field: SortField) -> MutexGuard<'_, Option<OrderList>> {
         match field {
+            SortField::Filename if self.case_insensitive_names => {
+                self.prepare_filename_nocase_order(tree);
+                self.filename_nocase_order.lock().unwrap()
+            }
             SortField::Filename => {
                 self.prepare_filename_order(tree);
-                let filename_order = self.filename_order.lock().unwrap();
-                self.sort_by_order_list(elements, filename_order.as_ref().unwrap(), order);
+                self.filename_order.lock().unwrap()
             }
             SortField::DateModified => {
                 self.prepare_date_modified_order(tree);
-                let date_modified_order = self.date_modified_order.lock().unwrap();
-                self.sort_by_order_list(elements, date_modified_order.as_ref().unwrap(), order);
+                self.date_modified_order.lock().unwrap()
             }
             SortField::DateCreated => {
-                self.prepare_date_created_order(tree); // Reuse the same method for date created
-                let date_created_order = self.date_created_order.lock().unwrap();
-                self.sort_by_order_list(elements, date_created_order.as_ref().unwrap(), order);
+                self.prepare_date_created_order(tree);
+                self.date_created_order.lock().unwrap()
             }
             SortField::Size => {
                 self.prepare_size_order(tree);
-                let size_order = self.size_order.lock().unwrap();
-                self.sort_by_order_list(elements, size_order.as_ref().unwrap(), order);
+                self.size_order.lock().unwrap()
             }
         }
     }
+
     fn prepare_filename_order(&self, tree: &FileTree) {
         let mut filename_order = self.filename_order.lock().unwrap();
         if filename_order.is_none() {
-            println!("Preparing filename order...");
+            let _span = tracing::debug_span!("prepare_order", field = "filename").entered();
             let timestamp = std::time::Instant::now();
             let mut sorted: Vec<usize> = (0..tree.get_elements().len()).collect();
-            sorted.sort_unstable_by(|&a, &b| tree.get_filename(a).cmp(&tree.get_filename(b)));
-            let mut order = vec![0; sorted.len()];
+            sorted.sort_unstable_by(|&a, &b| tree.get_filename(a).cmp(tree.get_filename(b)));
+            let order = OrderList::new(&sorted, 0);
 
-            for (i, &index) in sorted.iter().enumerate() {
-                order[index] = i;
-            }
+            tracing::debug!(
+                entries = order.len(),
+                elapsed = ?timestamp.elapsed(),
+                "Filename order prepared"
+            );
+            filename_order.replace(order);
+        }
+    }
 
-            println!(
-                "Filename order prepared with {} entries in {:?}",
-                order.len(),
-                timestamp.elapsed()
+    fn prepare_filename_nocase_order(&self, tree: &FileTree) {
+        let mut filename_order = self.filename_nocase_order.lock().unwrap();
+        if filename_order.is_none() {
+            let _span = tracing::debug_span!("prepare_order", field = "filename_nocase").entered();
+            let timestamp = std::time::Instant::now();
+            let mut sorted: Vec<usize> = (0..tree.get_elements().len()).collect();
+            // Names differing only in case are ordered case-sensitively, so the order is stable
+            sorted.sort_unstable_by(|&a, &b| {
+                let (a, b) = (tree.get_filename(a), tree.get_filename(b));
+                a.chars()
+                    .flat_map(char::to_lowercase)
+                    .cmp(b.chars().flat_map(char::to_lowercase))
+                    .then_with(|| a.cmp(b))
+            });
+            let order = OrderList::new(&sorted, 0);
+
+            tracing::debug!(
+                entries = order.len(),
+                elapsed = ?timestamp.elapsed(),
+                "Case-insensitive filename order prepared"
             );
             filename_order.replace(order);
         }
@@ -85,7 +270,7 @@ impl Sorter {
     fn prepare_date_modified_order(&self, tree: &FileTree) {
         let mut date_modified_order = self.date_modified_order.lock().unwrap();
         if date_modified_order.is_none() {
-            println!("Preparing date modified order...");
+            let _span = tracing::debug_span!("prepare_order", field = "date_modified").entered();
             let timestamp = std::time::Instant::now();
             let mut sorted: Vec<usize> = (0..tree.get_elements().len()).collect();
             sorted.sort_unstable_by(|&a, &b| {
@@ -94,16 +279,17 @@ impl Sorter {
                     .date_modified
                     .cmp(&tree.get(b).unwrap().date_modified)
             });
-            let mut order = vec![0; sorted.len()];
-
-            for (i, &index) in sorted.iter().enumerate() {
-                order[index] = i;
-            }
+            let nulls = tree
+                .get_elements()
+                .iter()
+                .filter(|e| e.date_modified.is_none())
+                .count();
+            let order = OrderList::new(&sorted, nulls);
 
-            println!(
-                "Date modified order prepared with {} entries in {:?}",
-                order.len(),
-                timestamp.elapsed()
+            tracing::debug!(
+                entries = order.len(),
+                elapsed = ?timestamp.elapsed(),
+                "Date modified order prepared"
             );
             date_modified_order.replace(order);
         }
@@ -112,7 +298,7 @@ impl Sorter {
     fn prepare_date_created_order(&self, tree: &FileTree) {
         let mut date_created_order = self.date_created_order.lock().unwrap();
         if date_created_order.is_none() {
-            println!("Preparing date created order...");
+            let _span = tracing::debug_span!("prepare_order", field = "date_created").entered();
             let timestamp = std::time::Instant::now();
             let mut sorted: Vec<usize> = (0..tree.get_elements().len()).collect();
             sorted.sort_unstable_by(|&a, &b| {
@@ -121,16 +307,17 @@ impl Sorter {
                     .date_created
                     .cmp(&tree.get(b).unwrap().date_created)
             });
-            let mut order = vec![0; sorted.len()];
+            let nulls = tree
+                .get_elements()
+                .iter()
+                .filter(|e| e.date_created.is_none())
+                .count();
+            let order = OrderList::new(&sorted, nulls);
 
-            for (i, &index) in sorted.iter().enumerate() {
-                order[index] = i;
-            }
-
-            println!(
-                "Date created order prepared with {} entries in {:?}",
-                order.len(),
-                timestamp.elapsed()
+            tracing::debug!(
+                entries = order.len(),
+                elapsed = ?timestamp.elapsed(),
+                "Date created order prepared"
             );
             date_created_order.replace(order);
         }
@@ -139,22 +326,23 @@ impl Sorter {
     fn prepare_size_order(&self, tree: &FileTree) {
         let mut size_order = self.size_order.lock().unwrap();
         if size_order.is_none() {
-            println!("Preparing size order...");
+            let _span = tracing::debug_span!("prepare_order", field = "size").entered();
             let timestamp = std::time::Instant::now();
             let mut sorted: Vec<usize> = (0..tree.get_elements().len()).collect();
             sorted.sort_unstable_by(|&a, &b| {
                 tree.get(a).unwrap().size.cmp(&tree.get(b).unwrap().size)
             });
-            let mut order = vec![0; sorted.len()];
-
-            for (i, &index) in sorted.iter().enumerate() {
-                order[index] = i;
-            }
+            let nulls = tree
+                .get_elements()
+                .iter()
+                .filter(|e| e.size.is_none())
+                .count();
+            let order = OrderList::new(&sorted, nulls);
 
-            println!(
-                "Size order prepared with {} entries in {:?}",
-                order.len(),
-                timestamp.elapsed()
+            tracing::debug!(
+                entries = order.len(),
+                elapsed = ?timestamp.elapsed(),
+                "Size order prepared"
             );
             size_order.replace(order);
         }
@@ -163,25 +351,18 @@ impl Sorter {
     fn sort_by_order_list(
         &self,
         elements: &mut [usize],
-        order_list: &Vec<usize>,
+        order_list: &OrderList,
         order: SortOrder,
+        nulls: Option<NullsOrder>,
     ) {
-        let len = order_list.len();
-        let mut elements_sorted: Vec<usize> = vec![usize::MAX; len];
-        if order == SortOrder::Ascending {
-            for &index in elements.as_ref() {
-                elements_sorted[order_list[index]] = index;
-            }
-        } else {
-            for &index in elements.as_ref() {
-                // For descending order, we need to reverse the order
-                elements_sorted[len - 1 - order_list[index]] = index;
-            }
+        let mut elements_sorted: Vec<usize> = vec![usize::MAX; order_list.len()];
+        for &index in elements.iter() {
+            elements_sorted[order_list.position(index, order, nulls)] = index;
         }
         let mut counter = 0;
-        for i in 0..elements_sorted.len() {
-            if elements_sorted[i] != usize::MAX {
-                elements[counter] = elements_sorted[i];
+        for &index in &elements_sorted {
+            if index != usize::MAX {
+                elements[counter] = index;
                 counter += 1;
             }
         }
@@ -191,30 +372,55 @@ impl Sorter {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::attributes::Attributes;
 
     #[test]
     fn test_sorter() {
         let mut tree = FileTree::with_capacity(10);
 
-        let element1 = tree.add_or_update_recursive(
-            "some/path/file1.txt",
-            Some(1000),
-            Some(4000),
-            Some(3000),
-            0,
-        );
-        let element2 = tree.add_or_update_recursive(
-            "other/path/file2.txt",
-            Some(3000),
-            Some(1000),
-            Some(4000),
-            0,
+        let element1 = tree
+            .add_or_update_recursive(
+                "some/path/file1.txt",
+                Some(1000),
+                Some(4000),
+                Some(3000),
+                Attributes::NONE,
+            )
+            .unwrap();
+        let element2 = tree
+            .add_or_update_recursive(
+                "other/path/file2.txt",
+                Some(3000),
+                Some(1000),
+                Some(4000),
+                Attributes::NONE,
+            )
+            .unwrap();
+        let element3 = tree
+            .add_or_update_recursive(
+                "mydir/file3.txt",
+                Some(2000),
+                Some(2000),
+                Some(2000),
+                Attributes::NONE,
+            )
+            .unwrap();
+        let element4 = tree
+            .add_or_update_recursive(
+                "C:/file4.txt",
+                Some(4000),
+                Some(3000),
+                Some(1000),
+                Attributes::NONE,
+            )
+            .unwrap();
+        tree.add_or_update_recursive(
+            "file5.txt",
+            Some(5000),
+            Some(5000),
+            Some(5000),
+            Attributes::NONE,
         );
-        let element3 =
-            tree.add_or_update_recursive("mydir/file3.txt", Some(2000), Some(2000), Some(2000), 0);
-        let element4 =
-            tree.add_or_update_recursive("C:/file4.txt", Some(4000), Some(3000), Some(1000), 0);
-        tree.add_or_update_recursive("file5.txt", Some(5000), Some(5000), Some(5000), 0);
 
         let sorter = Sorter::new();
         let mut indices = vec![element1, element2, element3, element4];
@@ -281,4 +487,183 @@ mod tests {
         );
         assert_eq!(indices, vec![element2, element1, element3, element4]);
     }
+
+    #[test]
+    fn test_case_insensitive_names() {
+        let mut tree = FileTree::with_capacity(6);
+        let zebra = tree.add_test_file("Zebra");
+        let apple = tree.add_test_file("apple");
+        let upper_apple = tree.add_test_file("Apple");
+        let banana = tree.add_test_file("banana");
+        let mut indices = vec![zebra, apple, upper_apple, banana];
+
+        // By default uppercase sorts before lowercase
+        Sorter::new().sort_by(
+            &tree,
+            &mut indices,
+            SortField::Filename,
+            SortOrder::Ascending,
+        );
+        assert_eq!(indices, vec![upper_apple, zebra, apple, banana]);
+
+        let sorter = Sorter::new().with_case_insensitive_names(true);
+        sorter.sort_by(
+            &tree,
+            &mut indices,
+            SortField::Filename,
+            SortOrder::Ascending,
+        );
+        assert_eq!(indices, vec![upper_apple, apple, banana, zebra]);
+        sorter.sort_by(
+            &tree,
+            &mut indices,
+            SortField::Filename,
+            SortOrder::Descending,
+        );
+        assert_eq!(indices, vec![zebra, banana, apple, upper_apple]);
+        let page = sorter.page(&tree, SortField::Filename, SortOrder::Ascending, 0, 4);
+        assert_eq!(page, vec![upper_apple, apple, banana, zebra]);
+        // The case-sensitive order is kept separately
+        assert!(sorter.filename_order.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_nulls_order() {
+        let mut tree = FileTree::with_capacity(6);
+        let mut add = |name: &str, size: Option<i64>| {
+            tree.add_or_update_recursive(name, size, None, None, Attributes::NONE)
+                .unwrap()
+        };
+        let small = add("small", Some(1));
+        let unknown = add("unknown", None);
+        let large = add("large", Some(9));
+        let medium = add("medium", Some(5));
+        let sorter = Sorter::new();
+        let sorted = |order, nulls| {
+            let mut indices = vec![small, unknown, large, medium];
+            sorter.sort_by_with_nulls(&tree, &mut indices, SortField::Size, order, nulls);
+            indices
+        };
+
+        // Without a policy unknown sizes sort as the smallest
+        assert_eq!(
+            sorted(SortOrder::Ascending, None),
+            vec![unknown, small, medium, large]
+        );
+        assert_eq!(
+            sorted(SortOrder::Descending, None),
+            vec![large, medium, small, unknown]
+        );
+        for order in [SortOrder::Ascending, SortOrder::Descending] {
+            let first = sorted(order, Some(NullsOrder::First));
+            let last = sorted(order, Some(NullsOrder::Last));
+            assert_eq!(first[0], unknown);
+            assert_eq!(last[3], unknown);
+            // The known sizes keep their order
+            assert_eq!(first[1..], last[..3]);
+        }
+        assert_eq!(
+            sorted(SortOrder::Ascending, Some(NullsOrder::Last)),
+            vec![small, medium, large, unknown]
+        );
+        assert_eq!(
+            sorted(SortOrder::Descending, Some(NullsOrder::First)),
+            vec![unknown, large, medium, small]
+        );
+    }
+
+    #[test]
+    fn test_group_dirs_first() {
+        let mut tree = FileTree::with_capacity(6);
+        let mut add = |name: &str, attributes| {
+            tree.add_or_update_recursive(name, Some(1), None, None, attributes)
+                .unwrap()
+        };
+        let b_file = add("b.txt", Attributes::NONE);
+        let c_dir = add("c", Attributes::DIRECTORY);
+        let a_file = add("a.txt", Attributes::NONE);
+        let d_dir = add("d", Attributes::DIRECTORY);
+        let sorter = Sorter::new();
+
+        let mut indices = vec![b_file, c_dir, a_file, d_dir];
+        sorter.sort_by(
+            &tree,
+            &mut indices,
+            SortField::Filename,
+            SortOrder::Ascending,
+        );
+        sorter.group_dirs_first(&tree, &mut indices);
+        assert_eq!(indices, vec![c_dir, d_dir, a_file, b_file]);
+
+        sorter.sort_by(
+            &tree,
+            &mut indices,
+            SortField::Filename,
+            SortOrder::Descending,
+        );
+        sorter.group_dirs_first(&tree, &mut indices);
+        assert_eq!(indices, vec![d_dir, c_dir, b_file, a_file]);
+    }
+
+    #[test]
+    fn test_page() {
+        let tree = FileTree::generate_synthetic(1_000, 4);
+        let sorter = Sorter::new();
+        for field in [SortField::Filename, SortField::Size] {
+            for order in [SortOrder::Ascending, SortOrder::Descending] {
+                let mut all: Vec<usize> = (1..tree.len()).collect();
+                sorter.sort_by(&tree, &mut all, field, order);
+                let pages: Vec<usize> = (0..tree.len())
+                    .step_by(100)
+                    .flat_map(|offset| sorter.page(&tree, field, order, offset, 100))
+                    .collect();
+                assert_eq!(pages, all, "{:?} {:?}", field, order);
+            }
+        }
+        assert!(
+            sorter
+                .page(
+                    &tree,
+                    SortField::Size,
+                    SortOrder::Ascending,
+                    tree.len(),
+                    100
+                )
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_warm_all() {
+        let tree = FileTree::generate_synthetic(1_000, 3);
+        let sorter = Sorter::new();
+        assert!(sorter.filename_order.lock().unwrap().is_none());
+
+        sorter.warm_all(&tree);
+        for order in [
+            &sorter.filename_order,
+            &sorter.date_modified_order,
+            &sorter.date_created_order,
+            &sorter.size_order,
+        ] {
+            assert_eq!(order.lock().unwrap().as_ref().unwrap().len(), tree.len());
+        }
+    }
+
+    #[test]
+    fn test_sort_spec() {
+        let presets = [
+            ("newest", SortField::DateModified, SortOrder::Descending),
+            ("Oldest", SortField::DateModified, SortOrder::Ascending),
+            ("largest", SortField::Size, SortOrder::Descending),
+            ("smallest", SortField::Size, SortOrder::Ascending),
+            ("name_az", SortField::Filename, SortOrder::Ascending),
+            ("NAME_ZA", SortField::Filename, SortOrder::Descending),
+        ];
+        for (name, field, order) in presets {
+            let spec = SortSpec::from_name(name).unwrap();
+            assert_eq!(spec.field_and_order(), (field, order), "{}", name);
+        }
+        assert_eq!(SortSpec::from_name("date_modified"), None);
+    }
 }
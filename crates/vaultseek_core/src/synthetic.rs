@@ -0,0 +1,218 @@
+// Generator for synthetic file trees, used by benchmarks and tests that shouldn't depend on a real filelist.efu.
+// Also has the helper tests use to add single files.
+// The output only depends on the seed, so results are reproducible across runs and machines.
+
+use crate::{attributes::Attributes, file_tree::FileTree};
+
+const ROOT_DIRS: [&str; 6] = [
+    "C:\\Users\\Public",
+    "C:\\Program Files",
+    "C:\\Windows",
+    "D:\\Media",
+    "D:\\Projects",
+    "E:\\Backup",
+];
+const WORDS: [&str; 32] = [
+    "report",
+    "invoice",
+    "holiday",
+    "photo",
+    "music",
+    "album",
+    "project",
+    "notes",
+    "backup",
+    "draft",
+    "final",
+    "summary",
+    "budget",
+    "index",
+    "readme",
+    "config",
+    "setup",
+    "data",
+    "archive",
+    "meeting",
+    "résumé",
+    "café",
+    "übersicht",
+    "日本",
+    "brand",
+    "logo",
+    "video",
+    "track",
+    "chapter",
+    "scan",
+    "export",
+    "node_modules",
+];
+const EXTENSIONS: [&str; 16] = [
+    "txt", "pdf", "docx", "xlsx", "jpg", "png", "mp3", "mp4", "mkv", "zip", "rs", "js", "html",
+    "json", "exe", "dll",
+];
+
+// FILETIME range of the generated dates, 2001-01-01 until 2025-01-01
+const DATE_START: i64 = 126_227_808_000_000_000;
+const DATE_END: i64 = 133_801_632_000_000_000;
+
+// SplitMix64, a tiny deterministic PRNG so the same seed always produces the same tree
+struct Rng(u64);
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len())]
+    }
+    fn date(&mut self) -> i64 {
+        DATE_START + (self.next_u64() % (DATE_END - DATE_START) as u64) as i64
+    }
+    fn size(&mut self) -> i64 {
+        // Log-uniform up to ~16 GiB, most files are small
+        let bits = self.below(35) as u32;
+        (self.next_u64() % (1u64 << bits).max(1)) as i64
+    }
+}
+
+fn random_name(rng: &mut Rng) -> String {
+    let mut name = rng.pick(&WORDS).to_string();
+    match rng.below(4) {
+        0 => name.push_str(&format!("_{:04}", rng.below(10_000))),
+        1 => {
+            name.push(' ');
+            name.push_str(rng.pick(&WORDS));
+        }
+        2 => name = name.to_uppercase(),
+        _ => {}
+    }
+    name
+}
+
+impl FileTree {
+    // Generate a tree with `num_files` files spread over a random directory structure. Only public
+    // for the benches, the bins and the web UI tests, so it's left out of the docs.
+    #[doc(hidden)]
+    pub fn generate_synthetic(num_files: usize, seed: u64) -> FileTree {
+        let mut rng = Rng(seed);
+        let mut tree = FileTree::with_capacity(num_files + num_files / 8);
+
+        let mut dirs: Vec<(String, usize)> = Vec::new(); // (path, depth)
+        for root in ROOT_DIRS {
+            // Add the parent folders as well, so every folder has the directory attribute
+            for (i, _) in root.match_indices('\\') {
+                tree.add_or_update_recursive(&root[..i], None, None, None, Attributes::DIRECTORY);
+            }
+            tree.add_or_update_recursive(
+                root,
+                None,
+                Some(rng.date()),
+                Some(rng.date()),
+                Attributes::DIRECTORY,
+            );
+            dirs.push((root.to_string(), 0));
+        }
+
+        let mut files = 0;
+        while files < num_files {
+            let (parent, depth) = dirs[rng.below(dirs.len())].clone();
+            // Roughly one new directory per eight files, up to a depth of 8
+            if rng.below(8) == 0 && depth < 8 {
+                let path = format!("{}\\{}", parent, random_name(&mut rng));
+                tree.add_or_update_recursive(
+                    &path,
+                    None,
+                    Some(rng.date()),
+                    Some(rng.date()),
+                    Attributes::DIRECTORY,
+                );
+                dirs.push((path, depth + 1));
+                continue;
+            }
+
+            let path = format!(
+                "{}\\{}.{}",
+                parent,
+                random_name(&mut rng),
+                rng.pick(&EXTENSIONS)
+            );
+            let modified = rng.date();
+            let created = rng.date().min(modified);
+            let len_before = tree.len();
+            tree.add_or_update_recursive(
+                &path,
+                Some(rng.size()),
+                Some(modified),
+                Some(created),
+                Attributes::ARCHIVE,
+            );
+            if tree.len() > len_before {
+                // Only count new files, an existing path was just updated
+                files += 1;
+            }
+        }
+        tree.shrink_to_fit();
+        tree
+    }
+
+    // Adds a file of size 1 without dates or attributes, the usual fixture in tests
+    #[doc(hidden)]
+    pub fn add_test_file(&mut self, path: &str) -> usize {
+        self.add_or_update_recursive(path, Some(1), None, None, Attributes::NONE)
+            .expect("Test paths are within the tree limits")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_synthetic() {
+        let tree = FileTree::generate_synthetic(5_000, 7);
+        // Every element except the root is either one of the requested files or a folder
+        let files = tree
+            .get_elements()
+            .iter()
+            .skip(1)
+            .filter(|element| !element.is_dir())
+            .count();
+        assert_eq!(files, 5_000);
+        assert!(tree.len() > 5_000);
+
+        for (index, element) in tree.get_elements().iter().enumerate() {
+            // filename_as_str validates the bytes and returns an empty string for invalid UTF-8
+            let name = tree.filename_as_str(&element.filename);
+            assert!(!name.is_empty());
+            assert_eq!(name, tree.get_filename(index));
+        }
+    }
+
+    #[test]
+    fn test_generate_synthetic_deterministic() {
+        let first = FileTree::generate_synthetic(1_000, 42);
+        let second = FileTree::generate_synthetic(1_000, 42);
+        let other = FileTree::generate_synthetic(1_000, 43);
+
+        let paths = |tree: &FileTree| {
+            (0..tree.len())
+                .map(|index| {
+                    let element = tree.get(index).unwrap();
+                    (
+                        tree.get_full_path(index),
+                        element.size,
+                        element.date_modified,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths(&first), paths(&second));
+        assert_ne!(paths(&first), paths(&other));
+    }
+}
@@ -0,0 +1,101 @@
+// Peak memory of the streaming ncdu import, compared to parsing the whole export first like the
+// loader did before. Counts allocations with a global allocator, so it runs in its own test binary.
+#![cfg(feature = "ncdu")]
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use serde_json::Value;
+use vaultseek_core::{attributes::Attributes, file_tree::FileTree, loader::ncdu_json};
+
+struct CountingAllocator;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(current, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// The most memory allocated at once while running `f`, on top of what was allocated before
+fn peak_allocation<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    let result = f();
+    (result, PEAK.load(Ordering::Relaxed) - before)
+}
+
+// The loader before streaming: the whole export as JSON values, then the tree built from them
+fn import_materialized(path: &std::path::Path) -> FileTree {
+    fn add_directory(tree: &mut FileTree, parent: usize, directory: &[Value]) {
+        let name = directory[0]["name"].as_str().unwrap();
+        let folder = tree.add_child(parent, name, None, None, None, Attributes::DIRECTORY);
+        for entry in &directory[1..] {
+            match entry {
+                Value::Array(subdirectory) => add_directory(tree, folder, subdirectory),
+                file => {
+                    let name = file["name"].as_str().unwrap();
+                    let size = file["asize"].as_i64();
+                    tree.add_child(folder, name, size, None, None, Attributes::NONE);
+                }
+            }
+        }
+    }
+
+    let reader = std::io::BufReader::new(std::fs::File::open(path).unwrap());
+    let export: Value = serde_json::from_reader(reader).unwrap();
+    let mut tree = FileTree::with_capacity(0);
+    add_directory(&mut tree, 0, export[3].as_array().unwrap());
+    tree.shrink_to_fit();
+    tree
+}
+
+#[test]
+fn test_streaming_import_peak_memory() {
+    let mut json = String::from(r#"[1,2,{"progname":"ncdu"},[{"name":"data"}"#);
+    for d in 0..200 {
+        json.push_str(&format!(r#",[{{"name":"folder number {}"}}"#, d));
+        for f in 0..100 {
+            json.push_str(&format!(
+                r#",{{"name":"document {} of folder {}.txt","asize":{},"mtime":1700000000}}"#,
+                f, d, f
+            ));
+        }
+        json.push(']');
+    }
+    json.push_str("]]");
+    let path = std::env::temp_dir().join("vaultseek_test_ncdu_peak_memory.json");
+    std::fs::write(&path, &json).unwrap();
+    drop(json);
+
+    let (materialized, materialized_peak) = peak_allocation(|| import_materialized(&path));
+    drop(materialized);
+    let (streamed, streamed_peak) = peak_allocation(|| ncdu_json::import_ncdu_json(&path).unwrap());
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(streamed.len(), 1 + 1 + 200 * 101);
+    // The JSON values of every entry are never held at the same time
+    assert!(
+        streamed_peak * 2 < materialized_peak,
+        "streamed {} bytes, materialized {} bytes",
+        streamed_peak,
+        materialized_peak
+    );
+}
@@ -1,38 +1,181 @@
-use crate::searcher::Searcher;
-use crate::sorter::{SortField, SortOrder};
+use crate::attributes::Attributes;
+use crate::file_tree::{ElementType, FileCategory};
+use crate::searcher::{SearchOptions, Searcher};
+use crate::sorter::{SortField, SortOrder, SortSpec};
+use query::query_parser::SpaceSemantics;
+use rocket::fairing::AdHoc;
+use rocket::figment::Figment;
 use rocket::fs::{FileServer, relative};
-use serde::{Deserialize, Serialize};
+use rocket::http::{ContentType, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::status::{BadRequest, Custom};
+use rocket::response::stream::TextStream;
+use rocket::tokio::sync::{Semaphore, SemaphorePermit};
+use rocket::{Build, Rocket};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
 use std::process::{self};
-use std::sync::Mutex;
-use std::time::Instant;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::{Duration, Instant};
+use vaultseek_core::attributes;
 use vaultseek_core::file_tree;
+use vaultseek_core::ignore_list::IgnoreList;
 use vaultseek_core::loader;
+use vaultseek_core::query;
 use vaultseek_core::searcher;
 use vaultseek_core::sorter;
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Deserialize, Clone)]
 struct FileResult {
     name: String,
     path: String,
     size: Option<i64>,
     date_modified: Option<i64>,
     date_created: Option<i64>,
-    attributes: u32,
+    attributes: Attributes, // Serialized as the number, decoded by the frontend
+    // Kind of file for the file-type icon, from the extension. None for folders.
+    #[serde(default)]
+    category: Option<FileCategory>,
+    // MIME type from the extension, None for folders and unknown extensions
+    #[serde(default)]
+    mime: Option<String>,
+    // Relevance to the query with rank=relevance, higher is better. None with an explicit sort.
+    #[serde(default)]
+    score: Option<f32>,
+    // Which of the fields above are serialized, all unless a client asked for fewer
+    #[serde(skip)]
+    fields: ResultFields,
+}
+// Written by hand to leave out the fields the client didn't ask for
+impl Serialize for FileResult {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        let fields = self.fields;
+        if fields.contains(ResultFields::NAME) {
+            map.serialize_entry("name", &self.name)?;
+        }
+        if fields.contains(ResultFields::PATH) {
+            map.serialize_entry("path", &self.path)?;
+        }
+        if fields.contains(ResultFields::SIZE) {
+            map.serialize_entry("size", &self.size)?;
+        }
+        if fields.contains(ResultFields::DATE_MODIFIED) {
+            map.serialize_entry("date_modified", &self.date_modified)?;
+        }
+        if fields.contains(ResultFields::DATE_CREATED) {
+            map.serialize_entry("date_created", &self.date_created)?;
+        }
+        if fields.contains(ResultFields::ATTRIBUTES) {
+            map.serialize_entry("attributes", &self.attributes)?;
+        }
+        if fields.contains(ResultFields::CATEGORY)
+            && let Some(category) = &self.category
+        {
+            map.serialize_entry("category", category)?;
+        }
+        if fields.contains(ResultFields::MIME)
+            && let Some(mime) = &self.mime
+        {
+            map.serialize_entry("mime", mime)?;
+        }
+        if let Some(score) = self.score {
+            map.serialize_entry("score", &score)?;
+        }
+        map.end()
+    }
 }
 impl FileResult {
     fn from_element<T: AsRef<str>, U: AsRef<str>>(
         element: &file_tree::Element,
         path: T,
         filename: U,
+        sanitizing: NameSanitizing,
     ) -> Self {
         FileResult {
-            name: filename.as_ref().to_string(),
-            path: path.as_ref().to_string(),
+            name: sanitizing.apply(filename.as_ref()),
+            path: sanitizing.apply(path.as_ref()),
             size: element.size,
             date_modified: element.date_modified,
             date_created: element.date_created,
             attributes: element.attributes,
+            category: if element.is_dir() {
+                None
+            } else {
+                Some(
+                    file_tree::extension(filename.as_ref())
+                        .map_or(FileCategory::Other, file_tree::classify_extension),
+                )
+            },
+            mime: if element.is_dir() {
+                None
+            } else {
+                file_tree::mime_type_of(filename.as_ref()).map(str::to_string)
+            },
+            score: None,
+            fields: ResultFields::ALL,
+        }
+    }
+}
+
+// The fields of each result in the JSON, e.g. ?fields=name,size to make a large export smaller.
+// A set of bits, in the order of RESULT_FIELDS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ResultFields(u16);
+const RESULT_FIELDS: [&str; 8] = [
+    "name",
+    "path",
+    "size",
+    "date_modified",
+    "date_created",
+    "attributes",
+    "category",
+    "mime",
+];
+impl ResultFields {
+    const NAME: ResultFields = ResultFields(1);
+    const PATH: ResultFields = ResultFields(1 << 1);
+    const SIZE: ResultFields = ResultFields(1 << 2);
+    const DATE_MODIFIED: ResultFields = ResultFields(1 << 3);
+    const DATE_CREATED: ResultFields = ResultFields(1 << 4);
+    const ATTRIBUTES: ResultFields = ResultFields(1 << 5);
+    const CATEGORY: ResultFields = ResultFields(1 << 6);
+    const MIME: ResultFields = ResultFields(1 << 7);
+    const ALL: ResultFields = ResultFields((1 << RESULT_FIELDS.len()) - 1);
+
+    // Parses a comma separated list of field names. No list (or an empty one) means all fields.
+    fn parse(fields: Option<&str>) -> Result<Self, String> {
+        let mut bits = 0;
+        for field in fields.unwrap_or_default().split(',').map(str::trim) {
+            if field.is_empty() {
+                continue;
+            }
+            let Some(position) = RESULT_FIELDS.iter().position(|&name| name == field) else {
+                return Err(format!(
+                    "Unknown field '{}', expected some of {}",
+                    field,
+                    RESULT_FIELDS.join(",")
+                ));
+            };
+            bits |= 1 << position;
         }
+        Ok(if bits == 0 {
+            ResultFields::ALL
+        } else {
+            ResultFields(bits)
+        })
+    }
+
+    fn contains(self, field: ResultFields) -> bool {
+        self.0 & field.0 != 0
+    }
+}
+impl Default for ResultFields {
+    fn default() -> Self {
+        ResultFields::ALL
     }
 }
 #[derive(Serialize, Deserialize)]
@@ -42,36 +185,500 @@ struct SearchResult {
     offset: usize,
     page_size: usize,
     time_taken: u128,
+    // How the query was read, only with ?explain=true
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parsed_query: Option<String>,
+    // A corrected query, only if there are no results
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    suggestion: Option<String>,
+    // The search ran past ?timeout_ms=, so only the results found until then are included
+    #[serde(default)]
+    timed_out: bool,
 }
 
-struct SearchCache {
+// The matching files counted by extension for /search?group=ext, most common first
+#[derive(Serialize, Deserialize)]
+struct ExtensionFacets {
+    extensions: Vec<ExtensionCount>,
+    total: usize, // All matches, including folders which have no extension bucket
+    time_taken: u128,
+    #[serde(default)]
+    timed_out: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExtensionCount {
+    extension: String, // Lowercased, empty for files without an extension
+    count: usize,
+}
+
+// What happens to control characters and NUL bytes in names and paths of results, as they can
+// break clients and terminals. Only the results are changed, searches match the original names.
+// `sanitize_names` in Rocket.toml or ROCKET_SANITIZE_NAMES, one of "replace" (with U+FFFD, the
+// default), "escape" (as \x1b) or "keep".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum NameSanitizing {
+    Keep,
+    #[default]
+    Replace,
+    Escape,
+}
+impl NameSanitizing {
+    fn apply(self, name: &str) -> String {
+        if self == NameSanitizing::Keep || !name.contains(char::is_control) {
+            return name.to_string();
+        }
+        let mut sanitized = String::with_capacity(name.len());
+        for c in name.chars() {
+            match self {
+                _ if !c.is_control() => sanitized.push(c),
+                NameSanitizing::Escape => sanitized.push_str(&format!("\\x{:02x}", c as u32)),
+                _ => sanitized.push(char::REPLACEMENT_CHARACTER),
+            }
+        }
+        sanitized
+    }
+}
+
+struct CachedSearch {
+    searcher: Weak<Searcher>, // The searcher the indices belong to, they're invalid after a reload
     query: String,
-    indices: Vec<usize>,
-    sort_by: Option<SortField>,
-    sort_order: Option<SortOrder>,
+    indices: Arc<Vec<usize>>,
+    options: SearchOptions,
+    last_used: Instant,
 }
-struct LastSearchCache {
-    search: Mutex<Option<SearchCache>>,
+impl CachedSearch {
+    fn memory(&self) -> usize {
+        self.indices.len() * std::mem::size_of::<usize>()
+    }
+}
+
+// The results of recent searches, so paging and repeating a search don't search again. Entries
+// are evicted least recently used first once their indices take more than `search_cache_memory`
+// bytes (Rocket.toml or ROCKET_SEARCH_CACHE_MEMORY, 256 MiB by default), and after
+// `search_cache_ttl` seconds without being used if that is set (ROCKET_SEARCH_CACHE_TTL).
+// Results larger than the whole budget aren't kept at all.
+// The `pinned_searches` (e.g. `pinned_searches = ["ext:pdf", "size:>1gb"]`) are searched with the
+// default options when the file list is loaded or reloaded. They're always answered from the
+// cache and never evicted.
+struct SearchCache {
+    entries: Mutex<VecDeque<CachedSearch>>, // Most recently used first
+    pinned: Mutex<Vec<CachedSearch>>,
+    pinned_queries: Vec<String>,
+    memory_budget: usize,
+    ttl: Option<Duration>,
+    computed: AtomicUsize, // Searches that weren't answered from the cache
+    hits: AtomicUsize,     // Searches answered from the cache
+}
+impl SearchCache {
+    fn new(memory_budget: usize, ttl: Option<Duration>) -> Self {
+        SearchCache {
+            entries: Mutex::new(VecDeque::new()),
+            pinned: Mutex::new(Vec::new()),
+            pinned_queries: Vec::new(),
+            memory_budget,
+            ttl,
+            computed: AtomicUsize::new(0),
+            hits: AtomicUsize::new(0),
+        }
+    }
+
+    fn with_pinned_queries(mut self, pinned_queries: Vec<String>) -> Self {
+        self.pinned_queries = pinned_queries;
+        self
+    }
+
+    // Searches the pinned queries on the searcher. Invalid queries are logged and skipped.
+    fn search_pinned(pinned_queries: &[String], searcher: &Arc<Searcher>) -> Vec<CachedSearch> {
+        let options = SearchOptions::default();
+        pinned_queries
+            .iter()
+            .filter_map(
+                |query| match searcher.try_search_with_options(query, &options) {
+                    Ok(indices) => Some(CachedSearch {
+                        searcher: Arc::downgrade(searcher),
+                        query: query.clone(),
+                        indices: Arc::new(indices),
+                        options: options.clone(),
+                        last_used: Instant::now(),
+                    }),
+                    Err(e) => {
+                        tracing::error!("Invalid pinned search '{}': {}", query, e);
+                        None
+                    }
+                },
+            )
+            .collect()
+    }
+
+    fn set_pinned(&self, pinned: Vec<CachedSearch>) {
+        *self.pinned.lock().unwrap() = pinned;
+    }
+
+    // The cached indices of the query, or the query and indices of the most recent search with
+    // the same options that a new search may refine
+    fn lookup(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+        searcher: &Arc<Searcher>,
+    ) -> CacheLookup {
+        let searcher = Arc::downgrade(searcher);
+        if let Some(pinned) = self.pinned.lock().unwrap().iter().find(|pinned| {
+            pinned.query == query
+                && pinned.options == *options
+                && Weak::ptr_eq(&pinned.searcher, &searcher)
+        }) {
+            return CacheLookup::Hit(pinned.indices.clone());
+        }
+        let mut entries = self.entries.lock().unwrap();
+        self.evict_expired(&mut entries);
+        let mut previous = None;
+        for (position, entry) in entries.iter().enumerate() {
+            if entry.options != *options || !Weak::ptr_eq(&entry.searcher, &searcher) {
+                continue;
+            }
+            if entry.query == query {
+                let mut entry = entries.remove(position).unwrap();
+                entry.last_used = Instant::now();
+                let indices = entry.indices.clone();
+                entries.push_front(entry);
+                return CacheLookup::Hit(indices);
+            }
+            if previous.is_none() {
+                previous = Some((entry.query.clone(), entry.indices.clone()));
+            }
+        }
+        match previous {
+            Some((query, indices)) => CacheLookup::Previous(query, indices),
+            None => CacheLookup::Miss,
+        }
+    }
+
+    fn insert(
+        &self,
+        query: &str,
+        options: SearchOptions,
+        searcher: &Arc<Searcher>,
+        indices: Arc<Vec<usize>>,
+    ) {
+        let searcher = Arc::downgrade(searcher);
+        let inserted = CachedSearch {
+            searcher,
+            query: query.to_string(),
+            indices,
+            options,
+            last_used: Instant::now(),
+        };
+        // Keeping it would evict everything else and still exceed the budget
+        if inserted.memory() > self.memory_budget {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|entry| {
+            entry.query != inserted.query
+                || entry.options != inserted.options
+                || !Weak::ptr_eq(&entry.searcher, &inserted.searcher)
+        });
+        entries.push_front(inserted);
+        self.evict_expired(&mut entries);
+        let mut memory: usize = entries.iter().map(CachedSearch::memory).sum();
+        while memory > self.memory_budget {
+            let Some(evicted) = entries.pop_back() else {
+                break;
+            };
+            memory -= evicted.memory();
+        }
+    }
+
+    fn evict_expired(&self, entries: &mut VecDeque<CachedSearch>) {
+        if let Some(ttl) = self.ttl {
+            entries.retain(|entry| entry.last_used.elapsed() < ttl);
+        }
+    }
+
+    fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+enum CacheLookup {
+    Hit(Arc<Vec<usize>>),
+    Previous(String, Arc<Vec<usize>>),
+    Miss,
+}
+
+// The searcher serving requests. /reload swaps in a new one built from the file list, searches
+// that already started keep their Arc of the old one until they finish.
+struct SearcherState {
+    current: RwLock<Arc<Searcher>>,
+    file_list: PathBuf,
+    ignore_list: IgnoreList,
+    name_sanitizing: NameSanitizing,
+    warm_sort_caches: bool,
+    case_insensitive_sort: bool,
+}
+impl SearcherState {
+    fn get(&self) -> Arc<Searcher> {
+        self.current.read().unwrap().clone()
+    }
+}
+
+// Bearer token required by the admin endpoints, `admin_token` in Rocket.toml or
+// ROCKET_ADMIN_TOKEN. With `protect_search = true` the search endpoints need it as well.
+// Without a token the admin endpoints are disabled, so they're never open by accident.
+struct AuthConfig {
+    admin_token: Option<String>,
+    protect_search: bool,
+}
+
+// Request guard for endpoints that change the server state, like /reload
+struct Admin;
+// Request guard for the read-only search endpoints, only checked if protect_search is set
+struct SearchAccess;
+
+fn check_token(request: &Request<'_>, auth: &AuthConfig) -> bool {
+    let Some(admin_token) = &auth.admin_token else {
+        return false;
+    };
+    request
+        .headers()
+        .get_one("Authorization")
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .is_some_and(|token| constant_time_eq(token.trim().as_bytes(), admin_token.as_bytes()))
+}
+
+// Compares every byte however early the tokens differ, so response times don't reveal how much
+// of a guessed token is right. Only the length can be told apart.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let difference = a
+        .iter()
+        .zip(b)
+        .fold(0, |difference, (x, y)| difference | (x ^ y));
+    std::hint::black_box(difference) == 0
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let auth = request.rocket().state::<AuthConfig>().unwrap();
+        if check_token(request, auth) {
+            Outcome::Success(Admin)
+        } else {
+            Outcome::Error((Status::Unauthorized, ()))
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SearchAccess {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let auth = request.rocket().state::<AuthConfig>().unwrap();
+        if !auth.protect_search || check_token(request, auth) {
+            Outcome::Success(SearchAccess)
+        } else {
+            Outcome::Error((Status::Unauthorized, ()))
+        }
+    }
+}
+
+// Upper bounds of the search latency histogram in seconds, the last bucket is +Inf
+const LATENCY_BUCKETS: [f64; 8] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+// Counters of /search and /search_stream for /metrics. Cache hits and misses are counted by the
+// SearchCache, the number of elements is read from the searcher when scraped.
+#[derive(Default)]
+struct Metrics {
+    searches: AtomicUsize,
+    latency_buckets: [AtomicUsize; LATENCY_BUCKETS.len() + 1], // Not cumulative, unlike the output
+    latency_sum_micros: AtomicUsize,
+}
+impl Metrics {
+    // The time_taken of a search in microseconds
+    fn record_search(&self, time_taken: u128) {
+        let seconds = time_taken as f64 / 1_000_000.0;
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|&bound| seconds <= bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.latency_sum_micros
+            .fetch_add(time_taken as usize, Ordering::Relaxed);
+        self.searches.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // The Prometheus text exposition format
+    fn render(&self, search_cache: &SearchCache, searcher: &Searcher) -> String {
+        let header = |name: &str, kind: &str, help: &str| {
+            format!("# HELP {name} {help}\n# TYPE {name} {kind}\n")
+        };
+        let counter = |name: &str, help: &str, value: usize| {
+            format!("{}{} {}\n", header(name, "counter", help), name, value)
+        };
+        let mut out = counter(
+            "vaultseek_searches_total",
+            "Searches served by /search and /search_stream",
+            self.searches.load(Ordering::Relaxed),
+        );
+        out += &counter(
+            "vaultseek_search_cache_hits_total",
+            "Searches answered from the search cache",
+            search_cache.hits.load(Ordering::Relaxed),
+        );
+        out += &counter(
+            "vaultseek_search_cache_misses_total",
+            "Searches that had to be computed",
+            search_cache.computed.load(Ordering::Relaxed),
+        );
+
+        let name = "vaultseek_search_duration_seconds";
+        out += &header(
+            name,
+            "histogram",
+            "Time taken by /search, and by /search_stream until streaming starts",
+        );
+        let mut cumulative = 0;
+        for (i, bucket) in self.latency_buckets.iter().enumerate() {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let bound = LATENCY_BUCKETS
+                .get(i)
+                .map_or("+Inf".to_string(), |bound| bound.to_string());
+            out += &format!("{name}_bucket{{le=\"{bound}\"}} {cumulative}\n");
+        }
+        let sum = self.latency_sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        out += &format!("{name}_sum {sum}\n{name}_count {cumulative}\n");
+
+        let name = "vaultseek_elements";
+        out += &header(name, "gauge", "Files and folders in the file list");
+        let elements = searcher.get_file_tree().len().saturating_sub(1); // Without the root
+        out += &format!("{name} {elements}\n");
+        out
+    }
+}
+
+// Limits how many searches run at the same time. Regex and path queries scan the whole tree,
+// so too many at once would only slow each other down. Waiting requests queue up in order.
+// Configured with `search_concurrency` in Rocket.toml or ROCKET_SEARCH_CONCURRENCY,
+// defaults to the number of CPUs.
+struct SearchLimiter {
+    semaphore: Semaphore,
+}
+impl SearchLimiter {
+    fn new(limit: usize) -> Self {
+        SearchLimiter {
+            semaphore: Semaphore::new(limit.max(1)),
+        }
+    }
+    async fn acquire(&self) -> SemaphorePermit<'_> {
+        // The semaphore is never closed, so acquiring can't fail
+        self.semaphore.acquire().await.unwrap()
+    }
+}
+
+// The last queries searched for, most recent first and without duplicates. Up to
+// `recent_searches` in Rocket.toml or ROCKET_RECENT_SEARCHES are kept, 20 by default.
+struct RecentSearches {
+    queries: Mutex<VecDeque<String>>,
+    capacity: usize,
+}
+impl RecentSearches {
+    fn new(capacity: usize) -> Self {
+        RecentSearches {
+            queries: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+    fn add(&self, query: &str) {
+        let query = query.trim();
+        if query.is_empty() || self.capacity == 0 {
+            return;
+        }
+        let mut queries = self.queries.lock().unwrap();
+        queries.retain(|recent| recent != query);
+        queries.push_front(query.to_string());
+        queries.truncate(self.capacity);
+    }
+}
+
+// Limits for /save, so a client can't grow the saved searches file without bounds
+const MAX_SAVED_SEARCHES: usize = 1000;
+const MAX_SAVED_NAME_LEN: usize = 100;
+const MAX_SAVED_QUERY_LEN: usize = 4096;
+
+// Named queries, kept in the JSON file `saved_searches` (Rocket.toml or ROCKET_SAVED_SEARCHES,
+// saved_searches.json by default) so they survive restarts
+struct SavedSearches {
+    searches: Mutex<BTreeMap<String, String>>,
+    path: PathBuf,
+}
+impl SavedSearches {
+    fn load(path: PathBuf) -> Result<Self, String> {
+        let searches = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| format!("Error reading saved searches {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => {
+                return Err(format!(
+                    "Error reading saved searches {}: {}",
+                    path.display(),
+                    e
+                ));
+            }
+        };
+        Ok(SavedSearches {
+            searches: Mutex::new(searches),
+            path,
+        })
+    }
+
+    fn save(&self, name: &str, query: &str) -> Result<(), Custom<String>> {
+        let mut searches = self.searches.lock().unwrap();
+        // Replacing a saved search is always possible, only new names count against the limit
+        if !searches.contains_key(name) && searches.len() >= MAX_SAVED_SEARCHES {
+            return Err(Custom(
+                Status::BadRequest,
+                format!("At most {} searches can be saved", MAX_SAVED_SEARCHES),
+            ));
+        }
+        let mut updated = searches.clone();
+        updated.insert(name.to_string(), query.to_string());
+        self.write(&updated).map_err(|e| {
+            Custom(
+                Status::InternalServerError,
+                format!("Error saving search: {}", e),
+            )
+        })?;
+        *searches = updated;
+        Ok(())
+    }
+
+    fn write(&self, searches: &BTreeMap<String, String>) -> std::io::Result<()> {
+        // Write a temporary file first, so a crash never leaves a truncated file behind
+        let json = serde_json::to_string_pretty(searches)?;
+        let temp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&temp_path, json)?;
+        std::fs::rename(&temp_path, &self.path)
+    }
 }
 
 #[macro_use]
 extern crate rocket;
 
-#[get("/search?<query>&<offset>&<sort_by>&<sort_order>")]
-fn search(
-    query: String,
-    offset: Option<usize>,
+fn parse_search_options(
+    sort: Option<String>,
     sort_by: Option<String>,
     sort_order: Option<String>,
-    searcher: &rocket::State<Searcher>,
-    last_search_cache: &rocket::State<LastSearchCache>,
-) -> String {
-    let time_start = Instant::now();
-    let result_indices;
-
-    // Normalize the query to lowercase for case-insensitive search
-    let query = query.to_lowercase();
-
+    r#type: Option<String>,
+    space: Option<String>,
+) -> SearchOptions {
     let sort_by: Option<SortField> = match sort_by.as_deref() {
         Some("filename") => Some(SortField::Filename),
         Some("date_modified") => Some(SortField::DateModified),
@@ -84,52 +691,328 @@ fn search(
         Some("descending") => Some(SortOrder::Descending),
         _ => None, // Default to None if no valid sort order is provided
     };
+    // A preset like sort=newest is only used without an explicit sort_by
+    let (sort_by, sort_order) = match (sort_by, sort.as_deref().and_then(SortSpec::from_name)) {
+        (None, Some(spec)) => {
+            let (field, order) = spec.field_and_order();
+            (Some(field), Some(sort_order.unwrap_or(order)))
+        }
+        _ => (sort_by, sort_order),
+    };
+    let element_type: Option<ElementType> = match r#type.as_deref() {
+        Some("file") => Some(ElementType::File),
+        Some("folder") => Some(ElementType::Folder),
+        _ => None, // Default to both files and folders
+    };
+    let space_semantics = match space.as_deref() {
+        Some("or") => SpaceSemantics::Or,
+        _ => SpaceSemantics::And, // Default to whitespace meaning AND
+    };
+    SearchOptions {
+        sort_by,
+        sort_order,
+        element_type,
+        space_semantics,
+        ..Default::default()
+    }
+}
+
+// Returns the indices for the query, reusing a cached search if the query and options are the same
+// Also returns whether the search timed out. Partial results aren't cached, and the timeout
+// isn't part of the cache key, so complete results are found with any timeout.
+async fn cached_search(
+    query: &str,
+    options: SearchOptions,
+    timeout: Option<Duration>,
+    searcher: &Arc<Searcher>,
+    search_cache: &SearchCache,
+) -> Result<(Arc<Vec<usize>>, bool), BadRequest<String>> {
+    let previous = match search_cache.lookup(query, &options, searcher) {
+        CacheLookup::Hit(indices) => {
+            search_cache.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok((indices, false));
+        }
+        CacheLookup::Previous(query, indices) => Some((query, indices)),
+        CacheLookup::Miss => None,
+    };
+
+    // The search runs on a blocking thread, so a slow one doesn't stall the other requests
+    let (indices, timed_out) = {
+        let searcher = Arc::clone(searcher);
+        let query = query.to_string();
+        let options = options.clone();
+        rocket::tokio::task::spawn_blocking(move || {
+            // While typing, the new query often only narrows down the previous one, e.g. `re`
+            // to `rep`. Then the cached results are filtered instead of searching again, without
+            // holding the lock.
+            let refined = previous.and_then(|(previous_query, previous_indices)| {
+                searcher.refine_search(previous_query, &previous_indices, &query, &options)
+            });
+            match refined {
+                Some(indices) => Ok((indices, false)),
+                None => {
+                    let timeout_options = SearchOptions { timeout, ..options };
+                    searcher
+                        .try_search_with_diagnostics(&query, &timeout_options)
+                        .map(|(indices, diagnostics)| (indices, diagnostics.timed_out))
+                        .map_err(|e| e.to_string())
+                }
+            }
+        })
+        .await
+        .map_err(|e| BadRequest(e.to_string()))?
+        .map_err(BadRequest)?
+    };
+    let indices = Arc::new(indices);
+    search_cache.computed.fetch_add(1, Ordering::Relaxed);
+
+    if !timed_out {
+        search_cache.insert(query, options, searcher, indices.clone());
+    }
+    Ok((indices, timed_out))
+}
+
+fn file_result(
+    searcher: &Searcher,
+    element: &file_tree::Element,
+    sanitizing: NameSanitizing,
+) -> FileResult {
+    FileResult::from_element(
+        element,
+        searcher.get_file_tree().get_full_path(element.parent),
+        searcher.get_file_tree().filename_as_str(&element.filename),
+        sanitizing,
+    )
+}
+
+// One page of all elements, unsorted in tree order or by the sorter's cached order. Unlike an
+// empty search no list of every index is built or cached.
+fn all_page(
+    searcher: &Searcher,
+    offset: usize,
+    page_size: usize,
+    sort_by: Option<SortField>,
+    sort_order: Option<SortOrder>,
+    sanitizing: NameSanitizing,
+) -> SearchResult {
+    let time_start = Instant::now();
+    // Without the root and the elements hidden by the ignore list or the scope
+    let (indices, total) = searcher.all_page(offset, page_size, sort_by, sort_order);
+    let results = searcher
+        .get_many(&indices)
+        .into_iter()
+        .flatten()
+        .map(|element| file_result(searcher, element, sanitizing))
+        .collect();
+    SearchResult {
+        results,
+        total,
+        offset,
+        page_size,
+        time_taken: time_start.elapsed().as_micros(),
+        parsed_query: None,
+        suggestion: None,
+        timed_out: false,
+    }
+}
+
+#[get("/all?<offset>&<page_size>&<sort>&<sort_by>&<sort_order>")]
+#[allow(clippy::too_many_arguments)]
+async fn all(
+    offset: Option<usize>,
+    page_size: Option<usize>,
+    sort: Option<String>,
+    sort_by: Option<String>,
+    sort_order: Option<String>,
+    searcher_state: &rocket::State<SearcherState>,
+    search_limiter: &rocket::State<SearchLimiter>,
+    _access: SearchAccess,
+) -> String {
+    let searcher = &searcher_state.get();
+    let options = parse_search_options(sort, sort_by, sort_order, None, None);
+    let page_size = page_size.unwrap_or(100).clamp(1, 1000);
+    // Preparing a sort order the first time walks the whole tree, so it counts as a search
+    let results = {
+        let _permit = search_limiter.acquire().await;
+        all_page(
+            searcher,
+            offset.unwrap_or(0),
+            page_size,
+            options.sort_by,
+            options.sort_order,
+            searcher_state.name_sanitizing,
+        )
+    };
+    match serde_json::to_string(&results) {
+        Ok(json) => json,
+        Err(e) => format!("Error serializing results: {}", e),
+    }
+}
 
-    // Check if the query is cached
-    let mut cache_guard = last_search_cache.search.lock().unwrap();
-    if let Some(cache) = cache_guard.as_ref()
-        && cache.query == query
-        && cache.sort_by == sort_by
-        && cache.sort_order == sort_order
+// With `group=folder` the results are the folders containing matching files instead, rolled up
+// to the ancestor at `depth` if given (1 = top level folder), see Searcher::matching_folders.
+// With `group=ext` the response is an ExtensionFacets with the number of matches per extension.
+// With `timeout_ms` a slow search returns the results found until then, with timed_out set.
+// Only the first page of such a partial result can be requested.
+#[get(
+    "/search?<query>&<offset>&<sort>&<sort_by>&<sort_order>&<type>&<space>&<explain>&<group>&<depth>&<include_dotdirs>&<dirs_first>&<fields>&<timeout_ms>&<rank>&<separator_boundaries>&<strict_negation>"
+)]
+#[allow(clippy::too_many_arguments)]
+async fn search(
+    query: String,
+    offset: Option<usize>,
+    sort: Option<String>,
+    sort_by: Option<String>,
+    sort_order: Option<String>,
+    r#type: Option<String>,
+    space: Option<String>,
+    explain: Option<bool>,
+    group: Option<String>,
+    depth: Option<usize>,
+    include_dotdirs: Option<bool>,
+    dirs_first: Option<bool>,
+    fields: Option<String>,
+    timeout_ms: Option<u64>,
+    rank: Option<String>,
+    separator_boundaries: Option<bool>,
+    strict_negation: Option<bool>,
+    searcher_state: &rocket::State<SearcherState>,
+    search_cache: &rocket::State<SearchCache>,
+    search_limiter: &rocket::State<SearchLimiter>,
+    recent_searches: &rocket::State<RecentSearches>,
+    metrics: &rocket::State<Metrics>,
+    _access: SearchAccess,
+) -> Result<String, BadRequest<String>> {
+    let time_start = Instant::now();
+    let searcher = &searcher_state.get();
+    let fields = ResultFields::parse(fields.as_deref()).map_err(BadRequest)?;
+    // include_dotdirs=false skips path matches inside folders like .git, dirs_first=true lists
+    // the folders before the files, rank=relevance orders unsorted results by their score.
+    // separator_boundaries=true lets separators end words in ww:path:, strict_negation=true
+    // keeps elements without a size or date out of !size: and !dm:.
+    let options = SearchOptions {
+        exclude_dot_components: include_dotdirs == Some(false),
+        dirs_first: dirs_first == Some(true),
+        rank_by_relevance: rank.as_deref() == Some("relevance"),
+        separator_word_boundaries: separator_boundaries == Some(true),
+        strict_negation: strict_negation == Some(true),
+        ..parse_search_options(sort, sort_by, sort_order, r#type, space)
+    };
+    let parsed_query = match explain {
+        Some(true) => Some(
+            searcher
+                .parse_query(&query, &options)
+                .map_err(|e| BadRequest(e.to_string()))?
+                .to_string(),
+        ),
+        _ => None,
+    };
+    let group_by_folder = group.as_deref() == Some("folder");
+    let group_by_ext = group.as_deref() == Some("ext");
+    // An empty query lists everything, page through it instead of caching every index
+    if query.trim().is_empty()
+        && options.element_type.is_none()
+        && !options.dirs_first
+        && !group_by_folder
+        && !group_by_ext
     {
-        result_indices = &cache.indices;
+        let mut results = {
+            let _permit = search_limiter.acquire().await;
+            all_page(
+                searcher,
+                offset.unwrap_or(0),
+                100,
+                options.sort_by,
+                options.sort_order,
+                searcher_state.name_sanitizing,
+            )
+        };
+        results.parsed_query = parsed_query;
+        for result in &mut results.results {
+            result.fields = fields;
+        }
+        metrics.record_search(results.time_taken);
+        return serde_json::to_string(&results).map_err(|e| BadRequest(e.to_string()));
+    }
+    let timeout = timeout_ms.map(Duration::from_millis);
+    let suggestion_options = options.clone();
+    // Grouped results are folders, they aren't ranked
+    let ranker = if group_by_folder {
+        None
     } else {
-        drop(cache_guard); // Release the lock before performing the search
+        searcher.relevance_ranker_for(&query, &options)
+    };
+    let (result_indices, timed_out) = {
+        let _permit = search_limiter.acquire().await;
+        if group_by_folder {
+            // The matching files are cached, the folders are quick to find from them
+            let file_options = SearchOptions {
+                sort_by: None,
+                element_type: Some(ElementType::File),
+                ..options.clone()
+            };
+            let (files, timed_out) =
+                cached_search(&query, file_options, timeout, searcher, search_cache).await?;
+            let folders = searcher.containing_folders(&files, depth, &options);
+            (Arc::new(folders), timed_out)
+        } else {
+            cached_search(&query, options, timeout, searcher, search_cache).await?
+        }
+    };
+    // A later page would come from another partial result, so it couldn't continue this one
+    if timed_out && offset.unwrap_or(0) > 0 {
+        return Err(BadRequest(
+            "The search timed out, increase timeout_ms to page through the results".to_string(),
+        ));
+    }
+    // Paging through the results isn't a new search
+    if offset.unwrap_or(0) == 0 {
+        recent_searches.add(&query);
+    }
+    if group_by_ext {
+        let facets = ExtensionFacets {
+            extensions: searcher
+                .facet_by_extension(&result_indices)
+                .into_iter()
+                .map(|(extension, count)| ExtensionCount { extension, count })
+                .collect(),
+            total: result_indices.len(),
+            time_taken: time_start.elapsed().as_micros(),
+            timed_out,
+        };
+        metrics.record_search(facets.time_taken);
+        return serde_json::to_string(&facets).map_err(|e| BadRequest(e.to_string()));
+    }
 
-        // Perform the search using the Searcher
-        let indices = searcher.search(&query, sort_by, sort_order);
+    // A timed out search may have missed the results, then the query is likely fine
+    let suggestion = if result_indices.is_empty() && !timed_out {
+        let _permit = search_limiter.acquire().await;
+        let searcher = Arc::clone(searcher);
+        let query = query.clone();
+        rocket::tokio::task::spawn_blocking(move || {
+            searcher.suggest_correction(&query, &suggestion_options)
+        })
+        .await
+        .ok()
+        .flatten()
+    } else {
+        None
+    };
 
-        cache_guard = last_search_cache.search.lock().unwrap();
-        cache_guard.replace(SearchCache {
-            query: query.clone(),
-            indices: indices,
-            sort_by,
-            sort_order,
-        });
-        result_indices = &cache_guard.as_ref().unwrap().indices;
-    }
-    let mut result_elements = Vec::new();
     // Now we have the indices of the elements that match the query
     // Prepare the results based on the indices
-    result_indices
-        .iter()
-        .skip(offset.unwrap_or(0))
-        .take(100)
-        .for_each(|&index| {
-            if let Some(element) = searcher.get(index) {
-                result_elements.push(element);
-            }
-        });
-
-    // Convert the elements to FileResult
-    let results: Vec<_> = result_elements
+    let start = offset.unwrap_or(0).min(result_indices.len());
+    let end = start.saturating_add(100).min(result_indices.len());
+    let results: Vec<_> = searcher
+        .get_many(&result_indices[start..end])
         .into_iter()
-        .map(|element| {
-            FileResult::from_element(
-                &element,
-                searcher.get_file_tree().get_full_path(element.parent),
-                searcher.get_file_tree().filename_as_str(&element.filename),
-            )
+        .flatten()
+        .map(|element| FileResult {
+            fields,
+            score: ranker.as_ref().map(|ranker| {
+                ranker.score(searcher.get_file_tree().filename_as_str(&element.filename))
+            }),
+            ..file_result(searcher, element, searcher_state.name_sanitizing)
         })
         .collect();
 
@@ -139,41 +1022,1607 @@ fn search(
         offset: offset.unwrap_or(0),
         page_size: 100, // Fixed page size for now
         time_taken: time_start.elapsed().as_micros(),
+        parsed_query,
+        suggestion,
+        timed_out,
     };
+    metrics.record_search(results.time_taken);
     // Convert results to JSON
     match serde_json::to_string(&results) {
-        Ok(json) => json,
-        Err(e) => format!("Error serializing results: {}", e),
+        Ok(json) => Ok(json),
+        Err(e) => Ok(format!("Error serializing results: {}", e)),
+    }
+}
+
+// Streams all results as newline-delimited JSON, one FileResult per line.
+// Lines are serialized as the client reads them, so the full response is never held in memory.
+#[get(
+    "/search_stream?<query>&<sort>&<sort_by>&<sort_order>&<type>&<space>&<include_dotdirs>&<dirs_first>&<fields>"
+)]
+#[allow(clippy::too_many_arguments)]
+async fn search_stream(
+    query: String,
+    sort: Option<String>,
+    sort_by: Option<String>,
+    sort_order: Option<String>,
+    r#type: Option<String>,
+    space: Option<String>,
+    include_dotdirs: Option<bool>,
+    dirs_first: Option<bool>,
+    fields: Option<String>,
+    searcher_state: &rocket::State<SearcherState>,
+    search_cache: &rocket::State<SearchCache>,
+    search_limiter: &rocket::State<SearchLimiter>,
+    metrics: &rocket::State<Metrics>,
+    _access: SearchAccess,
+) -> Result<(ContentType, TextStream![String]), BadRequest<String>> {
+    let time_start = Instant::now();
+    // The stream owns the searcher, so a reload while streaming doesn't affect it
+    let searcher = searcher_state.get();
+    let fields = ResultFields::parse(fields.as_deref()).map_err(BadRequest)?;
+    // include_dotdirs=false skips path matches inside folders like .git, dirs_first=true lists
+    // the folders before the files
+    let options = SearchOptions {
+        exclude_dot_components: include_dotdirs == Some(false),
+        dirs_first: dirs_first == Some(true),
+        ..parse_search_options(sort, sort_by, sort_order, r#type, space)
+    };
+    // Only the search itself is limited, streaming the results is cheap
+    let result_indices = {
+        let _permit = search_limiter.acquire().await;
+        cached_search(&query, options, None, &searcher, search_cache)
+            .await?
+            .0
+    };
+    metrics.record_search(time_start.elapsed().as_micros());
+
+    let sanitizing = searcher_state.name_sanitizing;
+    let stream = TextStream! {
+        for &index in result_indices.iter() {
+            if let Some(element) = searcher.get(index) {
+                let result = FileResult { fields, ..file_result(&searcher, element, sanitizing) };
+                match serde_json::to_string(&result) {
+                    Ok(json) => yield json + "\n",
+                    Err(e) => tracing::error!("Error serializing result: {}", e),
+                }
+            }
+        }
+    };
+    Ok((ContentType::new("application", "x-ndjson"), stream))
+}
+
+// The parsed query as JSON, e.g. for a visual query editor. Expressions are objects with the
+// variant as key, like {"And": [left, right]} or {"Function": {"Size": ["Gt", 1024]}}.
+#[get("/parse?<query>&<space>")]
+fn parse(
+    query: String,
+    space: Option<String>,
+    searcher_state: &rocket::State<SearcherState>,
+    _access: SearchAccess,
+) -> Result<(ContentType, String), BadRequest<String>> {
+    let options = parse_search_options(None, None, None, None, space);
+    let expr = searcher_state
+        .get()
+        .parse_query(&query, &options)
+        .map_err(|e| BadRequest(e.to_string()))?;
+    serde_json::to_string(&expr)
+        .map(|json| (ContentType::JSON, json))
+        .map_err(|e| BadRequest(e.to_string()))
+}
+
+#[get("/recent")]
+fn recent(recent_searches: &rocket::State<RecentSearches>, _access: SearchAccess) -> String {
+    let queries = recent_searches.queries.lock().unwrap();
+    serde_json::to_string(&*queries).unwrap_or_default()
+}
+
+#[get("/saved")]
+fn saved(saved_searches: &rocket::State<SavedSearches>, _access: SearchAccess) -> String {
+    let searches = saved_searches.searches.lock().unwrap();
+    serde_json::to_string(&*searches).unwrap_or_default()
+}
+
+// Saves the query under the name, replacing a saved search of the same name
+#[post("/save?<name>&<query>")]
+fn save(
+    name: String,
+    query: String,
+    saved_searches: &rocket::State<SavedSearches>,
+    _access: SearchAccess,
+) -> Result<String, Custom<String>> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Custom(
+            Status::BadRequest,
+            "The name can't be empty".to_string(),
+        ));
+    }
+    if name.chars().count() > MAX_SAVED_NAME_LEN {
+        return Err(Custom(
+            Status::BadRequest,
+            format!(
+                "The name can't be longer than {} characters",
+                MAX_SAVED_NAME_LEN
+            ),
+        ));
+    }
+    if query.chars().count() > MAX_SAVED_QUERY_LEN {
+        return Err(Custom(
+            Status::BadRequest,
+            format!(
+                "The query can't be longer than {} characters",
+                MAX_SAVED_QUERY_LEN
+            ),
+        ));
+    }
+    saved_searches.save(name, &query)?;
+    Ok(format!("Saved '{}'", name))
+}
+
+#[derive(Serialize, Deserialize)]
+struct BreadcrumbSegment {
+    index: usize,
+    name: String,
+    path: String, // Full path up to and including this segment, to search or link it
+}
+
+// The folders leading to the element at the path and the element itself, from the top level
+// folder down, e.g. to render clickable breadcrumbs
+#[get("/breadcrumb?<path>")]
+fn breadcrumb(
+    path: String,
+    searcher_state: &rocket::State<SearcherState>,
+    _access: SearchAccess,
+) -> Result<(ContentType, String), Custom<String>> {
+    let searcher = searcher_state.get();
+    let tree = searcher.get_file_tree();
+    let not_found = || Custom(Status::NotFound, format!("No element at '{}'", path));
+    let index = tree
+        .find_by_path(&path)
+        .filter(|&index| index != 0)
+        .ok_or_else(not_found)?;
+    let sanitizing = searcher_state.name_sanitizing;
+    let mut segments = Vec::new();
+    let mut segment_path = String::new();
+    for (index, name) in tree.ancestors(index) {
+        // Ignored elements are hidden everywhere, so is anything inside them
+        if searcher.is_ignored(index) {
+            return Err(not_found());
+        }
+        if !segment_path.is_empty() {
+            segment_path.push('\\');
+        }
+        segment_path.push_str(name);
+        segments.push(BreadcrumbSegment {
+            index,
+            name: sanitizing.apply(name),
+            path: sanitizing.apply(&segment_path),
+        });
+    }
+    serde_json::to_string(&segments)
+        .map(|json| (ContentType::JSON, json))
+        .map_err(|e| Custom(Status::InternalServerError, e.to_string()))
+}
+
+// The details of the element at the path, the same fields as a search result including the
+// MIME type, e.g. for a details pane
+#[get("/file?<path>")]
+fn file(
+    path: String,
+    searcher_state: &rocket::State<SearcherState>,
+    _access: SearchAccess,
+) -> Result<(ContentType, String), Custom<String>> {
+    let searcher = searcher_state.get();
+    let tree = searcher.get_file_tree();
+    let index = tree
+        .find_by_path(&path)
+        .filter(|&index| index != 0)
+        // Ignored elements are hidden everywhere, so is anything inside them
+        .filter(|&index| {
+            !tree
+                .ancestors(index)
+                .into_iter()
+                .any(|(index, _)| searcher.is_ignored(index))
+        })
+        .ok_or_else(|| Custom(Status::NotFound, format!("No element at '{}'", path)))?;
+    let element = &tree.get_elements()[index];
+    let result = file_result(&searcher, element, searcher_state.name_sanitizing);
+    serde_json::to_string(&result)
+        .map(|json| (ContentType::JSON, json))
+        .map_err(|e| Custom(Status::InternalServerError, e.to_string()))
+}
+
+// The contents of the folder at the path, to browse the tree without a query. With `depth` the
+// subfolders' contents are included down to that many levels (1 by default, the direct
+// children), in tree order. Pages of 100 like /search, the empty path is the root.
+#[get("/children?<path>&<depth>&<offset>")]
+fn children(
+    path: String,
+    depth: Option<usize>,
+    offset: Option<usize>,
+    searcher_state: &rocket::State<SearcherState>,
+    _access: SearchAccess,
+) -> Result<(ContentType, String), Custom<String>> {
+    let time_start = Instant::now();
+    let searcher = searcher_state.get();
+    let tree = searcher.get_file_tree();
+    let index = tree
+        .find_by_path(&path)
+        .filter(|&index| {
+            !tree
+                .ancestors(index)
+                .into_iter()
+                .any(|(index, _)| searcher.is_ignored(index))
+        })
+        .ok_or_else(|| Custom(Status::NotFound, format!("No element at '{}'", path)))?;
+    // Only the requested levels are walked, not the whole subtree
+    let mut indices = tree.collect_all_children_to_depth(index, depth.unwrap_or(1));
+    indices.retain(|&index| !searcher.is_ignored(index));
+    let offset = offset.unwrap_or(0);
+    let results = searcher
+        .get_many(&indices[offset.min(indices.len())..])
+        .into_iter()
+        .flatten()
+        .take(100)
+        .map(|element| file_result(&searcher, element, searcher_state.name_sanitizing))
+        .collect();
+    let results = SearchResult {
+        results,
+        total: indices.len(),
+        offset,
+        page_size: 100,
+        time_taken: time_start.elapsed().as_micros(),
+        parsed_query: None,
+        suggestion: None,
+        timed_out: false,
+    };
+    serde_json::to_string(&results)
+        .map(|json| (ContentType::JSON, json))
+        .map_err(|e| Custom(Status::InternalServerError, e.to_string()))
+}
+
+// Search counters and latency in the Prometheus text format, for scraping
+#[get("/metrics")]
+fn metrics(
+    metrics: &rocket::State<Metrics>,
+    search_cache: &rocket::State<SearchCache>,
+    searcher_state: &rocket::State<SearcherState>,
+    _access: SearchAccess,
+) -> (ContentType, String) {
+    let content_type = ContentType::new("text", "plain").with_params(("version", "0.0.4"));
+    (
+        content_type,
+        metrics.render(search_cache, &searcher_state.get()),
+    )
+}
+
+// Reads the file list again and swaps in the new searcher, without interrupting searches
+#[post("/reload")]
+async fn reload(
+    searcher_state: &rocket::State<SearcherState>,
+    search_cache: &rocket::State<SearchCache>,
+    _admin: Admin,
+) -> Result<String, Custom<String>> {
+    let file_list = searcher_state.file_list.clone();
+    let ignore_list = searcher_state.ignore_list.clone();
+    let warm_sort_caches = searcher_state.warm_sort_caches;
+    let case_insensitive_sort = searcher_state.case_insensitive_sort;
+    // Loading takes a while, keep it off the async workers so searches continue meanwhile
+    let searcher = rocket::tokio::task::spawn_blocking(move || {
+        let mut searcher = Searcher::from_file_tree(load_file_list(&file_list)?);
+        searcher.set_ignore_list(&ignore_list);
+        searcher.sorter.case_insensitive_names = case_insensitive_sort;
+        if warm_sort_caches {
+            searcher.sorter.warm_all(searcher.get_file_tree());
+        }
+        Ok::<_, String>(searcher)
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|searcher| searcher)
+    .map_err(|e| Custom(Status::InternalServerError, e))?;
+
+    let searcher = Arc::new(searcher);
+    let pinned_queries = search_cache.pinned_queries.clone();
+    let pinned = {
+        let searcher = searcher.clone();
+        rocket::tokio::task::spawn_blocking(move || {
+            SearchCache::search_pinned(&pinned_queries, &searcher)
+        })
+        .await
+        .map_err(|e| Custom(Status::InternalServerError, e.to_string()))?
+    };
+
+    let records = searcher.get_file_tree().len();
+    *searcher_state.current.write().unwrap() = searcher;
+    // The cached indices point into the old tree
+    search_cache.clear();
+    search_cache.set_pinned(pinned);
+    Ok(format!("Reloaded {} records", records))
+}
+
+fn load_file_list(path: &Path) -> Result<file_tree::FileTree, String> {
+    let start = Instant::now();
+    let tree = loader::efu::import_efu(path)
+        .map_err(|e| format!("Error reading file list {}: {}", path.display(), e))?;
+    tracing::info!(
+        records = tree.len(),
+        elapsed = ?start.elapsed(),
+        "Read file list {}",
+        path.display()
+    );
+    Ok(tree)
+}
+
+// Path of the file list, `file_list` in Rocket.toml or ROCKET_FILE_LIST
+fn file_list_path(figment: &Figment) -> PathBuf {
+    figment
+        .extract_inner::<PathBuf>("file_list")
+        .unwrap_or_else(|_| PathBuf::from("filelist.efu"))
+}
+
+fn build_rocket(searcher: Searcher) -> Rocket<Build> {
+    build_rocket_with_figment(rocket::Config::figment(), searcher)
+}
+
+// A rocket that fails to ignite with the error, for a configuration that can't be used. The
+// error is logged when Rocket starts, like its own configuration errors.
+fn failing_rocket(figment: Figment, error: String) -> Rocket<Build> {
+    rocket::custom(figment).attach(AdHoc::try_on_ignite("Configuration", |rocket| async move {
+        tracing::error!("{}", error);
+        Err(rocket)
+    }))
+}
+
+fn build_rocket_with_figment(figment: Figment, mut searcher: Searcher) -> Rocket<Build> {
+    // Paths hidden from all searches, e.g. `ignore = ["$Recycle.Bin", ".git"]` in Rocket.toml
+    // or ROCKET_IGNORE='[".git"]'
+    let ignore = figment
+        .extract_inner::<Vec<String>>("ignore")
+        .unwrap_or_default();
+    let ignore_list = match IgnoreList::new(&ignore) {
+        Ok(ignore_list) => ignore_list,
+        Err(e) => return failing_rocket(figment, format!("Invalid ignore pattern: {}", e)),
+    };
+    searcher.set_ignore_list(&ignore_list);
+    let file_list = file_list_path(&figment);
+    let name_sanitizing = figment
+        .extract_inner::<NameSanitizing>("sanitize_names")
+        .unwrap_or_default();
+    let auth = AuthConfig {
+        admin_token: figment
+            .extract_inner::<String>("admin_token")
+            .ok()
+            .filter(|token| !token.is_empty()),
+        protect_search: figment
+            .extract_inner::<bool>("protect_search")
+            .unwrap_or(false),
+    };
+    let recent_searches = figment
+        .extract_inner::<usize>("recent_searches")
+        .unwrap_or(20);
+    let saved_searches = figment
+        .extract_inner::<PathBuf>("saved_searches")
+        .unwrap_or_else(|_| PathBuf::from("saved_searches.json"));
+    let saved_searches = match SavedSearches::load(saved_searches) {
+        Ok(saved_searches) => saved_searches,
+        Err(e) => return failing_rocket(figment, e),
+    };
+    // Sort names like apple before Zebra, `case_insensitive_sort` in Rocket.toml or
+    // ROCKET_CASE_INSENSITIVE_SORT
+    let case_insensitive_sort = figment
+        .extract_inner::<bool>("case_insensitive_sort")
+        .unwrap_or(false);
+    searcher.sorter.case_insensitive_names = case_insensitive_sort;
+    // Build the sort orders before serving instead of on the first sort by each field, which
+    // takes seconds for large lists. `warm_sort_caches` in Rocket.toml or ROCKET_WARM_SORT_CACHES
+    let warm_sort_caches = figment
+        .extract_inner::<bool>("warm_sort_caches")
+        .unwrap_or(false);
+    if warm_sort_caches {
+        searcher.sorter.warm_all(searcher.get_file_tree());
+    }
+    let search_concurrency = figment
+        .extract_inner::<usize>("search_concurrency")
+        .unwrap_or_else(|_| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(4)
+        });
+    let search_cache_memory = figment
+        .extract_inner::<usize>("search_cache_memory")
+        .unwrap_or(256 * 1024 * 1024);
+    let search_cache_ttl = figment
+        .extract_inner::<u64>("search_cache_ttl")
+        .ok()
+        .map(Duration::from_secs);
+    let pinned_searches = figment
+        .extract_inner::<Vec<String>>("pinned_searches")
+        .unwrap_or_default();
+    let searcher = Arc::new(searcher);
+    let search_cache = SearchCache::new(search_cache_memory, search_cache_ttl)
+        .with_pinned_queries(pinned_searches);
+    search_cache.set_pinned(SearchCache::search_pinned(
+        &search_cache.pinned_queries,
+        &searcher,
+    ));
+    rocket::custom(figment)
+        .manage(SearcherState {
+            current: RwLock::new(searcher),
+            file_list,
+            ignore_list,
+            name_sanitizing,
+            warm_sort_caches,
+            case_insensitive_sort,
+        })
+        .manage(SearchLimiter::new(search_concurrency))
+        .manage(auth)
+        .manage(RecentSearches::new(recent_searches))
+        .manage(saved_searches)
+        .manage(search_cache)
+        .manage(Metrics::default())
+        .mount(
+            "/",
+            routes![
+                search,
+                search_stream,
+                all,
+                reload,
+                parse,
+                breadcrumb,
+                file,
+                children,
+                metrics,
+                recent,
+                saved,
+                save
+            ],
+        )
+        .mount("/", FileServer::from(relative!("public")))
+}
+
+// Human-readable logs of the search and indexing spans on stdout. The level is set with RUST_LOG,
+// e.g. RUST_LOG=debug to include the index and sort preparation, and defaults to info.
+// Rocket keeps logging its own messages.
+fn init_tracing() {
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter).finish();
+    if let Err(e) = tracing::subscriber::set_global_default(subscriber) {
+        eprintln!("Failed to set up logging: {}", e);
     }
 }
 
 #[launch]
 fn rocket() -> _ {
-    println!("Reading file list...");
-    let start = Instant::now();
-    match loader::efu::import_efu("filelist.efu") {
+    init_tracing();
+    tracing::info!("Reading file list...");
+    let figment = rocket::Config::figment();
+    match load_file_list(&file_list_path(&figment)) {
         Ok(tree) => {
-            println!(
-                "Read {} records from filelist.efu in {:?}",
-                tree.len(),
-                start.elapsed()
-            );
-
-            // Create searcher
-            let searcher = Searcher::from_file_tree(tree);
+            // Create searcher. Indexing gives up after `index_timeout` seconds in Rocket.toml
+            // or ROCKET_INDEX_TIMEOUT, e.g. if the file list is much larger than expected.
+            let searcher = match figment.extract_inner::<u64>("index_timeout") {
+                Ok(seconds) => {
+                    let deadline = Instant::now() + Duration::from_secs(seconds);
+                    Searcher::from_file_tree_with_deadline(tree, deadline).unwrap_or_else(|e| {
+                        tracing::error!("{}", e);
+                        process::exit(1);
+                    })
+                }
+                Err(_) => Searcher::from_file_tree(tree),
+            };
 
             //  exit(0); // Exit successfully after reading the file list
-            rocket::build()
-                .manage(searcher)
-                .manage(LastSearchCache {
-                    search: Mutex::new(None),
-                })
-                .mount("/", routes![search])
-                .mount("/", FileServer::from(relative!("public")))
+            build_rocket(searcher)
         }
         Err(e) => {
-            eprintln!("Error reading file list: {}", e);
+            tracing::error!("{}", e);
             process::exit(1);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::http::Header;
+    use rocket::local::blocking::Client;
+
+    fn client() -> Client {
+        let tree = file_tree::FileTree::generate_synthetic(5_000, 5);
+        Client::tracked(build_rocket(Searcher::from_file_tree(tree))).expect("valid rocket")
+    }
+
+    #[test]
+    fn test_search_stream() {
+        let client = client();
+        let response = client.get("/search?query=report").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let page: SearchResult = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        // More results than fit on one page, so the stream has to return more than /search
+        assert!(page.total > page.page_size);
+
+        let response = client.get("/search_stream?query=report").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.content_type(),
+            Some(ContentType::new("application", "x-ndjson"))
+        );
+        let body = response.into_string().unwrap();
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines.len(), page.total);
+        for line in lines {
+            let result: FileResult = serde_json::from_str(line).unwrap();
+            assert!(result.name.to_lowercase().contains("report"));
+        }
+    }
+
+    #[test]
+    fn test_search_suggestion() {
+        let client = client();
+        let response = client.get("/search?query=reprot").dispatch();
+        let page: SearchResult = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(page.total, 0);
+        assert_eq!(page.suggestion.as_deref(), Some("report"));
+
+        let body = client
+            .get("/search?query=report")
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(!body.contains("suggestion"));
+    }
+
+    #[test]
+    fn test_search_timeout() {
+        let client = client();
+        // A regex is matched against every element, so it can't finish within 0 ms. The matches
+        // among the candidates scanned until the deadline is checked are returned.
+        let query = "/search?query=regex:.&sort_by=size&sort_order=descending";
+        let response = client.get(format!("{}&timeout_ms=0", query)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let partial: SearchResult = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert!(partial.timed_out);
+        assert!(partial.suggestion.is_none());
+        assert_eq!(partial.results.len(), 100);
+        let sizes: Vec<_> = partial.results.iter().map(|result| result.size).collect();
+        assert!(sizes.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert!(sizes.first() > sizes.last());
+        // Later pages of a partial result aren't available
+        let response = client
+            .get(format!("{}&timeout_ms=0&offset=100", query))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+
+        // The partial results weren't cached
+        let response = client.get(format!("{}&timeout_ms=60000", query)).dispatch();
+        let complete: SearchResult =
+            serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert!(!complete.timed_out);
+        assert!(complete.total > partial.total);
+    }
+
+    #[test]
+    fn test_search_explain() {
+        let client = client();
+        let response = client
+            .get("/search?query=report%20%7C%20%3Cext:pdf%20size:%3E1kb%20%3E&explain=true")
+            .dispatch();
+        let page: SearchResult = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(
+            page.parsed_query.as_deref(),
+            Some("report | ext:pdf & size:>1024")
+        );
+
+        let body = client
+            .get("/search?query=report")
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(!body.contains("parsed_query"));
+    }
+
+    #[test]
+    fn test_search_group_by_folder() {
+        let mut tree = file_tree::FileTree::with_capacity(8);
+        for path in [
+            "C:\\Documents\\work\\report 1.txt",
+            "C:\\Documents\\work\\report 2.txt",
+            "C:\\Downloads\\report.pdf",
+        ] {
+            tree.add_test_file(path);
+        }
+        let client = Client::tracked(build_rocket(Searcher::from_file_tree(tree))).unwrap();
+        let folders = |uri: &str| -> Vec<String> {
+            let body = client.get(uri).dispatch().into_string().unwrap();
+            let page: SearchResult = serde_json::from_str(&body).unwrap();
+            assert_eq!(page.total, page.results.len());
+            page.results.into_iter().map(|r| r.name).collect()
+        };
+
+        // Both reports in work collapse to one folder
+        assert_eq!(
+            folders("/search?query=report&group=folder"),
+            vec!["work", "Downloads"]
+        );
+        assert_eq!(
+            folders("/search?query=report&group=folder&depth=1"),
+            vec!["C:"]
+        );
+        assert_eq!(folders("/search?query=report").len(), 3);
+    }
+
+    #[test]
+    fn test_search_group_by_ext() {
+        let mut tree = file_tree::FileTree::with_capacity(8);
+        for path in [
+            "C:/Photos/trip.jpg",
+            "C:/Photos/beach.JPG",
+            "C:/Photos/trip notes.txt",
+            "C:/Photos/raw/trip.cr2",
+            "C:/Docs/trip.pdf",
+            "C:/Docs/trip.txt",
+            "C:/Docs/trip.jpg",
+        ] {
+            tree.add_test_file(path);
+        }
+        let client = Client::tracked(build_rocket(Searcher::from_file_tree(tree))).unwrap();
+        let facets = |uri: &str| -> ExtensionFacets {
+            let body = client.get(uri).dispatch().into_string().unwrap();
+            serde_json::from_str(&body).unwrap()
+        };
+
+        let trip = facets("/search?query=trip&group=ext");
+        assert_eq!(trip.total, 6);
+        let counts: Vec<(&str, usize)> = trip
+            .extensions
+            .iter()
+            .map(|bucket| (bucket.extension.as_str(), bucket.count))
+            .collect();
+        assert_eq!(counts, vec![("jpg", 2), ("txt", 2), ("cr2", 1), ("pdf", 1)]);
+        // The empty query counts everything, the folders have no bucket
+        let all = facets("/search?group=ext&query=");
+        assert_eq!(all.extensions[0].extension, "jpg");
+        assert_eq!(all.extensions[0].count, 3);
+        assert_eq!(
+            all.extensions
+                .iter()
+                .map(|bucket| bucket.count)
+                .sum::<usize>(),
+            7
+        );
+        assert!(
+            facets("/search?query=missing&group=ext")
+                .extensions
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_search_include_dotdirs() {
+        let mut tree = file_tree::FileTree::with_capacity(8);
+        for path in ["repo\\src\\config.rs", "repo\\.git\\config"] {
+            tree.add_test_file(path);
+        }
+        let client = Client::tracked(build_rocket(Searcher::from_file_tree(tree))).unwrap();
+        let total = |uri: &str| -> usize {
+            let body = client.get(uri).dispatch().into_string().unwrap();
+            serde_json::from_str::<SearchResult>(&body).unwrap().total
+        };
+
+        assert_eq!(total("/search?query=path:repo%5C%20file:config"), 2);
+        assert_eq!(
+            total("/search?query=path:repo%5C%20file:config&include_dotdirs=false"),
+            1
+        );
+        assert_eq!(
+            total("/search?query=path:repo%5C%20file:config&include_dotdirs=true"),
+            2
+        );
+        // Only path matches are affected
+        assert_eq!(total("/search?query=file:config&include_dotdirs=false"), 2);
+    }
+
+    #[test]
+    fn test_search_invalid_regex() {
+        let client = client();
+        for uri in [
+            "/search?query=regex:foo(",
+            "/search_stream?query=regex:foo(",
+        ] {
+            let response = client.get(uri).dispatch();
+            assert_eq!(response.status(), Status::BadRequest, "uri '{}'", uri);
+            assert!(response.into_string().unwrap().contains("Invalid regex"));
+        }
+    }
+
+    #[test]
+    fn test_search_refines_cached_query() {
+        let total = |client: &Client, uri: &str| {
+            let response = client.get(uri).dispatch();
+            let page: SearchResult =
+                serde_json::from_str(&response.into_string().unwrap()).unwrap();
+            page.total
+        };
+        // Each query refines the previous one, the final total equals a search from scratch
+        let typing = client();
+        let typed: Vec<usize> = ["r", "re", "rep", "repo"]
+            .iter()
+            .map(|query| total(&typing, &format!("/search?query={}&sort_by=size", query)))
+            .collect();
+        assert!(typed.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert_eq!(
+            typed[3],
+            total(&client(), "/search?query=repo&sort_by=size")
+        );
+    }
+
+    #[test]
+    fn test_search_keeps_original_case() {
+        let mut tree = file_tree::FileTree::with_capacity(3);
+        tree.add_test_file("Docs\\ReadMe.TXT");
+        let client = Client::tracked(build_rocket(Searcher::from_file_tree(tree))).unwrap();
+
+        for query in ["readme", "README", "case:ReadMe"] {
+            let response = client.get(format!("/search?query={}", query)).dispatch();
+            let page: SearchResult =
+                serde_json::from_str(&response.into_string().unwrap()).unwrap();
+            assert_eq!(page.total, 1, "query '{}'", query);
+            assert_eq!(page.results[0].name, "ReadMe.TXT");
+            assert_eq!(page.results[0].path, "Docs");
+        }
+    }
+
+    #[test]
+    fn test_search_cache_evicts_least_recently_used() {
+        let searcher = Arc::new(Searcher::from_file_tree(
+            file_tree::FileTree::with_capacity(1),
+        ));
+        let options = SearchOptions::default();
+        // Room for 25 indices
+        let cache = SearchCache::new(25 * std::mem::size_of::<usize>(), None);
+        let cached = |query: &str| {
+            matches!(
+                cache.lookup(query, &options, &searcher),
+                CacheLookup::Hit(_)
+            )
+        };
+        cache.insert("a", options.clone(), &searcher, Arc::new(vec![0; 10]));
+        cache.insert("b", options.clone(), &searcher, Arc::new(vec![0; 10]));
+        // Using a makes b the least recently used
+        assert!(cached("a"));
+        cache.insert("c", options.clone(), &searcher, Arc::new(vec![0; 10]));
+        assert!(cached("a") && cached("c"));
+        assert!(!cached("b"));
+
+        // More than the whole budget isn't kept, and doesn't evict anything else
+        cache.insert("d", options.clone(), &searcher, Arc::new(vec![0; 30]));
+        assert!(!cached("d"));
+        assert!(cached("a") && cached("c"));
+    }
+
+    #[test]
+    fn test_search_cache_ttl() {
+        let searcher = Arc::new(Searcher::from_file_tree(
+            file_tree::FileTree::with_capacity(1),
+        ));
+        let options = SearchOptions::default();
+        let cache = SearchCache::new(usize::MAX, Some(Duration::from_millis(50)));
+        cache.insert("a", options.clone(), &searcher, Arc::new(vec![1]));
+        assert!(matches!(
+            cache.lookup("a", &options, &searcher),
+            CacheLookup::Hit(_)
+        ));
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(matches!(
+            cache.lookup("a", &options, &searcher),
+            CacheLookup::Miss
+        ));
+    }
+
+    #[test]
+    fn test_pinned_searches() {
+        let path = std::env::temp_dir().join("vaultseek_test_pinned.efu");
+        let header = "Filename,Size,Date Modified,Date Created,Attributes\n";
+        std::fs::write(&path, format!("{}\"C:\\report.pdf\",1,,,32\n", header)).unwrap();
+        let figment = rocket::Config::figment()
+            .merge(("file_list", &path))
+            .merge(("admin_token", "secret"))
+            .merge(("pinned_searches", ["ext:pdf", "regex:("]))
+            .merge(("search_cache_memory", 0));
+        let tree = load_file_list(&path).unwrap();
+        let client = Client::tracked(build_rocket_with_figment(
+            figment,
+            Searcher::from_file_tree(tree),
+        ))
+        .unwrap();
+        let total = |query: &str| {
+            let response = client.get(format!("/search?query={}", query)).dispatch();
+            serde_json::from_str::<SearchResult>(&response.into_string().unwrap())
+                .unwrap()
+                .total
+        };
+        let computed = || {
+            let search_cache = client.rocket().state::<SearchCache>().unwrap();
+            search_cache.computed.load(Ordering::Relaxed)
+        };
+
+        // Served from the pinned results every time, even though nothing else fits in the cache
+        for _ in 0..3 {
+            assert_eq!(total("ext:pdf"), 1);
+        }
+        assert_eq!(computed(), 0);
+        assert_eq!(total("report"), 1);
+        assert_eq!(total("report"), 1);
+        assert_eq!(computed(), 2);
+
+        // Reloading searches the pinned queries again
+        std::fs::write(
+            &path,
+            format!(
+                "{}\"C:\\report.pdf\",1,,,32\n\"C:\\new.pdf\",2,,,32\n",
+                header
+            ),
+        )
+        .unwrap();
+        let response = client
+            .post("/reload")
+            .header(Header::new("Authorization", "Bearer secret"))
+            .dispatch();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(total("ext:pdf"), 2);
+        assert_eq!(computed(), 2);
+    }
+
+    #[rocket::async_test]
+    async fn test_concurrent_searches_are_limited() {
+        use rocket::futures::future::join_all;
+        use rocket::local::asynchronous::Client;
+
+        let tree = file_tree::FileTree::generate_synthetic(5_000, 5);
+        let figment = rocket::Config::figment().merge(("search_concurrency", 2));
+        let rocket = build_rocket_with_figment(figment, Searcher::from_file_tree(tree));
+        let client = Client::tracked(rocket).await.expect("valid rocket");
+
+        // More searches than the limit, with different queries so the cache doesn't answer them
+        let queries = [
+            "regex:^rep",
+            "regex:^sum",
+            "path:media",
+            "path:backup",
+            "photo",
+            "notes",
+        ];
+        let responses = join_all(queries.iter().map(|query| {
+            let client = &client;
+            async move {
+                let response = client
+                    .get(format!("/search?query={}", query))
+                    .dispatch()
+                    .await;
+                (response.status(), response.into_string().await.unwrap())
+            }
+        }))
+        .await;
+        for (status, body) in responses {
+            assert_eq!(status, Status::Ok);
+            let _: SearchResult = serde_json::from_str(&body).unwrap();
+        }
+
+        // All permits are returned after the searches completed
+        let limiter = client.rocket().state::<SearchLimiter>().unwrap();
+        assert_eq!(limiter.semaphore.available_permits(), 2);
+    }
+
+    #[test]
+    fn test_search_ignored_paths() {
+        let mut tree = file_tree::FileTree::with_capacity(5);
+        tree.add_test_file("C:\\report.txt");
+        tree.add_test_file("C:\\.git\\report");
+        let figment = rocket::Config::figment().merge(("ignore", [".git"]));
+        let rocket = build_rocket_with_figment(figment, Searcher::from_file_tree(tree));
+        let client = Client::tracked(rocket).unwrap();
+
+        let response = client.get("/search?query=report").dispatch();
+        let page: SearchResult = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.results[0].path, "C:");
+
+        // Listing everything with an empty query or /all hides them as well
+        for uri in ["/search?query=", "/all", "/all?sort_by=name"] {
+            let response = client.get(uri).dispatch();
+            let page: SearchResult =
+                serde_json::from_str(&response.into_string().unwrap()).unwrap();
+            let names: Vec<String> = page.results.into_iter().map(|r| r.name).collect();
+            assert_eq!(page.total, 2, "{}", uri);
+            assert_eq!(names.len(), 2, "{}", uri);
+            assert!(
+                names
+                    .iter()
+                    .all(|name| name == "C:" || name == "report.txt")
+            );
+        }
+
+        // Breadcrumbs don't reveal them either
+        let status = |path: &str| {
+            let uri = format!("/breadcrumb?path={}", path);
+            client.get(uri).dispatch().status()
+        };
+        assert_eq!(status("C:%5Creport.txt"), Status::Ok);
+        assert_eq!(status("C:%5C.git"), Status::NotFound);
+        assert_eq!(status("C:%5C.git%5Creport"), Status::NotFound);
+    }
+
+    #[test]
+    fn test_search_rank_by_relevance() {
+        let mut tree = file_tree::FileTree::with_capacity(8);
+        for path in ["C:\\annual report.pdf", "C:\\reports", "C:\\report.pdf"] {
+            tree.add_test_file(path);
+        }
+        let client = Client::tracked(build_rocket(Searcher::from_file_tree(tree))).unwrap();
+        let page = |uri: &str| -> SearchResult {
+            serde_json::from_str(&client.get(uri).dispatch().into_string().unwrap()).unwrap()
+        };
+
+        let ranked = page("/search?query=report&rank=relevance");
+        let names: Vec<&str> = ranked.results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["report.pdf", "reports", "annual report.pdf"]);
+        let scores: Vec<f32> = ranked.results.iter().map(|r| r.score.unwrap()).collect();
+        assert!(
+            scores.windows(2).all(|pair| pair[0] > pair[1]),
+            "{:?}",
+            scores
+        );
+        // No scores without ranking or with an explicit sort
+        for uri in [
+            "/search?query=report",
+            "/search?query=report&rank=relevance&sort_by=size",
+        ] {
+            assert!(
+                page(uri).results.iter().all(|r| r.score.is_none()),
+                "{}",
+                uri
+            );
+        }
+    }
+
+    #[test]
+    fn test_search_boundary_and_negation_options() {
+        let mut tree = file_tree::FileTree::with_capacity(8);
+        tree.add_test_file("C:\\.config\\app.toml");
+        tree.add_or_update_recursive("C:\\unknown.bin", None, None, None, Attributes::NONE);
+        let client = Client::tracked(build_rocket(Searcher::from_file_tree(tree))).unwrap();
+        let names = |uri: &str| -> Vec<String> {
+            let body = client.get(uri).dispatch().into_string().unwrap();
+            let page: SearchResult = serde_json::from_str(&body).unwrap();
+            page.results.into_iter().map(|result| result.name).collect()
+        };
+
+        assert!(names("/search?query=ww:path:.config").is_empty());
+        assert_eq!(
+            names("/search?query=ww:path:.config&separator_boundaries=true"),
+            vec![".config", "app.toml"]
+        );
+        let negated = "/search?query=!size:%3E1000";
+        assert!(names(negated).contains(&"unknown.bin".to_string()));
+        let strict = names(&format!("{}&strict_negation=true", negated));
+        assert!(strict.contains(&"app.toml".to_string()));
+        assert!(!strict.contains(&"unknown.bin".to_string()));
+    }
+
+    #[test]
+    fn test_search_dirs_first() {
+        let mut tree = file_tree::FileTree::with_capacity(8);
+        for (path, attributes) in [
+            ("C:\\b notes.txt", Attributes::NONE),
+            ("C:\\c notes", Attributes::DIRECTORY),
+            ("C:\\a notes.txt", Attributes::NONE),
+            ("C:\\d notes", Attributes::DIRECTORY),
+        ] {
+            tree.add_or_update_recursive(path, Some(1), None, None, attributes);
+        }
+        let client = Client::tracked(build_rocket(Searcher::from_file_tree(tree))).unwrap();
+        let names = |uri: &str| -> Vec<String> {
+            let body = client.get(uri).dispatch().into_string().unwrap();
+            let page: SearchResult = serde_json::from_str(&body).unwrap();
+            page.results.into_iter().map(|result| result.name).collect()
+        };
+
+        let by_name = "sort_by=filename&sort_order=ascending";
+        assert_eq!(
+            names(&format!("/search?query=notes&{}&dirs_first=true", by_name)),
+            vec!["c notes", "d notes", "a notes.txt", "b notes.txt"]
+        );
+        assert_eq!(
+            names(&format!("/search?query=notes&{}", by_name)),
+            vec!["a notes.txt", "b notes.txt", "c notes", "d notes"]
+        );
+        // Listing everything puts the folders first too
+        assert_eq!(
+            names(&format!("/search?query=&{}&dirs_first=true", by_name))[..2],
+            ["c notes", "d notes"]
+        );
+    }
+
+    #[test]
+    fn test_sort_presets() {
+        let client = client();
+        let paths = |uri: &str| -> Vec<String> {
+            let response = client.get(uri).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            let page: SearchResult =
+                serde_json::from_str(&response.into_string().unwrap()).unwrap();
+            page.results.into_iter().map(|result| result.path).collect()
+        };
+
+        let presets = [
+            ("newest", "date_modified", "descending"),
+            ("oldest", "date_modified", "ascending"),
+            ("largest", "size", "descending"),
+            ("smallest", "size", "ascending"),
+            ("name_az", "filename", "ascending"),
+            ("name_za", "filename", "descending"),
+        ];
+        for (preset, sort_by, sort_order) in presets {
+            let explicit = format!("sort_by={}&sort_order={}", sort_by, sort_order);
+            assert_eq!(
+                paths(&format!("/search?query=report&sort={}", preset)),
+                paths(&format!("/search?query=report&{}", explicit)),
+                "{}",
+                preset
+            );
+            assert_eq!(
+                paths(&format!("/all?sort={}", preset)),
+                paths(&format!("/all?{}", explicit))
+            );
+        }
+        // The explicit params win over a preset
+        assert_eq!(
+            paths("/search?query=report&sort=newest&sort_by=size"),
+            paths("/search?query=report&sort_by=size")
+        );
+        assert_eq!(
+            paths("/search?query=report&sort=newest&sort_order=ascending"),
+            paths("/search?query=report&sort=oldest")
+        );
+    }
+
+    #[test]
+    fn test_case_insensitive_sort() {
+        let names = |case_insensitive_sort: bool| -> Vec<String> {
+            let mut tree = file_tree::FileTree::with_capacity(4);
+            for name in ["Zebra.txt", "apple.txt", "Mango.txt"] {
+                tree.add_test_file(name);
+            }
+            let figment =
+                rocket::Config::figment().merge(("case_insensitive_sort", case_insensitive_sort));
+            let client = Client::tracked(build_rocket_with_figment(
+                figment,
+                Searcher::from_file_tree(tree),
+            ))
+            .unwrap();
+            let response = client.get("/search?query=txt&sort=name_az").dispatch();
+            let page: SearchResult =
+                serde_json::from_str(&response.into_string().unwrap()).unwrap();
+            page.results.into_iter().map(|result| result.name).collect()
+        };
+        assert_eq!(names(false), vec!["Mango.txt", "Zebra.txt", "apple.txt"]);
+        assert_eq!(names(true), vec!["apple.txt", "Mango.txt", "Zebra.txt"]);
+    }
+
+    #[test]
+    fn test_all_pages() {
+        let client = client();
+        let page = |uri: String| {
+            let response = client.get(uri).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            serde_json::from_str::<SearchResult>(&response.into_string().unwrap()).unwrap()
+        };
+
+        for sort in ["", "&sort_by=size&sort_order=descending"] {
+            let mut paths = Vec::new();
+            let mut offset = 0;
+            let total = loop {
+                let result = page(format!("/all?offset={}&page_size=700{}", offset, sort));
+                assert_eq!(result.offset, offset);
+                assert!(result.results.len() <= 700);
+                if result.results.is_empty() {
+                    break result.total;
+                }
+                offset += result.results.len();
+                paths.extend(result.results);
+            };
+            assert_eq!(paths.len(), total);
+            if !sort.is_empty() {
+                let sizes: Vec<i64> = paths
+                    .iter()
+                    .map(|result| result.size.unwrap_or(-1))
+                    .collect();
+                assert!(sizes.windows(2).all(|pair| pair[0] >= pair[1]));
+            }
+
+            // An empty search returns the first page of the same listing
+            let first = page(format!("/all?page_size=100{}", sort));
+            let search = page(format!("/search?query={}", sort));
+            assert_eq!(search.total, total);
+            let names = |result: &SearchResult| -> Vec<String> {
+                result
+                    .results
+                    .iter()
+                    .map(|r| format!("{}\\{}", r.path, r.name))
+                    .collect()
+            };
+            assert_eq!(names(&search), names(&first));
+        }
+    }
+
+    #[test]
+    fn test_reload_picks_up_new_files() {
+        let path = std::env::temp_dir().join("vaultseek_test_reload.efu");
+        let header = "Filename,Size,Date Modified,Date Created,Attributes\n";
+        std::fs::write(&path, format!("{}\"C:\\old report.txt\",1,,,32\n", header)).unwrap();
+        let figment = rocket::Config::figment()
+            .merge(("file_list", &path))
+            .merge(("admin_token", "secret"));
+        let tree = load_file_list(&path).unwrap();
+        let client = Client::tracked(build_rocket_with_figment(
+            figment,
+            Searcher::from_file_tree(tree),
+        ))
+        .unwrap();
+        let total = |query: &str| {
+            let response = client.get(format!("/search?query={}", query)).dispatch();
+            serde_json::from_str::<SearchResult>(&response.into_string().unwrap())
+                .unwrap()
+                .total
+        };
+        assert_eq!(total("report"), 1);
+
+        std::fs::write(
+            &path,
+            format!(
+                "{}\"C:\\old report.txt\",1,,,32\n\"C:\\new report.txt\",2,,,32\n",
+                header
+            ),
+        )
+        .unwrap();
+        let response = client
+            .post("/reload")
+            .header(Header::new("Authorization", "Bearer secret"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        // The cached result of the old searcher isn't reused
+        assert_eq!(total("report"), 2);
+        assert_eq!(total("new"), 1);
+
+        // A failed reload keeps serving the current searcher
+        std::fs::remove_file(&path).unwrap();
+        let response = client
+            .post("/reload")
+            .header(Header::new("Authorization", "Bearer secret"))
+            .dispatch();
+        assert_eq!(response.status(), Status::InternalServerError);
+        assert_eq!(total("report"), 2);
+    }
+
+    #[test]
+    fn test_admin_token() {
+        let build = |figment: Figment| {
+            let tree = file_tree::FileTree::generate_synthetic(500, 6);
+            Client::tracked(build_rocket_with_figment(
+                figment,
+                Searcher::from_file_tree(tree),
+            ))
+            .unwrap()
+        };
+        let status = |client: &Client, uri: &str, token: Option<&str>| {
+            let mut request = match uri {
+                "/reload" => client.post(uri.to_string()),
+                _ => client.get(uri.to_string()),
+            };
+            if let Some(token) = token {
+                request = request.header(Header::new("Authorization", token.to_string()));
+            }
+            request.dispatch().status()
+        };
+
+        // Without a configured token /reload is never allowed
+        let client = build(rocket::Config::figment());
+        assert_eq!(status(&client, "/reload", None), Status::Unauthorized);
+        assert_eq!(
+            status(&client, "/reload", Some("Bearer ")),
+            Status::Unauthorized
+        );
+
+        let figment = rocket::Config::figment()
+            .merge(("admin_token", "secret"))
+            .merge(("file_list", "/nonexistent/filelist.efu"));
+        let client = build(figment.clone());
+        assert_eq!(status(&client, "/reload", None), Status::Unauthorized);
+        assert_eq!(
+            status(&client, "/reload", Some("Bearer wrong")),
+            Status::Unauthorized
+        );
+        // Same length, only the last byte differs
+        assert_eq!(
+            status(&client, "/reload", Some("Bearer secreT")),
+            Status::Unauthorized
+        );
+        assert_eq!(
+            status(&client, "/reload", Some("secret")),
+            Status::Unauthorized
+        );
+        // Authorized, but the file list doesn't exist
+        assert_eq!(
+            status(&client, "/reload", Some("Bearer secret")),
+            Status::InternalServerError
+        );
+        // Searching stays open by default
+        assert_eq!(status(&client, "/search?query=a", None), Status::Ok);
+
+        let client = build(figment.merge(("protect_search", true)));
+        for uri in ["/search?query=a", "/search_stream?query=a"] {
+            assert_eq!(status(&client, uri, None), Status::Unauthorized);
+            assert_eq!(status(&client, uri, Some("Bearer secret")), Status::Ok);
+        }
+    }
+
+    #[test]
+    fn test_recent_and_saved_searches() {
+        let saved_path = std::env::temp_dir().join("vaultseek_test_saved_searches.json");
+        let _ = std::fs::remove_file(&saved_path);
+        let build = || {
+            let figment = rocket::Config::figment()
+                .merge(("recent_searches", 3))
+                .merge(("saved_searches", &saved_path));
+            let tree = file_tree::FileTree::generate_synthetic(1_000, 5);
+            Client::tracked(build_rocket_with_figment(
+                figment,
+                Searcher::from_file_tree(tree),
+            ))
+            .unwrap()
+        };
+        let recent = |client: &Client| -> Vec<String> {
+            serde_json::from_str(&client.get("/recent").dispatch().into_string().unwrap()).unwrap()
+        };
+
+        let client = build();
+        for query in ["a", "b", "a", "c", "d"] {
+            client.get(format!("/search?query={}", query)).dispatch();
+        }
+        // Paging doesn't count as a new search
+        client.get("/search?query=b&offset=100").dispatch();
+        // Repeated queries move to the front, the oldest ones drop out
+        assert_eq!(recent(&client), vec!["d", "c", "a"]);
+
+        let save = |uri: &str| client.post(uri.to_string()).dispatch().status();
+        assert_eq!(
+            save("/save?name=Reports&query=report%20ext:pdf"),
+            Status::Ok
+        );
+        assert_eq!(save("/save?name=Photos&query=ext:jpg"), Status::Ok);
+        assert_eq!(save("/save?name=Photos&query=ext:png"), Status::Ok);
+        assert_eq!(save("/save?name=%20&query=x"), Status::BadRequest);
+        // Overlong names and queries are rejected
+        let long_name = format!("/save?name={}&query=x", "n".repeat(MAX_SAVED_NAME_LEN + 1));
+        assert_eq!(save(&long_name), Status::BadRequest);
+        let long_query = format!(
+            "/save?name=Long&query={}",
+            "q".repeat(MAX_SAVED_QUERY_LEN + 1)
+        );
+        assert_eq!(save(&long_query), Status::BadRequest);
+        drop(client);
+
+        // Saved searches are still there after a restart, recent searches are not
+        let client = build();
+        let saved: BTreeMap<String, String> =
+            serde_json::from_str(&client.get("/saved").dispatch().into_string().unwrap()).unwrap();
+        std::fs::remove_file(&saved_path).unwrap();
+        assert_eq!(
+            saved,
+            BTreeMap::from([
+                ("Photos".to_string(), "ext:png".to_string()),
+                ("Reports".to_string(), "report ext:pdf".to_string()),
+            ])
+        );
+        assert!(recent(&client).is_empty());
+
+        // An unreadable file fails at ignite instead of exiting the process
+        std::fs::write(&saved_path, "not json").unwrap();
+        let rocket = build_rocket_with_figment(
+            rocket::Config::figment().merge(("saved_searches", &saved_path)),
+            Searcher::from_file_tree(file_tree::FileTree::with_capacity(1)),
+        );
+        let Err(error) = Client::tracked(rocket) else {
+            panic!("Ignited with an invalid saved searches file");
+        };
+        assert!(matches!(
+            error.kind(),
+            rocket::error::ErrorKind::FailedFairings(_)
+        ));
+        std::fs::remove_file(&saved_path).unwrap();
+
+        // Once the limit is reached, only existing names can be replaced
+        let full = SavedSearches {
+            searches: Mutex::new(
+                (0..MAX_SAVED_SEARCHES)
+                    .map(|i| (i.to_string(), "x".to_string()))
+                    .collect(),
+            ),
+            path: saved_path.clone(),
+        };
+        assert_eq!(full.save("new", "x").unwrap_err().0, Status::BadRequest);
+        assert!(full.save("0", "y").is_ok());
+        std::fs::remove_file(&saved_path).unwrap();
+    }
+
+    #[test]
+    fn test_sanitize_names() {
+        let build = |sanitize: &str| {
+            let mut tree = file_tree::FileTree::with_capacity(3);
+            tree.add_test_file("C:\\bad\u{1b}dir\\bell\u{7}report\u{0}.txt");
+            let figment = rocket::Config::figment().merge(("sanitize_names", sanitize));
+            Client::tracked(build_rocket_with_figment(
+                figment,
+                Searcher::from_file_tree(tree),
+            ))
+            .unwrap()
+        };
+        let first_result = |client: &Client, query: &str| {
+            let body = client
+                .get(format!("/search?query={}", query))
+                .dispatch()
+                .into_string()
+                .unwrap();
+            // No control characters, neither raw nor escaped by serde
+            assert!(!body.contains("\\u00") && !body.contains(char::is_control));
+            let page: SearchResult = serde_json::from_str(&body).unwrap();
+            page.results[0].clone()
+        };
+
+        // Searches still match the original name
+        let result = first_result(&build("replace"), "bell%07report");
+        assert_eq!(result.name, "bell\u{FFFD}report\u{FFFD}.txt");
+        assert_eq!(result.path, "C:\\bad\u{FFFD}dir");
+        let result = first_result(&build("escape"), "report");
+        assert_eq!(result.name, "bell\\x07report\\x00.txt");
+        assert_eq!(result.path, "C:\\bad\\x1bdir");
+
+        let client = build("keep");
+        let body = client
+            .get("/search?query=report")
+            .dispatch()
+            .into_string()
+            .unwrap();
+        let page: SearchResult = serde_json::from_str(&body).unwrap();
+        assert_eq!(page.results[0].name, "bell\u{7}report\u{0}.txt");
+    }
+
+    #[test]
+    fn test_result_categories() {
+        let mut tree = file_tree::FileTree::with_capacity(4);
+        for path in ["C:\\docs\\photo.JPG", "C:\\docs\\notes.unknownext"] {
+            tree.add_test_file(path);
+        }
+        tree.add_or_update_recursive("C:\\docs", None, None, None, Attributes::DIRECTORY);
+        let client = Client::tracked(build_rocket(Searcher::from_file_tree(tree))).unwrap();
+        let category = |query: &str| {
+            let body = client
+                .get(format!("/search?query={}", query))
+                .dispatch()
+                .into_string()
+                .unwrap();
+            let page: SearchResult = serde_json::from_str(&body).unwrap();
+            page.results[0].category
+        };
+        assert_eq!(category("photo"), Some(FileCategory::Image));
+        assert_eq!(category("notes"), Some(FileCategory::Other));
+        assert_eq!(category("wfn:docs"), None);
+
+        let mime = |query: &str| {
+            let body = client
+                .get(format!("/search?query={}", query))
+                .dispatch()
+                .into_string()
+                .unwrap();
+            let page: SearchResult = serde_json::from_str(&body).unwrap();
+            page.results[0].mime.clone()
+        };
+        assert_eq!(mime("photo").as_deref(), Some("image/jpeg"));
+        assert_eq!(mime("notes"), None);
+        assert_eq!(mime("wfn:docs"), None);
+    }
+
+    #[test]
+    fn test_result_fields() {
+        let mut tree = file_tree::FileTree::with_capacity(4);
+        tree.add_or_update_recursive(
+            "C:\\docs\\photo.jpg",
+            Some(42),
+            None,
+            None,
+            Attributes::NONE,
+        );
+        let client = Client::tracked(build_rocket(Searcher::from_file_tree(tree))).unwrap();
+        let keys = |uri: &str| -> Vec<String> {
+            let body = client.get(uri).dispatch().into_string().unwrap();
+            let page: serde_json::Value = serde_json::from_str(&body).unwrap();
+            let result = page["results"][0].as_object().unwrap().clone();
+            result.keys().cloned().collect()
+        };
+
+        assert_eq!(keys("/search?query=photo").len(), RESULT_FIELDS.len());
+        assert_eq!(
+            keys("/search?query=photo&fields=name,size"),
+            vec!["name", "size"]
+        );
+        assert_eq!(keys("/search?query=&fields=path"), vec!["path"]);
+        let body = client
+            .get("/search_stream?query=photo&fields=size")
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert_eq!(body, "{\"size\":42}\n");
+
+        let response = client
+            .get("/search?query=photo&fields=name,owner")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        assert!(
+            response
+                .into_string()
+                .unwrap()
+                .contains("Unknown field 'owner'")
+        );
+    }
+
+    #[test]
+    fn test_breadcrumb() {
+        let mut tree = file_tree::FileTree::with_capacity(8);
+        let file = tree.add_test_file("C:\\Users\\me\\notes.txt");
+        let client = Client::tracked(build_rocket(Searcher::from_file_tree(tree))).unwrap();
+
+        let response = client
+            .get("/breadcrumb?path=C:/Users/me/notes.txt")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let segments: Vec<BreadcrumbSegment> =
+            serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        let names: Vec<&str> = segments.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["C:", "Users", "me", "notes.txt"]);
+        assert_eq!(segments[1].path, "C:\\Users");
+        assert_eq!(segments[3].index, file);
+
+        for path in ["C:/missing", ""] {
+            let response = client.get(format!("/breadcrumb?path={}", path)).dispatch();
+            assert_eq!(response.status(), Status::NotFound);
+        }
+    }
+
+    #[test]
+    fn test_file_details() {
+        let mut tree = file_tree::FileTree::with_capacity(8);
+        for path in ["C:\\pics\\photo.png", "C:\\.git\\config"] {
+            tree.add_test_file(path);
+        }
+        let figment = rocket::Config::figment().merge(("ignore", [".git"]));
+        let rocket = build_rocket_with_figment(figment, Searcher::from_file_tree(tree));
+        let client = Client::tracked(rocket).unwrap();
+
+        let response = client.get("/file?path=C:/pics/photo.png").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let result: FileResult = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(result.name, "photo.png");
+        assert_eq!(result.path, "C:\\pics");
+        assert_eq!(result.mime.as_deref(), Some("image/png"));
+        // Folders have no MIME type
+        let response = client.get("/file?path=C:/pics").dispatch();
+        let result: FileResult = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(result.mime, None);
+
+        for path in ["C:/missing", "", "C:/.git", "C:/.git/config"] {
+            let response = client.get(format!("/file?path={}", path)).dispatch();
+            assert_eq!(response.status(), Status::NotFound, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_children() {
+        let mut tree = file_tree::FileTree::with_capacity(8);
+        for path in [
+            "C:\\docs\\a.txt",
+            "C:\\docs\\old\\b.txt",
+            "C:\\docs\\old\\older\\c.txt",
+            "C:\\docs\\.git\\config",
+        ] {
+            tree.add_test_file(path);
+        }
+        let figment = rocket::Config::figment().merge(("ignore", [".git"]));
+        let rocket = build_rocket_with_figment(figment, Searcher::from_file_tree(tree));
+        let client = Client::tracked(rocket).unwrap();
+        let names = |uri: &str| -> Vec<String> {
+            let body = client.get(uri).dispatch().into_string().unwrap();
+            let page: SearchResult = serde_json::from_str(&body).unwrap();
+            page.results.into_iter().map(|result| result.name).collect()
+        };
+
+        assert_eq!(names("/children?path=C:/docs"), vec!["a.txt", "old"]);
+        // Deeper levels only up to the depth, ignored folders are left out
+        assert_eq!(
+            names("/children?path=C:/docs&depth=2"),
+            vec!["a.txt", "old", "b.txt", "older"]
+        );
+        assert_eq!(names("/children?path="), vec!["C:"]);
+        for path in ["C:/missing", "C:/docs/.git"] {
+            let response = client.get(format!("/children?path={}", path)).dispatch();
+            assert_eq!(response.status(), Status::NotFound, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_metrics() {
+        let client = client();
+        let scrape = || -> BTreeMap<String, f64> {
+            let response = client.get("/metrics").dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            response
+                .into_string()
+                .unwrap()
+                .lines()
+                .filter(|line| !line.starts_with('#'))
+                .filter_map(|line| line.rsplit_once(' '))
+                .map(|(name, value)| (name.to_string(), value.parse().unwrap()))
+                .collect()
+        };
+        let before = scrape();
+        assert_eq!(before["vaultseek_searches_total"], 0.0);
+        let all = client
+            .get("/all?page_size=1")
+            .dispatch()
+            .into_string()
+            .unwrap();
+        let all: SearchResult = serde_json::from_str(&all).unwrap();
+        assert_eq!(before["vaultseek_elements"], all.total as f64);
+
+        for query in ["report", "report", "txt"] {
+            client.get(format!("/search?query={}", query)).dispatch();
+        }
+        // Paging through a search is answered from the cache too
+        client.get("/search?query=txt&offset=100").dispatch();
+        // Streamed searches count like the others
+        client.get("/search_stream?query=doc").dispatch();
+        let after = scrape();
+        assert_eq!(after["vaultseek_searches_total"], 5.0);
+        assert_eq!(after["vaultseek_search_cache_hits_total"], 2.0);
+        assert_eq!(after["vaultseek_search_cache_misses_total"], 3.0);
+        assert_eq!(after["vaultseek_search_duration_seconds_count"], 5.0);
+        assert_eq!(
+            after["vaultseek_search_duration_seconds_bucket{le=\"+Inf\"}"],
+            5.0
+        );
+        assert!(after["vaultseek_search_duration_seconds_sum"] > 0.0);
+    }
+
+    #[test]
+    fn test_parse_endpoint() {
+        let client = client();
+        let response = client
+            .get("/parse?query=report%20%7C%20%3Cext:pdf%20!size:%3E1kb%20%3E")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        let ast: serde_json::Value =
+            serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert_eq!(ast["Or"][0]["Literal"]["Text"]["text"], "report");
+        assert_eq!(ast["Or"][1]["And"][0]["Function"]["Ext"][0], "pdf");
+        assert_eq!(
+            ast["Or"][1]["And"][1]["Not"]["Function"]["Size"],
+            serde_json::json!(["Gt", 1024])
+        );
+
+        let response = client.get("/parse?query=regex:%5B").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+}